@@ -12,14 +12,25 @@ pub fn generate_ast(output_dir: &str) -> io::Result<()> {
     define_ast(
         output_dir,
         "Expr",
-        vec!["error", "token", "object", "rc"],
+        vec!["error", "token", "object", "stmt", "rc"],
         vec![
+            "Array: Token bracket, Vec<Rc<Expr>> elements".to_string(),
             "Assign: Token name, Rc<Expr> value".to_string(),
             "Binary: Rc<Expr> left, Token operator, Rc<Expr> right".to_string(),
+            "Block: Rc<Vec<Rc<Stmt>>> statements, Option<Rc<Expr>> value".to_string(),
             "Call: Rc<Expr> callee, Token paren, Vec<Rc<Expr>> arguments".to_string(),
+            "Comma: Rc<Expr> left, Rc<Expr> right".to_string(),
+            "Function: Rc<FunctionStmt> declaration".to_string(),
+            "Get: Rc<Expr> object, Token name".to_string(),
             "Grouping: Rc<Expr> expression".to_string(),
+            "Index: Rc<Expr> object, Token bracket, Rc<Expr> index".to_string(),
+            "IndexSet: Rc<Expr> object, Token bracket, Rc<Expr> index, Rc<Expr> value".to_string(),
             "Literal: Option<Object> value".to_string(),
             "Logical: Rc<Expr> left, Token operator, Rc<Expr> right".to_string(),
+            "Map: Token brace, Vec<Rc<Expr>> keys, Vec<Rc<Expr>> values".to_string(),
+            "Set: Rc<Expr> object, Token name, Rc<Expr> value".to_string(),
+            "Super: Token keyword, Token method".to_string(),
+            "This: Token keyword".to_string(),
             "Unary: Token operator, Rc<Expr> right".to_string(),
             "Variable : Token name".to_string(),
         ],
@@ -31,14 +42,19 @@ pub fn generate_ast(output_dir: &str) -> io::Result<()> {
         vec!["error", "expr", "token", "rc"],
         vec![
             "Block : Rc<Vec<Rc<Stmt>>> statements".to_string(),
+            "Class : Token name, Option<Rc<Expr>> superclass, Rc<Vec<Rc<Stmt>>> methods".to_string(),
             "If : Rc<Expr> condition, Rc<Stmt> then_branch, Option<Rc<Stmt>> else_branch".to_string(),
             "Expression : Rc<Expr> expression".to_string(),
-            "Function : Token name, Rc<Vec<Token>> params, Rc<Vec<Rc<Stmt>>> body".to_string(),
-            "Break: Token token".to_string(),
+            "Function : Token name, Rc<Vec<Token>> params, bool variadic, Rc<Vec<Rc<Stmt>>> body, bool is_lambda".to_string(),
+            "Break: Token token, Option<Token> label".to_string(),
+            "Continue: Token token, Option<Token> label".to_string(),
+            "Import : Token path".to_string(),
+            "MultiVar : Rc<Vec<Rc<Stmt>>> declarations".to_string(),
             "Print : Rc<Expr> expression".to_string(),
             "Return : Token token, Option<Rc<Expr>> value".to_string(),
-            "Var : Token name, Option<Rc<Expr>> initializer".to_string(),
-            "While : Rc<Expr> condition, Rc<Stmt> body".to_string(),
+            "Try : Rc<Vec<Rc<Stmt>>> try_body, Token error, Rc<Vec<Rc<Stmt>>> catch_body, Option<Rc<Vec<Rc<Stmt>>>> finally_body".to_string(),
+            "Var : Token name, Option<Rc<Expr>> initializer, bool is_const".to_string(),
+            "While : Rc<Expr> condition, Rc<Stmt> body, Option<Rc<Expr>> increment, Option<Token> label, bool do_while".to_string(),
         ],
     )?;
 
@@ -77,6 +93,7 @@ fn define_ast(
         });
     }
 
+    writeln!(file, "#[derive(Clone)]")?;
     writeln!(file, "pub enum {} {{", base_name)?;
     for tree_type in &tree_types {
         writeln!(
@@ -104,9 +121,8 @@ fn define_ast(
     writeln!(file, "}}\n\nimpl Eq for {}{{}}\n", base_name)?;
     writeln!(file,"use std::hash::*;")?;
     writeln!(file, "impl Hash for {} {{",base_name)?;
-    writeln!(file,"    fn hash<H: Hasher>(&self, hasher: &mut H)")?;
-    writeln!(file,"    where H: Hasher, ")?;
-    writeln!(file,"       {{ match self {{")?;
+    writeln!(file,"    fn hash<H: Hasher>(&self, hasher: &mut H) {{")?;
+    writeln!(file,"       match self {{")?;
     for tree_type in &tree_types {
         writeln!(
             file,
@@ -129,7 +145,7 @@ fn define_ast(
     for tree_type in &tree_types {
         writeln!(
             file,
-            "            {0}::{1}(expr) => {3}_visitor.visit_{2}_{3}(wrapper,&expr),",
+            "            {0}::{1}(expr) => {3}_visitor.visit_{2}_{3}(wrapper, expr),",
             base_name,
             tree_type.base_class_name, tree_type.base_class_name.to_lowercase(), base_name.to_lowercase()
         )?;
@@ -0,0 +1,434 @@
+use crate::error::LoxResult;
+use crate::expr::*;
+use crate::object::{format_number, Object};
+use crate::stmt::*;
+use crate::token::Token;
+use std::rc::Rc;
+
+// Serialises the syntax tree as JSON for external tools. Every node is an object whose "type"
+// is the node's struct name (`VarStmt`, `BinaryExpr`, ...) and whose other keys are that
+// struct's field names. Tokens appear as their lexemes, absent optional children as null, and
+// punctuation tokens kept only for error locations are left out.
+pub struct AstJson;
+
+pub fn to_json(stmts: &[Rc<Stmt>]) -> Result<String, LoxResult> {
+    AstJson.stmts(stmts)
+}
+
+impl AstJson {
+    fn expr(&self, expr: &Rc<Expr>) -> Result<String, LoxResult> {
+        expr.accept(expr.clone(), self)
+    }
+
+    fn stmt(&self, stmt: &Rc<Stmt>) -> Result<String, LoxResult> {
+        stmt.accept(stmt.clone(), self)
+    }
+
+    fn exprs(&self, exprs: &[Rc<Expr>]) -> Result<String, LoxResult> {
+        let items = exprs
+            .iter()
+            .map(|expr| self.expr(expr))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("[{}]", items.join(",")))
+    }
+
+    fn stmts(&self, stmts: &[Rc<Stmt>]) -> Result<String, LoxResult> {
+        let items = stmts
+            .iter()
+            .map(|stmt| self.stmt(stmt))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("[{}]", items.join(",")))
+    }
+
+    fn optional_expr(&self, expr: &Option<Rc<Expr>>) -> Result<String, LoxResult> {
+        match expr {
+            Some(expr) => self.expr(expr),
+            None => Ok("null".to_string()),
+        }
+    }
+
+    fn token(&self, token: &Token) -> String {
+        string(&token.lexeme)
+    }
+
+    fn optional_token(&self, token: &Option<Token>) -> String {
+        match token {
+            Some(token) => self.token(token),
+            None => "null".to_string(),
+        }
+    }
+
+    // {"type":tag,"field":value,...} with the values already serialised.
+    fn node(&self, tag: &str, fields: &[(&str, String)]) -> Result<String, LoxResult> {
+        let mut builder = format!("{{\"type\":{}", string(tag));
+        for (name, value) in fields {
+            builder = format!("{},{}:{}", builder, string(name), value);
+        }
+        Ok(format!("{}}}", builder))
+    }
+
+    fn function(&self, tag: &str, function: &FunctionStmt) -> Result<String, LoxResult> {
+        let params: Vec<String> = function.params.iter().map(|p| self.token(p)).collect();
+        self.node(
+            tag,
+            &[
+                ("name", self.token(&function.name)),
+                ("params", format!("[{}]", params.join(","))),
+                ("variadic", function.variadic.to_string()),
+                ("body", self.stmts(&function.body)?),
+            ],
+        )
+    }
+}
+
+// A JSON string literal, escaping quotes, backslashes and control characters.
+pub(crate) fn string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl ExprVisitor<String> for AstJson {
+    fn visit_array_expr(&self, _: Rc<Expr>, expr: &ArrayExpr) -> Result<String, LoxResult> {
+        self.node("ArrayExpr", &[("elements", self.exprs(&expr.elements)?)])
+    }
+
+    fn visit_assign_expr(&self, _: Rc<Expr>, expr: &AssignExpr) -> Result<String, LoxResult> {
+        self.node(
+            "AssignExpr",
+            &[
+                ("name", self.token(&expr.name)),
+                ("value", self.expr(&expr.value)?),
+            ],
+        )
+    }
+
+    fn visit_binary_expr(&self, _: Rc<Expr>, expr: &BinaryExpr) -> Result<String, LoxResult> {
+        self.node(
+            "BinaryExpr",
+            &[
+                ("left", self.expr(&expr.left)?),
+                ("operator", self.token(&expr.operator)),
+                ("right", self.expr(&expr.right)?),
+            ],
+        )
+    }
+
+    fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<String, LoxResult> {
+        self.node(
+            "CallExpr",
+            &[
+                ("callee", self.expr(&expr.callee)?),
+                ("arguments", self.exprs(&expr.arguments)?),
+            ],
+        )
+    }
+
+    fn visit_comma_expr(&self, _: Rc<Expr>, expr: &CommaExpr) -> Result<String, LoxResult> {
+        self.node(
+            "CommaExpr",
+            &[
+                ("left", self.expr(&expr.left)?),
+                ("right", self.expr(&expr.right)?),
+            ],
+        )
+    }
+
+    fn visit_function_expr(&self, _: Rc<Expr>, expr: &FunctionExpr) -> Result<String, LoxResult> {
+        self.node(
+            "FunctionExpr",
+            &[(
+                "declaration",
+                self.function("FunctionStmt", &expr.declaration)?,
+            )],
+        )
+    }
+
+    fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<String, LoxResult> {
+        self.node(
+            "GetExpr",
+            &[
+                ("object", self.expr(&expr.object)?),
+                ("name", self.token(&expr.name)),
+            ],
+        )
+    }
+
+    fn visit_block_expr(&self, _: Rc<Expr>, expr: &BlockExpr) -> Result<String, LoxResult> {
+        self.node(
+            "BlockExpr",
+            &[
+                ("statements", self.stmts(&expr.statements)?),
+                ("value", self.optional_expr(&expr.value)?),
+            ],
+        )
+    }
+
+    fn visit_grouping_expr(&self, _: Rc<Expr>, expr: &GroupingExpr) -> Result<String, LoxResult> {
+        self.node(
+            "GroupingExpr",
+            &[("expression", self.expr(&expr.expression)?)],
+        )
+    }
+
+    fn visit_index_expr(&self, _: Rc<Expr>, expr: &IndexExpr) -> Result<String, LoxResult> {
+        self.node(
+            "IndexExpr",
+            &[
+                ("object", self.expr(&expr.object)?),
+                ("index", self.expr(&expr.index)?),
+            ],
+        )
+    }
+
+    fn visit_indexset_expr(&self, _: Rc<Expr>, expr: &IndexSetExpr) -> Result<String, LoxResult> {
+        self.node(
+            "IndexSetExpr",
+            &[
+                ("object", self.expr(&expr.object)?),
+                ("index", self.expr(&expr.index)?),
+                ("value", self.expr(&expr.value)?),
+            ],
+        )
+    }
+
+    fn visit_literal_expr(&self, _: Rc<Expr>, expr: &LiteralExpr) -> Result<String, LoxResult> {
+        let value = match &expr.value {
+            Some(Object::Number(n)) => format_number(*n),
+            Some(Object::String(s)) => string(s),
+            Some(Object::Bool(b)) => b.to_string(),
+            _ => "null".to_string(),
+        };
+        self.node("LiteralExpr", &[("value", value)])
+    }
+
+    fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<String, LoxResult> {
+        self.node(
+            "LogicalExpr",
+            &[
+                ("left", self.expr(&expr.left)?),
+                ("operator", self.token(&expr.operator)),
+                ("right", self.expr(&expr.right)?),
+            ],
+        )
+    }
+
+    fn visit_map_expr(&self, _: Rc<Expr>, expr: &MapExpr) -> Result<String, LoxResult> {
+        self.node(
+            "MapExpr",
+            &[
+                ("keys", self.exprs(&expr.keys)?),
+                ("values", self.exprs(&expr.values)?),
+            ],
+        )
+    }
+
+    fn visit_set_expr(&self, _: Rc<Expr>, expr: &SetExpr) -> Result<String, LoxResult> {
+        self.node(
+            "SetExpr",
+            &[
+                ("object", self.expr(&expr.object)?),
+                ("name", self.token(&expr.name)),
+                ("value", self.expr(&expr.value)?),
+            ],
+        )
+    }
+
+    fn visit_super_expr(&self, _: Rc<Expr>, expr: &SuperExpr) -> Result<String, LoxResult> {
+        self.node("SuperExpr", &[("method", self.token(&expr.method))])
+    }
+
+    fn visit_this_expr(&self, _: Rc<Expr>, _expr: &ThisExpr) -> Result<String, LoxResult> {
+        self.node("ThisExpr", &[])
+    }
+
+    fn visit_unary_expr(&self, _: Rc<Expr>, expr: &UnaryExpr) -> Result<String, LoxResult> {
+        self.node(
+            "UnaryExpr",
+            &[
+                ("operator", self.token(&expr.operator)),
+                ("right", self.expr(&expr.right)?),
+            ],
+        )
+    }
+
+    fn visit_variable_expr(&self, _: Rc<Expr>, expr: &VariableExpr) -> Result<String, LoxResult> {
+        self.node("VariableExpr", &[("name", self.token(&expr.name))])
+    }
+}
+
+impl StmtVisitor<String> for AstJson {
+    fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<String, LoxResult> {
+        self.node(
+            "BlockStmt",
+            &[("statements", self.stmts(&stmt.statements)?)],
+        )
+    }
+
+    fn visit_class_stmt(&self, _: Rc<Stmt>, stmt: &ClassStmt) -> Result<String, LoxResult> {
+        self.node(
+            "ClassStmt",
+            &[
+                ("name", self.token(&stmt.name)),
+                ("superclass", self.optional_expr(&stmt.superclass)?),
+                ("methods", self.stmts(&stmt.methods)?),
+            ],
+        )
+    }
+
+    fn visit_if_stmt(&self, _: Rc<Stmt>, stmt: &IfStmt) -> Result<String, LoxResult> {
+        let else_branch = match &stmt.else_branch {
+            Some(else_branch) => self.stmt(else_branch)?,
+            None => "null".to_string(),
+        };
+        self.node(
+            "IfStmt",
+            &[
+                ("condition", self.expr(&stmt.condition)?),
+                ("then_branch", self.stmt(&stmt.then_branch)?),
+                ("else_branch", else_branch),
+            ],
+        )
+    }
+
+    fn visit_expression_stmt(
+        &self,
+        _: Rc<Stmt>,
+        stmt: &ExpressionStmt,
+    ) -> Result<String, LoxResult> {
+        self.node(
+            "ExpressionStmt",
+            &[("expression", self.expr(&stmt.expression)?)],
+        )
+    }
+
+    fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<String, LoxResult> {
+        self.function("FunctionStmt", stmt)
+    }
+
+    fn visit_break_stmt(&self, _: Rc<Stmt>, stmt: &BreakStmt) -> Result<String, LoxResult> {
+        self.node("BreakStmt", &[("label", self.optional_token(&stmt.label))])
+    }
+
+    fn visit_continue_stmt(&self, _: Rc<Stmt>, stmt: &ContinueStmt) -> Result<String, LoxResult> {
+        self.node(
+            "ContinueStmt",
+            &[("label", self.optional_token(&stmt.label))],
+        )
+    }
+
+    fn visit_import_stmt(&self, _: Rc<Stmt>, stmt: &ImportStmt) -> Result<String, LoxResult> {
+        let path = match &stmt.path.literal {
+            Some(Object::String(path)) => string(path),
+            _ => "null".to_string(),
+        };
+        self.node("ImportStmt", &[("path", path)])
+    }
+
+    fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<String, LoxResult> {
+        self.node("PrintStmt", &[("expression", self.expr(&stmt.expression)?)])
+    }
+
+    fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<String, LoxResult> {
+        self.node("ReturnStmt", &[("value", self.optional_expr(&stmt.value)?)])
+    }
+
+    fn visit_var_stmt(&self, _: Rc<Stmt>, stmt: &VarStmt) -> Result<String, LoxResult> {
+        self.node(
+            "VarStmt",
+            &[
+                ("name", self.token(&stmt.name)),
+                ("initializer", self.optional_expr(&stmt.initializer)?),
+                ("is_const", stmt.is_const.to_string()),
+            ],
+        )
+    }
+
+    fn visit_try_stmt(&self, _: Rc<Stmt>, stmt: &TryStmt) -> Result<String, LoxResult> {
+        self.node(
+            "TryStmt",
+            &[
+                ("try_body", self.stmts(&stmt.try_body)?),
+                ("error", self.token(&stmt.error)),
+                ("catch_body", self.stmts(&stmt.catch_body)?),
+                (
+                    "finally_body",
+                    match &stmt.finally_body {
+                        Some(finally_body) => self.stmts(finally_body)?,
+                        None => "null".to_string(),
+                    },
+                ),
+            ],
+        )
+    }
+
+    fn visit_multivar_stmt(&self, _: Rc<Stmt>, stmt: &MultiVarStmt) -> Result<String, LoxResult> {
+        self.node(
+            "MultiVarStmt",
+            &[("declarations", self.stmts(&stmt.declarations)?)],
+        )
+    }
+
+    fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<String, LoxResult> {
+        self.node(
+            "WhileStmt",
+            &[
+                ("condition", self.expr(&stmt.condition)?),
+                ("body", self.stmt(&stmt.body)?),
+                ("increment", self.optional_expr(&stmt.increment)?),
+                ("label", self.optional_token(&stmt.label)),
+                ("do_while", stmt.do_while.to_string()),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn json(source: &str) -> String {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let stmts = parser.parse().unwrap();
+        assert!(parser.success());
+        to_json(&stmts).unwrap()
+    }
+
+    #[test]
+    fn serialises_a_variable_declaration() {
+        assert_eq!(
+            json("var x = 1 + 2;"),
+            "[{\"type\":\"VarStmt\",\"name\":\"x\",\"initializer\":{\"type\":\"BinaryExpr\",\
+             \"left\":{\"type\":\"LiteralExpr\",\"value\":1},\"operator\":\"+\",\
+             \"right\":{\"type\":\"LiteralExpr\",\"value\":2}},\"is_const\":false}]"
+        );
+    }
+
+    #[test]
+    fn keeps_nested_blocks_and_else_branches() {
+        assert_eq!(
+            json("if (a) { { print \"q\\\"\"; } } else b;"),
+            "[{\"type\":\"IfStmt\",\"condition\":{\"type\":\"VariableExpr\",\"name\":\"a\"},\
+             \"then_branch\":{\"type\":\"BlockStmt\",\"statements\":[{\"type\":\"BlockStmt\",\
+             \"statements\":[{\"type\":\"PrintStmt\",\"expression\":\
+             {\"type\":\"LiteralExpr\",\"value\":\"q\\\"\"}}]}]},\
+             \"else_branch\":{\"type\":\"ExpressionStmt\",\"expression\":\
+             {\"type\":\"VariableExpr\",\"name\":\"b\"}}}]"
+        );
+    }
+}
@@ -1,57 +1,353 @@
-// pub(crate) struct AstPrinter;
-//
-// impl AstPrinter {
-//     pub fn print(&self, expr: &Expr) -> Result<String, LoxResult> {
-//         expr.accept(self)
-//     }
-//
-//     // let name = "*".to_string();
-//     // let expr1 = Box::new(Expr::Literal(LiteralExpr { value: Some(Object::Number(4.0)) }));
-//     // let expr2 = Box::new(Expr::Literal(LiteralExpr { value: Some(Object::Number(3.0)) }));
-//     // let exprs = [&expr1, &expr2];
-//     //
-//     // let mult_result = ast_printer.parenthesize(name, &exprs);
-//     // // Result: "(* 4 3)"
-//     // For the unary minus:
-//     // let name = "-".to_string();
-//     // let expr = Box::new(Expr::Grouping(GroupingExpr { expression: Box::new(/* the multiplication expression */) }));
-//     // let exprs = [&expr];
-//     //
-//     // let final_result = ast_printer.parenthesize(name, &exprs);
-//     // // Result: "(- (* 4 3))"
-//     fn parenthesize(&self, name: String, exprs: &[&Expr]) -> Result<String, LoxResult> {
-//         let mut builder = format!("({} ", name);
-//         for expr in exprs {
-//             builder = format!("{} {}", builder, self.print(expr)?);
-//         }
-//         Ok(format!("{} )", builder))
-//     }
-// }
-//
-// impl ExprVisitor<String> for AstPrinter {
-//     fn visit_assign_expr(&self, expr: &AssignExpr) -> Result<String, LoxResult> {
-//     }
-//     fn visit_binary_expr(&self, expr: &BinaryExpr) -> Result<String, LoxResult> {
-//         self.parenthesize(expr.operator.lexeme.clone(), &[&expr.left, &expr.right])
-//     }
-//     fn visit_grouping_expr(&self, expr: &GroupingExpr) -> Result<String, LoxResult> {
-//         self.parenthesize("group".to_string(), &[&expr.expression])
-//     }
-//
-//     fn visit_literal_expr(&self, expr: &LiteralExpr) -> Result<String, LoxResult> {
-//         match &expr.value {
-//             Some(value) => Ok(value.to_string()),
-//             None => Ok("nil".to_string()),
-//         }
-//     }
-//
-//     fn visit_logical_expr(&self, expr: &LogicalExpr) -> Result<String, LoxResult> {
-//     }
-//
-//     fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<String, LoxResult> {
-//         self.parenthesize(expr.operator.lexeme.clone(), &[&expr.right])
-//     }
-//
-//     fn visit_variable_expr(&self, expr: &VariableExpr) -> Result<String, LoxResult> {
-//     }
-// }
+use crate::error::LoxResult;
+use crate::expr::*;
+use crate::stmt::*;
+use std::rc::Rc;
+
+// Prints the tree in Lisp-style prefix form, e.g. `-123 * (45.67)` becomes
+// `(* (- 123) (group 45.67))`. Statements that own other statements put each child on its
+// own line, indented two spaces per level of nesting.
+pub struct AstPrinter;
+
+impl AstPrinter {
+    pub fn print(&self, expr: &Rc<Expr>) -> Result<String, LoxResult> {
+        expr.accept(expr.clone(), self)
+    }
+
+    pub fn print_stmt(&self, stmt: &Rc<Stmt>) -> Result<String, LoxResult> {
+        stmt.accept(stmt.clone(), self)
+    }
+
+    // let name = "*".to_string();
+    // let exprs = [&four, &three];
+    //
+    // let mult_result = ast_printer.parenthesize(name, &exprs);
+    // // Result: "(* 4 3)"
+    fn parenthesize(&self, name: String, exprs: &[&Rc<Expr>]) -> Result<String, LoxResult> {
+        let mut builder = format!("({}", name);
+        for expr in exprs {
+            builder = format!("{} {}", builder, self.print(expr)?);
+        }
+        Ok(format!("{})", builder))
+    }
+
+    // Like parenthesize, but each child statement goes on its own indented line.
+    fn nest(&self, header: String, stmts: &[Rc<Stmt>]) -> Result<String, LoxResult> {
+        let mut builder = format!("({}", header);
+        for stmt in stmts {
+            for line in self.print_stmt(stmt)?.lines() {
+                builder = format!("{}\n  {}", builder, line);
+            }
+        }
+        Ok(format!("{})", builder))
+    }
+}
+
+impl ExprVisitor<String> for AstPrinter {
+    fn visit_array_expr(&self, _: Rc<Expr>, expr: &ArrayExpr) -> Result<String, LoxResult> {
+        let elements: Vec<&Rc<Expr>> = expr.elements.iter().collect();
+        self.parenthesize("list".to_string(), &elements)
+    }
+
+    fn visit_assign_expr(&self, _: Rc<Expr>, expr: &AssignExpr) -> Result<String, LoxResult> {
+        self.parenthesize(format!("= {}", expr.name.lexeme), &[&expr.value])
+    }
+
+    fn visit_binary_expr(&self, _: Rc<Expr>, expr: &BinaryExpr) -> Result<String, LoxResult> {
+        self.parenthesize(expr.operator.lexeme.clone(), &[&expr.left, &expr.right])
+    }
+
+    fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<String, LoxResult> {
+        let mut exprs = vec![&expr.callee];
+        exprs.extend(expr.arguments.iter());
+        self.parenthesize("call".to_string(), &exprs)
+    }
+
+    fn visit_comma_expr(&self, _: Rc<Expr>, expr: &CommaExpr) -> Result<String, LoxResult> {
+        self.parenthesize(",".to_string(), &[&expr.left, &expr.right])
+    }
+
+    fn visit_function_expr(&self, _: Rc<Expr>, expr: &FunctionExpr) -> Result<String, LoxResult> {
+        self.nest(
+            format!("fun ({})", param_list(&expr.declaration)),
+            &expr.declaration.body,
+        )
+    }
+
+    fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<String, LoxResult> {
+        Ok(format!(
+            "(. {} {})",
+            self.print(&expr.object)?,
+            expr.name.lexeme
+        ))
+    }
+
+    // Like a block statement, with the final value, if any, on the last line.
+    fn visit_block_expr(&self, _: Rc<Expr>, expr: &BlockExpr) -> Result<String, LoxResult> {
+        let mut tree = self.nest("block-expr".to_string(), &expr.statements)?;
+        if let Some(value) = &expr.value {
+            tree.pop();
+            tree = format!("{}\n  {})", tree, self.print(value)?);
+        }
+        Ok(tree)
+    }
+
+    fn visit_grouping_expr(&self, _: Rc<Expr>, expr: &GroupingExpr) -> Result<String, LoxResult> {
+        self.parenthesize("group".to_string(), &[&expr.expression])
+    }
+
+    fn visit_index_expr(&self, _: Rc<Expr>, expr: &IndexExpr) -> Result<String, LoxResult> {
+        self.parenthesize("index".to_string(), &[&expr.object, &expr.index])
+    }
+
+    fn visit_indexset_expr(&self, _: Rc<Expr>, expr: &IndexSetExpr) -> Result<String, LoxResult> {
+        Ok(format!(
+            "(= (index {} {}) {})",
+            self.print(&expr.object)?,
+            self.print(&expr.index)?,
+            self.print(&expr.value)?
+        ))
+    }
+
+    fn visit_literal_expr(&self, _: Rc<Expr>, expr: &LiteralExpr) -> Result<String, LoxResult> {
+        match &expr.value {
+            Some(value) => Ok(value.to_string()),
+            None => Ok("nil".to_string()),
+        }
+    }
+
+    fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<String, LoxResult> {
+        self.parenthesize(expr.operator.lexeme.clone(), &[&expr.left, &expr.right])
+    }
+
+    fn visit_map_expr(&self, _: Rc<Expr>, expr: &MapExpr) -> Result<String, LoxResult> {
+        let entries: Vec<&Rc<Expr>> = expr
+            .keys
+            .iter()
+            .zip(expr.values.iter())
+            .flat_map(|(key, value)| [key, value])
+            .collect();
+        self.parenthesize("map".to_string(), &entries)
+    }
+
+    fn visit_set_expr(&self, _: Rc<Expr>, expr: &SetExpr) -> Result<String, LoxResult> {
+        Ok(format!(
+            "(= (. {} {}) {})",
+            self.print(&expr.object)?,
+            expr.name.lexeme,
+            self.print(&expr.value)?
+        ))
+    }
+
+    fn visit_super_expr(&self, _: Rc<Expr>, expr: &SuperExpr) -> Result<String, LoxResult> {
+        Ok(format!("(super {})", expr.method.lexeme))
+    }
+
+    fn visit_this_expr(&self, _: Rc<Expr>, _expr: &ThisExpr) -> Result<String, LoxResult> {
+        Ok("this".to_string())
+    }
+
+    fn visit_unary_expr(&self, _: Rc<Expr>, expr: &UnaryExpr) -> Result<String, LoxResult> {
+        self.parenthesize(expr.operator.lexeme.clone(), &[&expr.right])
+    }
+
+    fn visit_variable_expr(&self, _: Rc<Expr>, expr: &VariableExpr) -> Result<String, LoxResult> {
+        Ok(expr.name.lexeme.clone())
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<String, LoxResult> {
+        self.nest("block".to_string(), &stmt.statements)
+    }
+
+    fn visit_class_stmt(&self, _: Rc<Stmt>, stmt: &ClassStmt) -> Result<String, LoxResult> {
+        let header = match &stmt.superclass {
+            Some(superclass) => format!("class {} < {}", stmt.name.lexeme, self.print(superclass)?),
+            None => format!("class {}", stmt.name.lexeme),
+        };
+        self.nest(header, &stmt.methods)
+    }
+
+    fn visit_if_stmt(&self, _: Rc<Stmt>, stmt: &IfStmt) -> Result<String, LoxResult> {
+        let mut branches = vec![stmt.then_branch.clone()];
+        if let Some(else_branch) = &stmt.else_branch {
+            branches.push(else_branch.clone());
+        }
+        self.nest(format!("if {}", self.print(&stmt.condition)?), &branches)
+    }
+
+    fn visit_expression_stmt(
+        &self,
+        _: Rc<Stmt>,
+        stmt: &ExpressionStmt,
+    ) -> Result<String, LoxResult> {
+        self.parenthesize(";".to_string(), &[&stmt.expression])
+    }
+
+    fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<String, LoxResult> {
+        self.nest(
+            format!("fun {} ({})", stmt.name.lexeme, param_list(stmt)),
+            &stmt.body,
+        )
+    }
+
+    fn visit_break_stmt(&self, _: Rc<Stmt>, stmt: &BreakStmt) -> Result<String, LoxResult> {
+        Ok(match &stmt.label {
+            Some(label) => format!("(break {})", label.lexeme),
+            None => "(break)".to_string(),
+        })
+    }
+
+    fn visit_continue_stmt(&self, _: Rc<Stmt>, stmt: &ContinueStmt) -> Result<String, LoxResult> {
+        Ok(match &stmt.label {
+            Some(label) => format!("(continue {})", label.lexeme),
+            None => "(continue)".to_string(),
+        })
+    }
+
+    fn visit_import_stmt(&self, _: Rc<Stmt>, stmt: &ImportStmt) -> Result<String, LoxResult> {
+        Ok(format!("(import {})", stmt.path.lexeme))
+    }
+
+    fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<String, LoxResult> {
+        self.parenthesize("print".to_string(), &[&stmt.expression])
+    }
+
+    fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<String, LoxResult> {
+        match &stmt.value {
+            Some(value) => self.parenthesize("return".to_string(), &[value]),
+            None => Ok("(return)".to_string()),
+        }
+    }
+
+    fn visit_var_stmt(&self, _: Rc<Stmt>, stmt: &VarStmt) -> Result<String, LoxResult> {
+        let keyword = if stmt.is_const { "const" } else { "var" };
+        let name = format!("{} {}", keyword, stmt.name.lexeme);
+        match &stmt.initializer {
+            Some(initializer) => self.parenthesize(name, &[initializer]),
+            None => Ok(format!("({})", name)),
+        }
+    }
+
+    fn visit_try_stmt(&self, _: Rc<Stmt>, stmt: &TryStmt) -> Result<String, LoxResult> {
+        let try_block = self.nest("try".to_string(), &stmt.try_body)?;
+        let catch_block = self.nest(format!("catch {}", stmt.error.lexeme), &stmt.catch_body)?;
+        match &stmt.finally_body {
+            Some(finally_body) => {
+                let finally_block = self.nest("finally".to_string(), finally_body)?;
+                Ok(format!("{}\n{}\n{}", try_block, catch_block, finally_block))
+            }
+            None => Ok(format!("{}\n{}", try_block, catch_block)),
+        }
+    }
+
+    fn visit_multivar_stmt(&self, _: Rc<Stmt>, stmt: &MultiVarStmt) -> Result<String, LoxResult> {
+        self.nest("vars".to_string(), &stmt.declarations)
+    }
+
+    fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<String, LoxResult> {
+        let keyword = if stmt.do_while { "do-while" } else { "while" };
+        let mut header = format!("{} {}", keyword, self.print(&stmt.condition)?);
+        if let Some(label) = &stmt.label {
+            header = format!("{}: {}", label.lexeme, header);
+        }
+        if let Some(increment) = &stmt.increment {
+            header = format!("{} (step {})", header, self.print(increment)?);
+        }
+        self.nest(header, std::slice::from_ref(&stmt.body))
+    }
+}
+
+// The space-separated parameter names of a function, with `...` marking a rest parameter.
+fn param_list(function: &FunctionStmt) -> String {
+    let mut params: Vec<String> = function.params.iter().map(|p| p.lexeme.clone()).collect();
+    if function.variadic {
+        if let Some(last) = params.last_mut() {
+            last.insert_str(0, "...");
+        }
+    }
+    params.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::token::Token;
+    use crate::token_type::TokenType;
+
+    fn print_source(source: &str) -> String {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        statements
+            .iter()
+            .map(|stmt| AstPrinter.print_stmt(stmt).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn literal(value: Object) -> Rc<Expr> {
+        Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: Some(value) })))
+    }
+
+    #[test]
+    fn prints_nested_expression() {
+        let expr = Rc::new(Expr::Binary(Rc::new(BinaryExpr {
+            left: Rc::new(Expr::Unary(Rc::new(UnaryExpr {
+                operator: Token::new(TokenType::Minus, "-".to_string(), None, 1, 1),
+                right: literal(Object::Number(123.0)),
+            }))),
+            operator: Token::new(TokenType::Star, "*".to_string(), None, 1, 1),
+            right: Rc::new(Expr::Grouping(Rc::new(GroupingExpr {
+                expression: literal(Object::Number(45.67)),
+            }))),
+        })));
+        assert_eq!(
+            AstPrinter.print(&expr).unwrap(),
+            "(* (- 123) (group 45.67))"
+        );
+    }
+
+    #[test]
+    fn prints_simple_statements() {
+        assert_eq!(print_source("var a = 1;"), "(var a 1)");
+        assert_eq!(print_source("var b;"), "(var b)");
+        assert_eq!(print_source("print a + 2;"), "(print (+ a 2))");
+        assert_eq!(
+            print_source("a = f(1, b) or c;"),
+            "(; (= a (or (call f 1 b) c)))"
+        );
+        assert_eq!(print_source("p.x = this.y;"), "(; (= (. p x) (. this y)))");
+        assert_eq!(
+            print_source("l[0] = [1, l[1]];"),
+            "(; (= (index l 0) (list 1 (index l 1))))"
+        );
+    }
+
+    #[test]
+    fn indents_nested_blocks() {
+        assert_eq!(
+            print_source("{ var a = 1; { print a; } }"),
+            "(block\n  (var a 1)\n  (block\n    (print a)))"
+        );
+    }
+
+    #[test]
+    fn prints_functions_and_control_flow() {
+        assert_eq!(
+            print_source("fun add(a, b) { return a + b; }"),
+            "(fun add (a b)\n  (return (+ a b)))"
+        );
+        assert_eq!(
+            print_source("if (x) print 1; else print 2;"),
+            "(if x\n  (print 1)\n  (print 2))"
+        );
+        assert_eq!(
+            print_source("while (true) break;"),
+            "(while true\n  (break))"
+        );
+    }
+}
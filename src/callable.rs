@@ -1,8 +1,11 @@
-use std::fmt;
 use crate::error::LoxResult;
-use crate::interpreter::Interpreter;
-use crate::object::Object;
+use crate::interpreter::{Capability, Interpreter};
+use crate::object::{checked_index, format_number, Freezable, Object};
+use crate::scanner::parse_number;
+use std::collections::HashMap;
+use std::fmt;
 use std::fmt::{Debug, Display};
+use std::io::BufRead;
 use std::rc::Rc;
 
 #[derive(Clone)]
@@ -31,7 +34,19 @@ impl PartialEq for Callable {
 pub trait LoxCallable {
     fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult>;
     fn arity(&self) -> usize;
+    // Callables taking optional trailing arguments accept anywhere from arity() to max_arity().
+    fn max_arity(&self) -> usize {
+        self.arity()
+    }
     fn to_string(&self) -> String;
+    // What an error message calls this callable: to_string() without any `<fn ...>` wrapper.
+    fn name(&self) -> String {
+        let text = self.to_string();
+        text.strip_prefix('<')
+            .and_then(|inner| inner.strip_suffix('>'))
+            .and_then(|inner| inner.rsplit_once(' '))
+            .map_or(text.clone(), |(_, name)| name.to_string())
+    }
 }
 impl LoxCallable for Callable {
     fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
@@ -42,27 +57,1437 @@ impl LoxCallable for Callable {
         self.func.arity()
     }
 
+    fn max_arity(&self) -> usize {
+        self.func.max_arity()
+    }
+
     fn to_string(&self) -> String {
         self.func.to_string()
     }
+
+    fn name(&self) -> String {
+        self.func.name()
+    }
 }
 
+fn since_epoch() -> std::time::Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+}
+
+// Seconds since the Unix epoch, with a fractional part, like clock() in the book's jlox.
 pub struct NativeClock;
 impl LoxCallable for NativeClock {
+    fn call(&self, interpreter: &Interpreter, _args: Vec<Object>) -> Result<Object, LoxResult> {
+        interpreter.require(Capability::Clock)?;
+        Ok(Object::Number(since_epoch().as_secs_f64()))
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn to_string(&self) -> String {
+        "<native clock>".to_string()
+    }
+}
+
+// Whole milliseconds since the Unix epoch.
+pub struct NativeClockMillis;
+impl LoxCallable for NativeClockMillis {
+    fn call(&self, interpreter: &Interpreter, _args: Vec<Object>) -> Result<Object, LoxResult> {
+        interpreter.require(Capability::Clock)?;
+        Ok(Object::Number(since_epoch().as_millis() as f64))
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn to_string(&self) -> String {
+        "<native clockMillis>".to_string()
+    }
+}
+
+// Ends the script with the given process exit code. The interpreter only unwinds with
+// LoxResult::Exit; leaving the process is up to whoever is running it.
+pub struct NativeExit;
+impl LoxCallable for NativeExit {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        interpreter.require(Capability::Exit)?;
+        let code = number_arg("exit", &args[0])?;
+        if code.fract() != 0.0 || code < i32::MIN as f64 || code > i32::MAX as f64 {
+            return Err(LoxResult::system_error("exit expects an integer exit code"));
+        }
+        Err(LoxResult::Exit { code: code as i32 })
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native exit>".to_string()
+    }
+}
+
+// Whole seconds since the Unix epoch: wall-clock time, where clock() is for measuring.
+pub struct NativeNow;
+impl LoxCallable for NativeNow {
+    fn call(&self, interpreter: &Interpreter, _args: Vec<Object>) -> Result<Object, LoxResult> {
+        interpreter.require(Capability::Clock)?;
+        Ok(Object::Number(since_epoch().as_secs() as f64))
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn to_string(&self) -> String {
+        "<native now>".to_string()
+    }
+}
+
+// Pauses the script for the given number of milliseconds.
+pub struct NativeSleep;
+impl LoxCallable for NativeSleep {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let millis = number_arg("sleep", &args[0])?;
+        // an infinite or NaN duration would hang or wrap around rather than fail
+        if !millis.is_finite() || millis < 0.0 {
+            return Err(LoxResult::system_error(
+                "sleep expects a non-negative number of milliseconds",
+            ));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(millis as u64));
+        Ok(Object::Nil)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native sleep>".to_string()
+    }
+}
+
+// Writes its argument and a newline to the interpreter's error writer, stderr by default.
+pub struct NativeEprint;
+impl LoxCallable for NativeEprint {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        interpreter.write_error_line(&args[0].to_string())?;
+        Ok(Object::Nil)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native eprint>".to_string()
+    }
+}
+
+pub type NativeFn = Rc<dyn Fn(&[Object]) -> Result<Object, LoxResult>>;
+
+// A native supplied by the host program through Interpreter::define_native.
+pub struct HostNative {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+impl LoxCallable for HostNative {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        (self.function)(&args)
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn to_string(&self) -> String {
+        format!("<native {}>", self.name)
+    }
+}
+
+// Prints a value the way `print` does, but without the newline.
+//
+//     write("Name: ");
+pub struct NativeWrite;
+impl LoxCallable for NativeWrite {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        interpreter.write_output(&args[0].to_string())?;
+        Ok(Object::Nil)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native write>".to_string()
+    }
+}
+
+// Prints the format with each `{}` replaced by the next argument, and no newline. There must
+// be exactly one argument per placeholder.
+//
+//     printf("{} + {} = {}\n", 1, 2, 3);
+pub struct NativePrintf;
+impl LoxCallable for NativePrintf {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let Object::String(format) = &args[0] else {
+            return Err(LoxResult::system_error(&format!(
+                "printf expects a string format but got {}",
+                args[0].get_type()
+            )));
+        };
+        let pieces: Vec<&str> = format.split("{}").collect();
+        let values = &args[1..];
+        if pieces.len() - 1 != values.len() {
+            return Err(LoxResult::system_error(&format!(
+                "printf format has {} placeholders but got {} arguments",
+                pieces.len() - 1,
+                values.len()
+            )));
+        }
+        let mut text = pieces[0].to_string();
+        for (value, piece) in values.iter().zip(&pieces[1..]) {
+            text.push_str(&value.to_string());
+            text.push_str(piece);
+        }
+        interpreter.write_output(&text)?;
+        Ok(Object::Nil)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        usize::MAX
+    }
+
+    fn to_string(&self) -> String {
+        "<native printf>".to_string()
+    }
+}
+
+// Reads one line from stdin, without the trailing newline; returns nil at end of input.
+//
+//     var name = readLine();
+pub struct NativeReadLine;
+impl LoxCallable for NativeReadLine {
     fn call(&self, _interpreter: &Interpreter, _args: Vec<Object>) -> Result<Object, LoxResult> {
+        read_line_from(&mut std::io::stdin().lock())
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn to_string(&self) -> String {
+        "<native readLine>".to_string()
+    }
+}
+
+fn read_line_from(reader: &mut impl BufRead) -> Result<Object, LoxResult> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) => Ok(Object::Nil),
+        Ok(_) => {
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            Ok(Object::String(line))
+        }
+        Err(err) => Err(LoxResult::system_error(&format!(
+            "readLine failed: {}",
+            err
+        ))),
+    }
+}
+
+// Reads a whole file as a string.
+//
+//     var text = readFile("notes.txt");
+pub struct NativeReadFile;
+impl LoxCallable for NativeReadFile {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let path = path_arg(interpreter, "readFile", &args[0])?;
+        std::fs::read_to_string(path)
+            .map(Object::String)
+            .map_err(|err| file_error("read", path, err))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native readFile>".to_string()
+    }
+}
+
+// Replaces a file's contents with a string, creating the file if needed.
+//
+//     writeFile("notes.txt", "remember the milk");
+pub struct NativeWriteFile;
+impl LoxCallable for NativeWriteFile {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let path = path_arg(interpreter, "writeFile", &args[0])?;
+        let Object::String(contents) = &args[1] else {
+            return Err(LoxResult::system_error(&format!(
+                "writeFile expects string contents but got {}",
+                args[1].get_type()
+            )));
+        };
+        std::fs::write(path, contents)
+            .map(|_| Object::Nil)
+            .map_err(|err| file_error("write", path, err))
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native writeFile>".to_string()
+    }
+}
+
+// The path a file native was given, once the interpreter has said scripts may touch files.
+fn path_arg<'a>(
+    interpreter: &Interpreter,
+    name: &str,
+    arg: &'a Object,
+) -> Result<&'a str, LoxResult> {
+    interpreter.require(Capability::Io)?;
+    match arg {
+        Object::String(path) => Ok(path),
+        _ => Err(LoxResult::system_error(&format!(
+            "{} expects a string path but got {}",
+            name,
+            arg.get_type()
+        ))),
+    }
+}
+
+fn file_error(action: &str, path: &str, err: std::io::Error) -> LoxResult {
+    LoxResult::system_error(&format!("Could not {} file '{}': {}", action, path, err))
+}
+
+// Math natives take numbers only; natives have no call-site token, so type errors are system errors.
+fn number_arg(name: &str, arg: &Object) -> Result<f64, LoxResult> {
+    match arg {
+        Object::Number(n) => Ok(*n),
+        _ => Err(LoxResult::system_error(&format!(
+            "{} expects a number but got {}",
+            name,
+            arg.get_type()
+        ))),
+    }
+}
+
+pub struct NativeSqrt;
+impl LoxCallable for NativeSqrt {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let n = number_arg("sqrt", &args[0])?;
+        Ok(Object::Number(n.sqrt()))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native sqrt>".to_string()
+    }
+}
+
+pub struct NativeFloor;
+impl LoxCallable for NativeFloor {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let n = number_arg("floor", &args[0])?;
+        Ok(Object::Number(n.floor()))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native floor>".to_string()
+    }
+}
+
+pub struct NativeAbs;
+impl LoxCallable for NativeAbs {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let n = number_arg("abs", &args[0])?;
+        Ok(Object::Number(n.abs()))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native abs>".to_string()
+    }
+}
+
+pub struct NativePow;
+impl LoxCallable for NativePow {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let base = number_arg("pow", &args[0])?;
+        let exponent = number_arg("pow", &args[1])?;
+        Ok(Object::Number(base.powf(exponent)))
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native pow>".to_string()
+    }
+}
+
+fn list_arg(name: &str, arg: &Object) -> Result<Rc<Freezable<Vec<Object>>>, LoxResult> {
+    match arg {
+        Object::List(list) => Ok(Rc::clone(list)),
+        _ => Err(LoxResult::system_error(&format!(
+            "{} expects a list but got {}",
+            name,
+            arg.get_type()
+        ))),
+    }
+}
+
+// A float in [0, 1) from the interpreter's seeded generator.
+pub struct NativeRandom;
+impl LoxCallable for NativeRandom {
+    fn call(&self, interpreter: &Interpreter, _args: Vec<Object>) -> Result<Object, LoxResult> {
+        // the top 53 bits fill a double's mantissa exactly
+        let bits = interpreter.next_random() >> 11;
+        Ok(Object::Number(bits as f64 / (1u64 << 53) as f64))
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn to_string(&self) -> String {
+        "<native random>".to_string()
+    }
+}
+
+// A whole number from lo to hi, both included.
+pub struct NativeRandomInt;
+impl LoxCallable for NativeRandomInt {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let lo = integer_arg("randomInt", &args[0])?;
+        let hi = integer_arg("randomInt", &args[1])?;
+        if lo > hi {
+            return Err(LoxResult::system_error(
+                "randomInt expects lo to be no greater than hi",
+            ));
+        }
+        let span = (hi - lo) as u64 + 1;
         Ok(Object::Number(
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as f64,
+            (lo + (interpreter.next_random() % span) as i64) as f64,
         ))
     }
 
     fn arity(&self) -> usize {
-        0
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native randomInt>".to_string()
+    }
+}
+
+// Whole numbers within 2^53, so every one of them, and the span between two, is exact.
+fn integer_arg(name: &str, arg: &Object) -> Result<i64, LoxResult> {
+    let n = number_arg(name, arg)?;
+    if n.fract() != 0.0 || n.abs() > (1u64 << 53) as f64 {
+        return Err(LoxResult::system_error(&format!(
+            "{} expects an integer but got {}",
+            name,
+            format_number(n)
+        )));
+    }
+    Ok(n as i64)
+}
+
+// Restarts random() and randomInt() from a sequence fixed by the seed.
+pub struct NativeRandomSeed;
+impl LoxCallable for NativeRandomSeed {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let seed = number_arg("randomSeed", &args[0])?;
+        interpreter.seed_random(seed.to_bits());
+        Ok(Object::Nil)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native randomSeed>".to_string()
+    }
+}
+
+pub struct NativeLen;
+impl LoxCallable for NativeLen {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        // strings count characters rather than bytes
+        let len = match &args[0] {
+            Object::String(s) => s.chars().count(),
+            Object::List(list) => list.borrow().len(),
+            Object::Map(map) => map.borrow().len(),
+            _ => return Err(LoxResult::system_error("object has no length")),
+        };
+        Ok(Object::Number(len as f64))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native len>".to_string()
+    }
+}
+
+pub struct NativePush;
+impl LoxCallable for NativePush {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        list_arg("push", &args[0])?.mutate()?.push(args[1].clone());
+        Ok(Object::Nil)
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native push>".to_string()
+    }
+}
+
+pub struct NativePop;
+impl LoxCallable for NativePop {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let popped = list_arg("pop", &args[0])?.mutate()?.pop();
+        popped.ok_or_else(|| LoxResult::system_error("pop from empty list"))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native pop>".to_string()
+    }
+}
+
+// Makes a list or map read-only for good, and returns it. Only the container itself is frozen:
+// lists and maps stored inside it can still change.
+pub struct NativeFreeze;
+impl LoxCallable for NativeFreeze {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        match &args[0] {
+            Object::List(list) => list.freeze(),
+            Object::Map(map) => map.freeze(),
+            other => {
+                return Err(LoxResult::system_error(&format!(
+                    "freeze expects a list or map but got {}",
+                    other.get_type()
+                )))
+            }
+        }
+        Ok(args[0].clone())
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native freeze>".to_string()
+    }
+}
+
+fn map_arg(name: &str, arg: &Object) -> Result<Rc<Freezable<HashMap<String, Object>>>, LoxResult> {
+    match arg {
+        Object::Map(map) => Ok(Rc::clone(map)),
+        _ => Err(LoxResult::system_error(&format!(
+            "{} expects a map but got {}",
+            name,
+            arg.get_type()
+        ))),
+    }
+}
+
+// Returns the keys as a new list, sorted so the order is predictable.
+pub struct NativeKeys;
+impl LoxCallable for NativeKeys {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let map = map_arg("keys", &args[0])?;
+        let mut keys: Vec<String> = map.borrow().keys().cloned().collect();
+        keys.sort();
+        Ok(Object::List(Rc::new(Freezable::new(
+            keys.into_iter().map(Object::String).collect(),
+        ))))
+    }
+
+    fn arity(&self) -> usize {
+        1
     }
 
     fn to_string(&self) -> String {
-        "Native:clock".to_string()
+        "<native keys>".to_string()
+    }
+}
+
+pub struct NativeHas;
+impl LoxCallable for NativeHas {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let map = map_arg("has", &args[0])?;
+        match &args[1] {
+            Object::String(key) => Ok(Object::Bool(map.borrow().contains_key(key))),
+            _ => Err(LoxResult::system_error("has expects a string key")),
+        }
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native has>".to_string()
+    }
+}
+
+fn string_arg(name: &str, arg: &Object) -> Result<Vec<char>, LoxResult> {
+    match arg {
+        Object::String(s) => Ok(s.chars().collect()),
+        _ => Err(LoxResult::system_error(&format!(
+            "{} expects a string but got {}",
+            name,
+            arg.get_type()
+        ))),
+    }
+}
+
+// substring(s, start, end): the characters from start up to, but not including, end.
+pub struct NativeSubstring;
+impl LoxCallable for NativeSubstring {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let chars = string_arg("substring", &args[0])?;
+        // Either bound may sit one past the last character, so it can't be checked as an index.
+        let bound = |arg: &Object| match arg {
+            Object::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= chars.len() as f64 => {
+                Ok(*n as usize)
+            }
+            _ => Err(LoxResult::system_error(&format!(
+                "substring bound {} out of range for string of length {}",
+                arg,
+                chars.len()
+            ))),
+        };
+        let (start, end) = (bound(&args[1])?, bound(&args[2])?);
+        if start > end {
+            return Err(LoxResult::system_error(&format!(
+                "substring start {} is after end {}",
+                start, end
+            )));
+        }
+        Ok(Object::String(chars[start..end].iter().collect()))
+    }
+
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn to_string(&self) -> String {
+        "<native substring>".to_string()
+    }
+}
+
+pub struct NativeCharAt;
+impl LoxCallable for NativeCharAt {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let chars = string_arg("charAt", &args[0])?;
+        let index = checked_index(&args[1], chars.len(), "string")
+            .map_err(|message| LoxResult::system_error(&message))?;
+        Ok(Object::String(chars[index].to_string()))
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native charAt>".to_string()
+    }
+}
+
+pub struct NativeToUpper;
+impl LoxCallable for NativeToUpper {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let s: String = string_arg("toUpper", &args[0])?.into_iter().collect();
+        Ok(Object::String(s.to_uppercase()))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native toUpper>".to_string()
+    }
+}
+
+pub struct NativeToLower;
+impl LoxCallable for NativeToLower {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let s: String = string_arg("toLower", &args[0])?.into_iter().collect();
+        Ok(Object::String(s.to_lowercase()))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native toLower>".to_string()
+    }
+}
+
+// Drops leading and trailing whitespace.
+pub struct NativeTrim;
+impl LoxCallable for NativeTrim {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let s: String = string_arg("trim", &args[0])?.into_iter().collect();
+        Ok(Object::String(s.trim().to_string()))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native trim>".to_string()
+    }
+}
+
+// split(s, sep): the pieces of s between each sep, so a string without sep, even "", gives a
+// one-element list. An empty sep splits s into its characters.
+pub struct NativeSplit;
+impl LoxCallable for NativeSplit {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let s: String = string_arg("split", &args[0])?.into_iter().collect();
+        let separator: String = string_arg("split", &args[1])?.into_iter().collect();
+        let pieces: Vec<Object> = if separator.is_empty() {
+            s.chars().map(|c| Object::String(c.to_string())).collect()
+        } else {
+            s.split(separator.as_str())
+                .map(|piece| Object::String(piece.to_string()))
+                .collect()
+        };
+        Ok(Object::List(Rc::new(Freezable::new(pieces))))
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native split>".to_string()
+    }
+}
+
+pub struct NativeTypeOf;
+impl LoxCallable for NativeTypeOf {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        Ok(Object::String(args[0].get_type()))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native typeof>".to_string()
+    }
+}
+
+pub struct NativeStr;
+impl LoxCallable for NativeStr {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        Ok(Object::String(args[0].to_string()))
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native str>".to_string()
+    }
+}
+
+pub struct NativeNum;
+impl LoxCallable for NativeNum {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        match &args[0] {
+            Object::Number(n) => Ok(Object::Number(*n)),
+            // the same syntax as a number literal, so "inf", "NaN", "+1" and ".5" are refused
+            Object::String(s) => parse_number(s.trim())
+                .map(Object::Number)
+                .ok_or_else(|| LoxResult::system_error("could not convert to number")),
+            _ => Err(LoxResult::system_error("could not convert to number")),
+        }
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        "<native num>".to_string()
+    }
+}
+
+// Unicode-aware case folding: upper-casing first expands characters such as 'ß' into "SS",
+// so "straße" and "STRASSE" fold to the same string.
+fn fold_case(s: &str) -> String {
+    s.to_uppercase().to_lowercase()
+}
+
+pub struct NativeEqualsIgnoreCase;
+impl LoxCallable for NativeEqualsIgnoreCase {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        match (&args[0], &args[1]) {
+            (Object::String(a), Object::String(b)) => {
+                Ok(Object::Bool(fold_case(a) == fold_case(b)))
+            }
+            _ => Err(LoxResult::system_error(
+                "equals_ignore_case expects two strings",
+            )),
+        }
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native equals_ignore_case>".to_string()
+    }
+}
+
+pub struct NativeCompareIgnoreCase;
+impl LoxCallable for NativeCompareIgnoreCase {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        match (&args[0], &args[1]) {
+            (Object::String(a), Object::String(b)) => {
+                let ordering = match fold_case(a).cmp(&fold_case(b)) {
+                    std::cmp::Ordering::Less => -1.0,
+                    std::cmp::Ordering::Equal => 0.0,
+                    std::cmp::Ordering::Greater => 1.0,
+                };
+                Ok(Object::Number(ordering))
+            }
+            _ => Err(LoxResult::system_error(
+                "compare_ignore_case expects two strings",
+            )),
+        }
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native compare_ignore_case>".to_string()
+    }
+}
+
+// assert(condition, message): fails with the message unless the condition is truthy.
+pub struct NativeAssert;
+impl LoxCallable for NativeAssert {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        if interpreter.is_truthy(&args[0]) {
+            Ok(Object::Nil)
+        } else {
+            Err(LoxResult::system_error(&args[1].to_string()))
+        }
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native assert>".to_string()
+    }
+}
+
+pub struct NativeAssertEq;
+impl LoxCallable for NativeAssertEq {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        if args[0].lox_equals(&args[1]) {
+            return Ok(Object::Nil);
+        }
+        let message = match args.get(2) {
+            Some(message) => format!("assertion failed: {} ({} != {})", message, args[0], args[1]),
+            None => format!("assertion failed: {} != {}", args[0], args[1]),
+        };
+        Err(LoxResult::system_error(&message))
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn max_arity(&self) -> usize {
+        3
+    }
+
+    fn to_string(&self) -> String {
+        "<native assert_eq>".to_string()
+    }
+}
+
+pub struct NativeCompose;
+impl LoxCallable for NativeCompose {
+    fn call(&self, _interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        match (&args[0], &args[1]) {
+            (Object::Func(f), Object::Func(g))
+                if accepts_one_argument(f) && accepts_one_argument(g) =>
+            {
+                Ok(Object::Func(Callable {
+                    func: Rc::new(ComposedFunction {
+                        f: f.clone(),
+                        g: g.clone(),
+                    }),
+                }))
+            }
+            _ => Err(LoxResult::system_error(
+                "compose expects two functions taking one argument",
+            )),
+        }
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn to_string(&self) -> String {
+        "<native compose>".to_string()
+    }
+}
+
+fn accepts_one_argument(callable: &Callable) -> bool {
+    callable.arity() <= 1 && callable.max_arity() >= 1
+}
+
+// The callable returned by compose(f, g): calls g, then passes its result to f. Both calls go
+// through the interpreter like any other, so they are checked, count towards the depth limit
+// and show in tracebacks, reported at the call of the composed function.
+struct ComposedFunction {
+    f: Callable,
+    g: Callable,
+}
+impl LoxCallable for ComposedFunction {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let paren = interpreter
+            .call_site()
+            .ok_or_else(|| LoxResult::system_error("compose() result called outside a call"))?;
+        let inner = interpreter.call_function(&self.g.func, args, &paren)?;
+        interpreter.call_function(&self.f.func, vec![inner], &paren)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn to_string(&self) -> String {
+        format!("compose({}, {})", self.f, self.g)
+    }
+}
+
+// `receiver.name` on a string or list: a property such as `length`, or a built-in method bound
+// to the receiver. None when values of that type have no such member.
+pub fn builtin_member(receiver: &Object, name: &str) -> Option<Object> {
+    let method: Rc<dyn LoxCallable> = match (receiver, name) {
+        (Object::String(s), "length") => return Some(Object::Number(s.chars().count() as f64)),
+        (Object::List(list), "length") => return Some(Object::Number(list.borrow().len() as f64)),
+        (Object::String(_), "upper") => Rc::new(NativeToUpper),
+        (Object::List(_), "push") => Rc::new(NativePush),
+        (Object::List(_), "pop") => Rc::new(NativePop),
+        _ => return None,
+    };
+    Some(Object::Func(Callable {
+        func: Rc::new(BoundNative {
+            receiver: receiver.clone(),
+            method,
+            name: name.to_string(),
+        }),
+    }))
+}
+
+// A native with its first argument fixed, so `list.push(x)` calls push(list, x).
+struct BoundNative {
+    receiver: Object,
+    method: Rc<dyn LoxCallable>,
+    name: String,
+}
+impl LoxCallable for BoundNative {
+    fn call(&self, interpreter: &Interpreter, mut args: Vec<Object>) -> Result<Object, LoxResult> {
+        args.insert(0, self.receiver.clone());
+        self.method.call(interpreter, args)
+    }
+
+    fn arity(&self) -> usize {
+        self.method.arity() - 1
+    }
+
+    fn max_arity(&self) -> usize {
+        self.method.max_arity() - 1
+    }
+
+    fn to_string(&self) -> String {
+        format!("<native method {}>", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Object {
+        Object::String(s.to_string())
+    }
+
+    #[test]
+    fn equals_ignore_case_matches_different_cases() {
+        let interpreter = Interpreter::new();
+        let result =
+            NativeEqualsIgnoreCase.call(&interpreter, vec![string("Hello"), string("hello")]);
+        assert_eq!(result.unwrap(), Object::Bool(true));
+
+        let result =
+            NativeEqualsIgnoreCase.call(&interpreter, vec![string("Hello"), string("world")]);
+        assert_eq!(result.unwrap(), Object::Bool(false));
+    }
+
+    #[test]
+    fn equals_ignore_case_folds_unicode() {
+        let interpreter = Interpreter::new();
+        let result =
+            NativeEqualsIgnoreCase.call(&interpreter, vec![string("Straße"), string("STRASSE")]);
+        assert_eq!(result.unwrap(), Object::Bool(true));
+
+        let result =
+            NativeEqualsIgnoreCase.call(&interpreter, vec![string("ÉCOLE"), string("école")]);
+        assert_eq!(result.unwrap(), Object::Bool(true));
+    }
+
+    #[test]
+    fn compare_ignore_case_orders_strings() {
+        let interpreter = Interpreter::new();
+        let result =
+            NativeCompareIgnoreCase.call(&interpreter, vec![string("apple"), string("BANANA")]);
+        assert_eq!(result.unwrap(), Object::Number(-1.0));
+
+        let result =
+            NativeCompareIgnoreCase.call(&interpreter, vec![string("Apple"), string("aPPLE")]);
+        assert_eq!(result.unwrap(), Object::Number(0.0));
+
+        let result =
+            NativeCompareIgnoreCase.call(&interpreter, vec![string("cherry"), string("Banana")]);
+        assert_eq!(result.unwrap(), Object::Number(1.0));
+    }
+
+    #[test]
+    fn ignore_case_natives_reject_non_strings() {
+        let interpreter = Interpreter::new();
+        assert!(NativeEqualsIgnoreCase
+            .call(&interpreter, vec![string("a"), Object::Number(1.0)])
+            .is_err());
+        assert!(NativeCompareIgnoreCase
+            .call(&interpreter, vec![Object::Nil, string("a")])
+            .is_err());
+    }
+
+    #[test]
+    fn assert_eq_passes_for_equal_values() {
+        let interpreter = Interpreter::new();
+        let result =
+            NativeAssertEq.call(&interpreter, vec![Object::Number(3.0), Object::Number(3.0)]);
+        assert_eq!(result.unwrap(), Object::Nil);
+    }
+
+    #[test]
+    fn assert_eq_reports_both_values() {
+        let interpreter = Interpreter::new();
+        let result =
+            NativeAssertEq.call(&interpreter, vec![Object::Number(3.0), Object::Number(4.0)]);
+        match result {
+            Err(LoxResult::SystemError { message }) => {
+                assert_eq!(message, "assertion failed: 3 != 4")
+            }
+            _ => panic!("expected assertion failure"),
+        }
+    }
+
+    #[test]
+    fn assert_eq_includes_custom_message() {
+        let interpreter = Interpreter::new();
+        let result = NativeAssertEq.call(
+            &interpreter,
+            vec![string("a"), string("b"), string("letters differ")],
+        );
+        match result {
+            Err(LoxResult::SystemError { message }) => {
+                assert_eq!(message, "assertion failed: letters differ (a != b)")
+            }
+            _ => panic!("expected assertion failure"),
+        }
+    }
+
+    #[test]
+    fn assert_eq_accepts_two_or_three_arguments() {
+        assert_eq!(NativeAssertEq.arity(), 2);
+        assert_eq!(NativeAssertEq.max_arity(), 3);
+    }
+
+    #[test]
+    fn read_line_strips_newline_and_returns_nil_at_eof() {
+        let mut reader = "first\r\nsecond\nlast".as_bytes();
+        assert_eq!(read_line_from(&mut reader).unwrap(), string("first"));
+        assert_eq!(read_line_from(&mut reader).unwrap(), string("second"));
+        assert_eq!(read_line_from(&mut reader).unwrap(), string("last"));
+        assert_eq!(read_line_from(&mut reader).unwrap(), Object::Nil);
+    }
+
+    #[test]
+    fn read_line_takes_no_arguments() {
+        assert_eq!(NativeReadLine.arity(), 0);
+        assert_eq!(LoxCallable::to_string(&NativeReadLine), "<native readLine>");
+    }
+
+    #[test]
+    fn math_natives_compute_results() {
+        let interpreter = Interpreter::new();
+        let n = Object::Number;
+        assert_eq!(NativeSqrt.call(&interpreter, vec![n(9.0)]).unwrap(), n(3.0));
+        assert_eq!(
+            NativeFloor.call(&interpreter, vec![n(3.7)]).unwrap(),
+            n(3.0)
+        );
+        assert_eq!(NativeAbs.call(&interpreter, vec![n(-4.0)]).unwrap(), n(4.0));
+        assert_eq!(
+            NativePow.call(&interpreter, vec![n(2.0), n(10.0)]).unwrap(),
+            n(1024.0)
+        );
+    }
+
+    #[test]
+    fn math_natives_reject_non_numbers() {
+        let interpreter = Interpreter::new();
+        match NativePow.call(&interpreter, vec![Object::Number(2.0), string("10")]) {
+            Err(LoxResult::SystemError { message }) => {
+                assert_eq!(message, "pow expects a number but got string")
+            }
+            _ => panic!("expected a type error"),
+        }
+        assert!(NativeSqrt.call(&interpreter, vec![Object::Nil]).is_err());
+    }
+
+    #[test]
+    fn len_counts_strings_lists_and_maps() {
+        let interpreter = Interpreter::new();
+        let n = Object::Number;
+        let len = |arg: Object| NativeLen.call(&interpreter, vec![arg]);
+        assert_eq!(len(string("héllo")).unwrap(), n(5.0));
+        let list = Object::List(Rc::new(Freezable::new(vec![n(1.0), n(2.0), n(3.0)])));
+        assert_eq!(len(list).unwrap(), n(3.0));
+        let map = HashMap::from([("a".to_string(), n(1.0)), ("b".to_string(), n(2.0))]);
+        assert_eq!(
+            len(Object::Map(Rc::new(Freezable::new(map)))).unwrap(),
+            n(2.0)
+        );
+        for value in [n(42.0), Object::Bool(true), Object::Nil] {
+            match len(value) {
+                Err(LoxResult::SystemError { message }) => {
+                    assert_eq!(message, "object has no length")
+                }
+                _ => panic!("expected len to reject a value without a length"),
+            }
+        }
+    }
+
+    #[test]
+    fn random_seed_makes_the_sequence_repeatable() {
+        let interpreter = Interpreter::new();
+        let n = Object::Number;
+        let sequence = || {
+            NativeRandomSeed.call(&interpreter, vec![n(42.0)]).unwrap();
+            (0..20)
+                .map(|_| NativeRandom.call(&interpreter, vec![]).unwrap())
+                .collect::<Vec<Object>>()
+        };
+        let first = sequence();
+        assert_eq!(first, sequence());
+        assert!(first
+            .iter()
+            .all(|value| matches!(value, Object::Number(x) if (0.0..1.0).contains(x))));
+        assert!(first.windows(2).any(|pair| pair[0] != pair[1]));
+
+        NativeRandomSeed.call(&interpreter, vec![n(43.0)]).unwrap();
+        let other = NativeRandom.call(&interpreter, vec![]).unwrap();
+        assert_ne!(other, first[0]);
+    }
+
+    #[test]
+    fn random_int_stays_in_its_inclusive_range() {
+        let interpreter = Interpreter::new();
+        let n = Object::Number;
+        for _ in 0..100 {
+            assert_eq!(
+                NativeRandomInt
+                    .call(&interpreter, vec![n(1.0), n(1.0)])
+                    .unwrap(),
+                n(1.0)
+            );
+            match NativeRandomInt.call(&interpreter, vec![n(-2.0), n(2.0)]) {
+                Ok(Object::Number(x)) => assert!(x.fract() == 0.0 && (-2.0..=2.0).contains(&x)),
+                other => panic!("expected a number, got {:?}", other),
+            }
+        }
+        for (args, expected) in [
+            (
+                vec![n(3.0), n(1.0)],
+                "randomInt expects lo to be no greater than hi",
+            ),
+            (
+                vec![n(0.5), n(1.0)],
+                "randomInt expects an integer but got 0.5",
+            ),
+            (
+                vec![n(0.0), string("9")],
+                "randomInt expects a number but got string",
+            ),
+        ] {
+            match NativeRandomInt.call(&interpreter, args) {
+                Err(LoxResult::SystemError { message }) => assert_eq!(message, expected),
+                _ => panic!("expected {:?}", expected),
+            }
+        }
+    }
+
+    #[test]
+    fn pop_rejects_an_empty_list() {
+        let interpreter = Interpreter::new();
+        let list = Object::List(Rc::new(Freezable::new(vec![])));
+        match NativePop.call(&interpreter, vec![list]) {
+            Err(LoxResult::SystemError { message }) => assert_eq!(message, "pop from empty list"),
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn substring_checks_its_bounds() {
+        let interpreter = Interpreter::new();
+        let hello = || string("hello");
+        let n = Object::Number;
+        assert_eq!(
+            NativeSubstring
+                .call(&interpreter, vec![hello(), n(0.0), n(5.0)])
+                .unwrap(),
+            hello()
+        );
+        match NativeSubstring.call(&interpreter, vec![hello(), n(2.0), n(6.0)]) {
+            Err(LoxResult::SystemError { message }) => {
+                assert_eq!(
+                    message,
+                    "substring bound 6 out of range for string of length 5"
+                )
+            }
+            _ => panic!("expected a bounds error"),
+        }
+        assert!(NativeSubstring
+            .call(&interpreter, vec![hello(), n(3.0), n(1.0)])
+            .is_err());
+    }
+
+    #[test]
+    fn string_natives_transform_strings() {
+        let interpreter = Interpreter::new();
+        let call = |native: &dyn LoxCallable, args: Vec<Object>| {
+            native.call(&interpreter, args).unwrap().to_string()
+        };
+        assert_eq!(call(&NativeToUpper, vec![string("Straße")]), "STRASSE");
+        assert_eq!(call(&NativeToLower, vec![string("MiXeD")]), "mixed");
+        assert_eq!(call(&NativeTrim, vec![string(" \t padded \n")]), "padded");
+        assert_eq!(
+            call(&NativeSplit, vec![string("a,b,c"), string(",")]),
+            "[\"a\", \"b\", \"c\"]"
+        );
+        assert_eq!(
+            call(&NativeSplit, vec![string("a, b"), string(", ")]),
+            "[\"a\", \"b\"]"
+        );
+        assert_eq!(
+            call(&NativeSplit, vec![string("abc"), string("")]),
+            "[\"a\", \"b\", \"c\"]"
+        );
+        match NativeSplit.call(&interpreter, vec![string(""), string(",")]) {
+            Ok(Object::List(pieces)) => assert_eq!(*pieces.borrow(), vec![string("")]),
+            _ => panic!("expected a list"),
+        }
+    }
+
+    #[test]
+    fn string_natives_reject_non_strings() {
+        let interpreter = Interpreter::new();
+        let natives: [(&dyn LoxCallable, &str); 3] = [
+            (&NativeToUpper, "toUpper"),
+            (&NativeToLower, "toLower"),
+            (&NativeTrim, "trim"),
+        ];
+        for (native, name) in natives {
+            match native.call(&interpreter, vec![Object::Number(1.0)]) {
+                Err(LoxResult::SystemError { message }) => {
+                    assert_eq!(message, format!("{} expects a string but got number", name))
+                }
+                _ => panic!("expected {} to reject a number", name),
+            }
+        }
+        assert!(NativeSplit
+            .call(&interpreter, vec![string("a"), Object::Nil])
+            .is_err());
+        assert!(NativeSplit
+            .call(&interpreter, vec![Object::Nil, string(",")])
+            .is_err());
+    }
+
+    #[test]
+    fn conversion_natives() {
+        let interpreter = Interpreter::new();
+        assert_eq!(
+            NativeTypeOf
+                .call(&interpreter, vec![Object::Number(1.0)])
+                .unwrap(),
+            string("number")
+        );
+        assert_eq!(
+            NativeStr
+                .call(&interpreter, vec![Object::Bool(true)])
+                .unwrap(),
+            string("true")
+        );
+        assert_eq!(
+            NativeNum.call(&interpreter, vec![string("3.5")]).unwrap(),
+            Object::Number(3.5)
+        );
+        assert_eq!(
+            NativeNum
+                .call(&interpreter, vec![string(" -0x10 ")])
+                .unwrap(),
+            Object::Number(-16.0)
+        );
+        for text in ["abc", "inf", "NaN", "infinity", "+1", ".5", ""] {
+            match NativeNum.call(&interpreter, vec![string(text)]) {
+                Err(LoxResult::SystemError { message }) => {
+                    assert_eq!(message, "could not convert to number")
+                }
+                _ => panic!("expected {:?} not to convert", text),
+            }
+        }
+    }
+
+    #[test]
+    fn clock_counts_seconds_and_clock_millis_milliseconds() {
+        let interpreter = Interpreter::new();
+        let Object::Number(seconds) = NativeClock.call(&interpreter, vec![]).unwrap() else {
+            panic!("clock should return a number");
+        };
+        let Object::Number(millis) = NativeClockMillis.call(&interpreter, vec![]).unwrap() else {
+            panic!("clockMillis should return a number");
+        };
+        // 2020-01-01 in seconds: anything earlier means clock isn't counting seconds
+        assert!(seconds > 1_577_836_800.0 && seconds < 1e11);
+        assert_eq!(millis.fract(), 0.0);
+        assert!((millis / 1000.0 - seconds).abs() < 5.0);
+    }
+
+    #[test]
+    fn exit_unwinds_with_an_integer_code() {
+        let interpreter = Interpreter::new();
+        match NativeExit.call(&interpreter, vec![Object::Number(3.0)]) {
+            Err(LoxResult::Exit { code }) => assert_eq!(code, 3),
+            _ => panic!("expected exit to unwind"),
+        }
+        match NativeExit.call(&interpreter, vec![Object::Number(1.5)]) {
+            Err(LoxResult::SystemError { message }) => {
+                assert_eq!(message, "exit expects an integer exit code")
+            }
+            _ => panic!("expected a fractional exit code to fail"),
+        }
+        assert!(NativeExit.call(&interpreter, vec![Object::Nil]).is_err());
+    }
+
+    #[test]
+    fn now_counts_whole_seconds_and_sleep_returns_nil() {
+        let interpreter = Interpreter::new();
+        let Object::Number(now) = NativeNow.call(&interpreter, vec![]).unwrap() else {
+            panic!("now should return a number");
+        };
+        assert!(now > 1_577_836_800.0 && now < 1e11);
+        assert_eq!(now.fract(), 0.0);
+        let slept = NativeSleep.call(&interpreter, vec![Object::Number(0.0)]);
+        assert_eq!(slept.unwrap(), Object::Nil);
+        // 1e308 * 10 overflows to infinity
+        for millis in [-1.0, 1e308 * 10.0, f64::NAN] {
+            match NativeSleep.call(&interpreter, vec![Object::Number(millis)]) {
+                Err(LoxResult::SystemError { message }) => assert_eq!(
+                    message,
+                    "sleep expects a non-negative number of milliseconds"
+                ),
+                _ => panic!("expected sleep({}) to fail", millis),
+            }
+        }
+        assert!(NativeSleep.call(&interpreter, vec![string("1")]).is_err());
+    }
+
+    #[test]
+    fn assert_fails_with_its_message_on_falsey_conditions() {
+        let interpreter = Interpreter::new();
+        let passed = NativeAssert.call(&interpreter, vec![Object::Number(0.0), string("unused")]);
+        assert_eq!(passed.unwrap(), Object::Nil);
+        match NativeAssert.call(&interpreter, vec![Object::Nil, string("value was nil")]) {
+            Err(LoxResult::SystemError { message }) => assert_eq!(message, "value was nil"),
+            _ => panic!("expected the assertion to fail"),
+        }
+        match NativeAssert.call(
+            &interpreter,
+            vec![Object::Bool(false), Object::Number(42.0)],
+        ) {
+            Err(LoxResult::SystemError { message }) => assert_eq!(message, "42"),
+            _ => panic!("expected the assertion to fail"),
+        }
     }
 }
@@ -0,0 +1,77 @@
+use crate::callable::LoxCallable;
+use crate::error::LoxResult;
+use crate::function::LoxFunction;
+use crate::instance::LoxInstance;
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// Cloning a class is cheap: every instance keeps its own handle to the shared method table.
+#[derive(Clone)]
+pub struct LoxClass {
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: Rc<HashMap<String, Rc<LoxFunction>>>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: &str,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, Rc<LoxFunction>>,
+    ) -> Self {
+        LoxClass {
+            name: name.to_string(),
+            superclass,
+            methods: Rc::new(methods),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Looks up a method on this class, falling back to the superclass chain.
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl LoxCallable for LoxClass {
+    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
+        let instance = Object::Instance(Rc::new(RefCell::new(LoxInstance::new(self))));
+        if let Some(initializer) = self.find_method("init") {
+            initializer.bind(&instance).call(interpreter, args)?;
+        }
+        Ok(instance)
+    }
+
+    fn arity(&self) -> usize {
+        self.find_method("init")
+            .map_or(0, |initializer| initializer.arity())
+    }
+
+    fn max_arity(&self) -> usize {
+        self.find_method("init")
+            .map_or(0, |initializer| initializer.max_arity())
+    }
+
+    fn to_string(&self) -> String {
+        self.name.clone()
+    }
+}
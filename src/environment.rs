@@ -1,63 +1,188 @@
 use crate::error::LoxResult;
 use crate::object::Object;
+use crate::symbol::Symbol;
 use crate::token::Token;
 use std::cell::RefCell;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
-use crate::token_type::TokenType;
 
+// Globals are looked up by name. Every other scope keeps its variables in the order they are
+// defined, and the resolver tells the interpreter which slot each use refers to. A variable
+// declared without an initializer holds None until it is assigned; get() and get_at() read it
+// as nil, and lookup() and lookup_at() let strict mode tell the two apart. A `const` is
+// recorded by name or slot, and assigning to it is an error.
 pub struct Environment {
-    values: HashMap<String, Object>,
+    kind: ScopeKind,
+    values: HashMap<Symbol, Option<Object>>,
+    slots: Vec<Option<Object>>,
+    constants: HashSet<Symbol>,
+    constant_slots: HashSet<usize>,
     enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
+// What a scope was opened for. A function body's scope carries the function's name, and the
+// innermost one around a piece of code names it in a traceback.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeKind {
+    Global,
+    Function(String),
+    Block,
+    Loop,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Self {
         Environment {
+            kind: ScopeKind::Global,
             values: HashMap::new(),
+            slots: Vec::new(),
+            constants: HashSet::new(),
+            constant_slots: HashSet::new(),
             enclosing: None,
         }
     }
 
     pub fn new_with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
         Environment {
+            kind: ScopeKind::Block,
             values: HashMap::new(),
+            slots: Vec::new(),
+            constants: HashSet::new(),
+            constant_slots: HashSet::new(),
             enclosing: Some(enclosing),
         }
     }
 
-    pub fn define(&mut self, name: String, value: Object) {
-        self.values.insert(name, value);
+    // A nested scope is a plain block until tagged otherwise.
+    pub fn with_kind(mut self, kind: ScopeKind) -> Self {
+        self.kind = kind;
+        self
     }
 
+    pub fn kind(&self) -> &ScopeKind {
+        &self.kind
+    }
 
-    pub fn get_at(&self, distance: usize, name: &str) -> Result<Object, LoxResult> {
+    // The function whose body this scope is, or sits inside; "<script>" at the top level.
+    pub fn function_name(&self) -> String {
+        match (&self.kind, &self.enclosing) {
+            (ScopeKind::Function(name), _) => name.clone(),
+            (_, Some(enclosing)) => enclosing.borrow().function_name(),
+            (_, None) => "<script>".to_string(),
+        }
+    }
+
+    // Only the global scope has no enclosing scope; anywhere else the resolver has already
+    // turned the name into the next slot.
+    pub fn define(&mut self, name: Symbol, value: Object) {
+        self.define_value(name, Some(value));
+    }
+
+    // `var x;`: x exists but has not been given a value.
+    pub fn define_uninitialized(&mut self, name: Symbol) {
+        self.define_value(name, None);
+    }
+
+    pub fn define_constant(&mut self, name: Symbol, value: Object) {
+        if self.enclosing.is_none() {
+            self.constants.insert(name);
+            self.values.insert(name, Some(value));
+        } else {
+            self.constant_slots.insert(self.slots.len());
+            self.slots.push(Some(value));
+        }
+    }
+
+    // Redeclaring a global replaces it, constness and all.
+    fn define_value(&mut self, name: Symbol, value: Option<Object>) {
+        if self.enclosing.is_none() {
+            self.constants.remove(&name);
+            self.values.insert(name, value);
+        } else {
+            self.slots.push(value);
+        }
+    }
+
+    // The resolver promises the slot exists, so failing to find it is a bug in the resolver or
+    // interpreter. There is no token to hang that on here, so it surfaces as a system error.
+    fn mismatch() -> LoxResult {
+        LoxResult::system_error("resolver/runtime mismatch: variable not found at expected depth")
+    }
+
+    fn constant_error(name: &Token) -> LoxResult {
+        LoxResult::runtime_error(
+            name,
+            &format!("Cannot assign to constant '{}'.", name.lexeme),
+        )
+    }
+
+    pub fn get_at(&self, distance: usize, slot: usize) -> Result<Object, LoxResult> {
+        Ok(self.lookup_at(distance, slot)?.unwrap_or(Object::Nil))
+    }
+
+    pub fn lookup_at(&self, distance: usize, slot: usize) -> Result<Option<Object>, LoxResult> {
         if distance == 0 {
-            Ok(self.values.get(name).unwrap().clone())
-        }else{
-           self.enclosing.as_ref().unwrap().borrow().get_at(distance - 1, name)
+            self.slots
+                .get(slot)
+                .cloned()
+                .ok_or_else(Environment::mismatch)
+        } else {
+            self.enclosing
+                .as_ref()
+                .ok_or_else(Environment::mismatch)?
+                .borrow()
+                .lookup_at(distance - 1, slot)
         }
     }
 
-    pub fn assign_at(&mut self, distance: usize, name: &Token, value: Object) -> Result<(), LoxResult> {
+    pub fn assign_at(
+        &mut self,
+        distance: usize,
+        slot: usize,
+        name: &Token,
+        value: Object,
+    ) -> Result<(), LoxResult> {
         if distance == 0 {
-            self.values.insert(name.lexeme.clone(), value);
+            let target = self.slots.get_mut(slot).ok_or_else(Environment::mismatch)?;
+            if self.constant_slots.contains(&slot) {
+                return Err(Environment::constant_error(name));
+            }
+            *target = Some(value);
             Ok(())
-        }else{
+        } else {
             self.enclosing
                 .as_ref()
-                .unwrap()
+                .ok_or_else(Environment::mismatch)?
                 .borrow_mut()
-                .assign_at(distance - 1, name, value)
+                .assign_at(distance - 1, slot, name, value)
         }
     }
 
-pub fn get(&self, name: &Token) -> Result<Object, LoxResult> {
-        if let Some(value) = self.values.get(name.lexeme.as_str()) {
+    // Every name defined directly in this scope, with a variable that has no value yet read as
+    // nil. Only meaningful for the global scope, the one scope that keeps names.
+    pub fn bindings(&self) -> Vec<(Symbol, Object)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (*name, value.clone().unwrap_or(Object::Nil)))
+            .collect()
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Object, LoxResult> {
+        Ok(self.lookup(name)?.unwrap_or(Object::Nil))
+    }
+
+    pub fn lookup(&self, name: &Token) -> Result<Option<Object>, LoxResult> {
+        if let Some(value) = self.values.get(&name.symbol()) {
             Ok(value.clone())
         } else if let Some(enclosing) = &self.enclosing {
-            enclosing.borrow().get(name)
+            enclosing.borrow().lookup(name)
         } else {
             Err(LoxResult::runtime_error(
                 name,
@@ -66,8 +191,11 @@ pub fn get(&self, name: &Token) -> Result<Object, LoxResult> {
         }
     }
     pub fn assign(&mut self, name: &Token, value: Object) -> Result<(), LoxResult> {
-        if let Entry::Occupied(mut object) = self.values.entry(name.lexeme.clone()) {
-            object.insert(value);
+        if let Entry::Occupied(mut object) = self.values.entry(name.symbol()) {
+            if self.constants.contains(&name.symbol()) {
+                return Err(Environment::constant_error(name));
+            }
+            object.insert(Some(value));
             Ok(())
         } else if let Some(enclosing) = &self.enclosing {
             enclosing.borrow_mut().assign(name, value)
@@ -85,46 +213,71 @@ mod tests {
     use super::*;
     use crate::token_type::TokenType;
 
+    fn identifier(name: &str) -> Token {
+        Token::new(TokenType::Identifier, name.to_string(), None, 0, 0)
+    }
+
     #[test]
     fn test_define() {
         let mut env = Environment::new();
-        env.define("a".to_string(), Object::Number(1.0));
-        assert_eq!(env.values.get("a"), Some(&Object::Number(1.0)));
+        env.define(Symbol::intern("a"), Object::Number(1.0));
+        assert_eq!(
+            env.values.get(&Symbol::intern("a")),
+            Some(&Some(Object::Number(1.0)))
+        );
     }
 
     #[test]
     fn test_define_multiple() {
         let mut env = Environment::new();
-        env.define("a".to_string(), Object::Number(1.0));
-        env.define("a".to_string(), Object::Bool(true));
-        let result = env.get(&Token::new(TokenType::Identifier, "a".to_string(), None, 0));
+        env.define(Symbol::intern("a"), Object::Number(1.0));
+        env.define(Symbol::intern("a"), Object::Bool(true));
+        let result = env.get(&Token::new(
+            TokenType::Identifier,
+            "a".to_string(),
+            None,
+            0,
+            0,
+        ));
         assert_eq!(result.unwrap(), Object::Bool(true));
     }
 
     #[test]
     fn error_when_getting_undefined_variable() {
         let env = Environment::new();
-        let result = env.get(&Token::new(TokenType::Identifier, "a".to_string(), None, 0));
+        let result = env.get(&Token::new(
+            TokenType::Identifier,
+            "a".to_string(),
+            None,
+            0,
+            0,
+        ));
         assert!(result.is_err());
     }
     #[test]
     fn error_when_assigning_undefined_variable() {
         let mut env = Environment::new();
-        let tok = &Token::new(TokenType::Identifier, "b".to_string(), None, 0);
-        assert!(env.assign(&tok, Object::Number(1.0)).is_err());
+        let tok = &Token::new(TokenType::Identifier, "b".to_string(), None, 0, 0);
+        assert!(env.assign(tok, Object::Number(1.0)).is_err());
     }
 
     #[test]
     fn test_re_assign() {
         let mut env = Environment::new();
-        env.define("a".to_string(), Object::Number(1.0));
+        env.define(Symbol::intern("a"), Object::Number(1.0));
         assert!(env
             .assign(
-                &Token::new(TokenType::Identifier, "a".to_string(), None, 0),
+                &Token::new(TokenType::Identifier, "a".to_string(), None, 0, 0),
                 Object::Bool(true)
             )
             .is_ok());
-        let result = env.get(&Token::new(TokenType::Identifier, "a".to_string(), None, 0));
+        let result = env.get(&Token::new(
+            TokenType::Identifier,
+            "a".to_string(),
+            None,
+            0,
+            0,
+        ));
         assert_eq!(result.unwrap(), Object::Bool(true));
     }
 
@@ -139,22 +292,132 @@ mod tests {
     fn can_read_from_enclosing_environment() {
         let env = Rc::new(RefCell::new(Environment::new()));
         env.borrow_mut()
-            .define("a".to_string(), Object::Number(1.0));
+            .define(Symbol::intern("a"), Object::Number(1.0));
         let env2 = Environment::new_with_enclosing(Rc::clone(&env));
         assert!(env2.enclosing.is_some());
-        let result = env2.get(&Token::new(TokenType::Identifier, "a".to_string(), None, 0));
+        let result = env2.get(&Token::new(
+            TokenType::Identifier,
+            "a".to_string(),
+            None,
+            0,
+            0,
+        ));
         assert_eq!(result.unwrap(), Object::Number(1.0));
     }
     #[test]
     fn can_assign_to_enclosing_environment() {
         let env = Rc::new(RefCell::new(Environment::new()));
         env.borrow_mut()
-            .define("a".to_string(), Object::Number(1.0));
+            .define(Symbol::intern("a"), Object::Number(1.0));
         let mut env2 = Environment::new_with_enclosing(Rc::clone(&env));
-        let token = Token::new(TokenType::Identifier, "a".to_string(), None, 0);
+        let token = Token::new(TokenType::Identifier, "a".to_string(), None, 0, 0);
         let assign_result = env2.assign(&token, Object::Number(92.0));
         assert!(assign_result.is_ok());
         let result = env2.get(&token);
         assert_eq!(result.unwrap(), Object::Number(92.0));
     }
+
+    #[test]
+    fn local_scopes_use_slots_in_definition_order() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let outer = Rc::new(RefCell::new(Environment::new_with_enclosing(globals)));
+        outer
+            .borrow_mut()
+            .define(Symbol::intern("a"), Object::Number(1.0));
+        outer
+            .borrow_mut()
+            .define(Symbol::intern("b"), Object::Number(2.0));
+        let mut inner = Environment::new_with_enclosing(Rc::clone(&outer));
+        inner.define(Symbol::intern("a"), Object::Number(3.0));
+        assert_eq!(inner.get_at(0, 0).unwrap(), Object::Number(3.0));
+        assert_eq!(inner.get_at(1, 1).unwrap(), Object::Number(2.0));
+        assert!(inner
+            .assign_at(1, 0, &identifier("a"), Object::Bool(true))
+            .is_ok());
+        assert_eq!(outer.borrow().get_at(0, 0).unwrap(), Object::Bool(true));
+        assert!(outer.borrow().values.is_empty());
+    }
+
+    #[test]
+    fn uninitialized_variables_read_as_nil_until_assigned() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let token = Token::new(TokenType::Identifier, "g".to_string(), None, 0, 0);
+        globals.borrow_mut().define_uninitialized(token.symbol());
+        assert_eq!(globals.borrow().get(&token).unwrap(), Object::Nil);
+        assert_eq!(globals.borrow().lookup(&token).unwrap(), None);
+        globals.borrow_mut().assign(&token, Object::Nil).unwrap();
+        assert_eq!(globals.borrow().lookup(&token).unwrap(), Some(Object::Nil));
+
+        let mut local = Environment::new_with_enclosing(globals);
+        local.define_uninitialized(Symbol::intern("l"));
+        assert_eq!(local.get_at(0, 0).unwrap(), Object::Nil);
+        assert_eq!(local.lookup_at(0, 0).unwrap(), None);
+        local
+            .assign_at(0, 0, &identifier("l"), Object::Number(1.0))
+            .unwrap();
+        assert_eq!(local.lookup_at(0, 0).unwrap(), Some(Object::Number(1.0)));
+    }
+
+    #[test]
+    fn missing_slots_are_errors_not_panics() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let mut local = Environment::new_with_enclosing(globals);
+        local.define(Symbol::intern("a"), Object::Nil);
+        for result in [
+            local.get_at(5, 0).map(|_| ()),
+            local.get_at(0, 1).map(|_| ()),
+            local.assign_at(5, 0, &identifier("a"), Object::Nil),
+            local.assign_at(0, 1, &identifier("a"), Object::Nil),
+        ] {
+            match result {
+                Err(LoxResult::SystemError { message }) => assert_eq!(
+                    message,
+                    "resolver/runtime mismatch: variable not found at expected depth"
+                ),
+                _ => panic!("expected a mismatch error"),
+            }
+        }
+    }
+
+    #[test]
+    fn constants_can_be_read_but_not_assigned() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let pi = identifier("PI");
+        globals
+            .borrow_mut()
+            .define_constant(pi.symbol(), Object::Number(3.0));
+        assert_eq!(globals.borrow().get(&pi).unwrap(), Object::Number(3.0));
+        match globals.borrow_mut().assign(&pi, Object::Nil) {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Cannot assign to constant 'PI'.")
+            }
+            _ => panic!("expected a constant error"),
+        }
+
+        let mut local = Environment::new_with_enclosing(globals);
+        local.define(Symbol::intern("v"), Object::Nil);
+        local.define_constant(Symbol::intern("c"), Object::Bool(true));
+        assert!(local.assign_at(0, 0, &identifier("v"), Object::Nil).is_ok());
+        assert!(local
+            .assign_at(0, 1, &identifier("c"), Object::Nil)
+            .is_err());
+        assert_eq!(local.get_at(0, 1).unwrap(), Object::Bool(true));
+    }
+
+    #[test]
+    fn scopes_inside_a_function_take_its_name() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        assert_eq!(globals.borrow().function_name(), "<script>");
+        let body = Environment::new_with_enclosing(Rc::clone(&globals))
+            .with_kind(ScopeKind::Function("tally".to_string()));
+        let body = Rc::new(RefCell::new(body));
+        let lp = Environment::new_with_enclosing(body).with_kind(ScopeKind::Loop);
+        let block = Environment::new_with_enclosing(Rc::new(RefCell::new(lp)));
+        assert_eq!(block.kind(), &ScopeKind::Block);
+        assert_eq!(block.function_name(), "tally");
+        assert_eq!(
+            Environment::new_with_enclosing(globals).function_name(),
+            "<script>"
+        );
+    }
 }
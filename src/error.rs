@@ -1,75 +1,416 @@
+use crate::ast_json;
 use crate::object::Object;
 use crate::token::Token;
 use crate::token_type::TokenType;
+use std::io::{self, Write};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LoxResult {
     ParseError { token: Token, message: String },
     RuntimeError { token: Token, message: String },
     GenericError { line: usize, message: String },
     SystemError { message: String },
-    Break,
+    // Control flow unwinding through the interpreter, not errors: never reported.
+    // A loop label, when the signal names one, picks which enclosing loop it applies to.
+    Break { label: Option<String> },
+    Continue { label: Option<String> },
     ReturnValue { value: Object },
+    // exit(code) unwinding the whole script, so an embedding host decides what ending means.
+    Exit { code: i32 },
 }
+// How diagnostics are written. Plain is the text jlox prints. Color is the same text with the
+// location in yellow and "Error" in red, for a terminal. Json is one object per error and line,
+// with no source snippet, for tools to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Plain,
+    Color,
+    Json,
+}
+
+impl ReportFormat {
+    // The format a --error-format value names.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "plain" => Some(ReportFormat::Plain),
+            "color" => Some(ReportFormat::Color),
+            "json" => Some(ReportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+// Errors stop the program; warnings, like unreachable code, are reported and it runs anyway.
+#[derive(Clone, Copy, PartialEq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        }
+    }
+
+    fn colour(self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => MAGENTA,
+        }
+    }
+}
+
+// Constructing an error does not report it: whoever finally handles it decides whether and
+// where it is written, with report() or report_to().
 impl LoxResult {
     pub fn new(line: usize, message: &str) -> Self {
-        let err = LoxResult::GenericError {
+        LoxResult::GenericError {
             line,
             message: message.to_string(),
-        };
-        err.report("");
-        err
+        }
     }
     pub fn pares_error(token: Token, message: &str) -> Self {
-        let err = LoxResult::ParseError {
-            token: token.clone(),
+        LoxResult::ParseError {
+            token,
             message: message.to_string(),
-        };
-        err.report("");
-        err
+        }
     }
     pub fn runtime_error(token: &Token, message: &str) -> Self {
-        let err = LoxResult::RuntimeError {
+        LoxResult::RuntimeError {
             token: token.clone(),
             message: message.to_string(),
-        };
-        err.report("");
-        err
+        }
     }
 
     pub fn return_value(value: Object) -> Self {
-        let err = LoxResult::ReturnValue { value };
-        err.report("");
-        err
+        LoxResult::ReturnValue { value }
+    }
+
+    pub fn is_control_flow(&self) -> bool {
+        matches!(
+            self,
+            LoxResult::Break { .. }
+                | LoxResult::Continue { .. }
+                | LoxResult::ReturnValue { .. }
+                | LoxResult::Exit { .. }
+        )
+    }
+
+    // The text of a genuine error, without its location; None for control flow signals.
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            LoxResult::ParseError { message, .. }
+            | LoxResult::RuntimeError { message, .. }
+            | LoxResult::GenericError { message, .. }
+            | LoxResult::SystemError { message } => Some(message),
+            LoxResult::Break { .. }
+            | LoxResult::Continue { .. }
+            | LoxResult::ReturnValue { .. }
+            | LoxResult::Exit { .. } => None,
+        }
+    }
+
+    // The line a genuine error points at, when it has one.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            LoxResult::ParseError { token, .. } | LoxResult::RuntimeError { token, .. } => {
+                Some(token.line)
+            }
+            LoxResult::GenericError { line, .. } => Some(*line),
+            _ => None,
+        }
     }
 
     pub fn system_error(message: &str) -> Self {
-        let err = LoxResult::SystemError {
+        LoxResult::SystemError {
             message: message.to_string(),
+        }
+    }
+    // "[line L, col C]" and " at 'lexeme': message", pointing at the start of the token.
+    fn token_parts(token: &Token, message: &str) -> (String, String) {
+        let at = if token.ttype == TokenType::Eof {
+            "end".to_string()
+        } else {
+            format!("'{}'", token.lexeme)
         };
-        err.report("");
-        err
+        (
+            format!("[line {}, col {}]", token.line, token.column),
+            format!(" at {}: {}", at, message),
+        )
     }
-    pub fn report(&self, loc: &str) {
+
+    // A diagnostic line split around the word "Error": the location before it, when there is
+    // one, and the text after it. None for control flow signals.
+    fn parts(&self, loc: &str) -> Option<(Option<String>, String)> {
         match self {
             LoxResult::ParseError { token, message }
             | LoxResult::RuntimeError { token, message } => {
-                if token.ttype == TokenType::Eof {
-                    eprintln!("[line {}] Error at end: {}", token.line, message);
-                } else {
-                    eprintln!(
-                        "[line {}] Error at '{}': {}",
-                        token.line, token.lexeme, message
-                    );
-                }
+                let (location, rest) = LoxResult::token_parts(token, message);
+                Some((Some(location), rest))
             }
-            LoxResult::GenericError { line, message } => {
-                eprintln!("[line {}] Error {}: {}", line, loc, message);
+            LoxResult::GenericError { line, message } => Some((
+                Some(format!("[line {}]", line)),
+                format!(" {}: {}", loc, message),
+            )),
+            LoxResult::SystemError { message } => Some((None, format!(": {}", message))),
+            LoxResult::Break { .. }
+            | LoxResult::Continue { .. }
+            | LoxResult::ReturnValue { .. }
+            | LoxResult::Exit { .. } => None,
+        }
+    }
+
+    pub fn report(&self, loc: &str) {
+        // nothing useful can be done if stderr itself is gone
+        let _ = self.report_to(&mut io::stderr(), loc);
+    }
+
+    // Writes the diagnostic line for a genuine error; control flow signals write nothing.
+    pub fn report_to(&self, out: &mut impl Write, loc: &str) -> io::Result<()> {
+        self.write_line(out, loc, Severity::Error, false)
+    }
+
+    // With colour on, the location is yellow and the severity red for an error, magenta for a
+    // warning.
+    fn write_line(
+        &self,
+        out: &mut impl Write,
+        loc: &str,
+        severity: Severity,
+        colour: bool,
+    ) -> io::Result<()> {
+        let Some((location, rest)) = self.parts(loc) else {
+            return Ok(());
+        };
+        let paint = |text: &str, code: &str| {
+            if colour {
+                format!("{}{}{}", code, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        if let Some(location) = location {
+            write!(out, "{} ", paint(&location, YELLOW))?;
+        }
+        writeln!(out, "{}{}", paint(severity.name(), severity.colour()), rest)
+    }
+
+    // {"line":N,"col":C,"kind":K,"message":M}, with null for a line or column the error
+    // doesn't have. None for control flow signals.
+    pub fn to_json(&self) -> Option<String> {
+        self.json(Severity::Error)
+    }
+
+    // A warning's kind is "warning" whatever variant carries it.
+    fn json(&self, severity: Severity) -> Option<String> {
+        let (line, col, kind, message) = match self {
+            LoxResult::ParseError { token, message } => {
+                (Some(token.line), Some(token.column), "parse", message)
+            }
+            LoxResult::RuntimeError { token, message } => {
+                (Some(token.line), Some(token.column), "runtime", message)
+            }
+            LoxResult::GenericError { line, message } => (Some(*line), None, "generic", message),
+            LoxResult::SystemError { message } => (None, None, "system", message),
+            LoxResult::Break { .. }
+            | LoxResult::Continue { .. }
+            | LoxResult::ReturnValue { .. }
+            | LoxResult::Exit { .. } => return None,
+        };
+        let kind = match severity {
+            Severity::Error => kind,
+            Severity::Warning => "warning",
+        };
+        let number = |n: Option<usize>| n.map_or("null".to_string(), |n| n.to_string());
+        Some(format!(
+            "{{\"line\":{},\"col\":{},\"kind\":{},\"message\":{}}}",
+            number(line),
+            number(col),
+            ast_json::string(kind),
+            ast_json::string(message)
+        ))
+    }
+
+    // Like report_to, but errors at a token are followed by that token's line of the source
+    // and a caret under its first character. A token past the last line (the EOF token after
+    // a trailing newline) gets no snippet.
+    pub fn report_source_to(
+        &self,
+        out: &mut impl Write,
+        loc: &str,
+        source: &str,
+    ) -> io::Result<()> {
+        self.report_format_to(out, loc, source, ReportFormat::Plain)
+    }
+
+    // report_source_to in the given format; Json leaves out the snippet.
+    pub fn report_format_to(
+        &self,
+        out: &mut impl Write,
+        loc: &str,
+        source: &str,
+        format: ReportFormat,
+    ) -> io::Result<()> {
+        self.write_report(out, loc, source, format, Severity::Error)
+    }
+
+    // report_format_to for a problem that doesn't stop the program, headed "Warning".
+    pub fn report_warning_to(
+        &self,
+        out: &mut impl Write,
+        source: &str,
+        format: ReportFormat,
+    ) -> io::Result<()> {
+        self.write_report(out, "", source, format, Severity::Warning)
+    }
+
+    fn write_report(
+        &self,
+        out: &mut impl Write,
+        loc: &str,
+        source: &str,
+        format: ReportFormat,
+        severity: Severity,
+    ) -> io::Result<()> {
+        match format {
+            ReportFormat::Plain => self.write_line(out, loc, severity, false)?,
+            ReportFormat::Color => self.write_line(out, loc, severity, true)?,
+            ReportFormat::Json => {
+                return match self.json(severity) {
+                    Some(json) => writeln!(out, "{}", json),
+                    None => Ok(()),
+                };
             }
-            LoxResult::Break | LoxResult::ReturnValue { .. } => {}
-            LoxResult::SystemError { message } => {
-                eprintln!("Error: {}", message);
+        }
+        if let LoxResult::ParseError { token, .. } | LoxResult::RuntimeError { token, .. } = self {
+            if let Some(snippet) = LoxResult::snippet(source, token) {
+                writeln!(out, "{}", snippet)?;
             }
         }
+        Ok(())
+    }
+
+    fn snippet(source: &str, token: &Token) -> Option<String> {
+        let text = source.lines().nth(token.line.checked_sub(1)?)?;
+        // keep tabs so the caret lines up however the terminal expands them
+        let indent: String = text
+            .chars()
+            .take(token.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        Some(format!("    {}\n    {}^", text, indent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reported(err: &LoxResult, format: ReportFormat) -> String {
+        let mut out = Vec::new();
+        err.report_format_to(&mut out, "", "", format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn token_errors_include_the_column() {
+        let token = Token::new(TokenType::Identifier, "x".to_string(), None, 3, 14);
+        assert_eq!(
+            reported(
+                &LoxResult::runtime_error(&token, "Undefined variable."),
+                ReportFormat::Plain
+            ),
+            "[line 3, col 14] Error at 'x': Undefined variable.\n"
+        );
+        assert_eq!(
+            reported(
+                &LoxResult::pares_error(Token::eof(4, 1), "Expect ';' after value."),
+                ReportFormat::Plain
+            ),
+            "[line 4, col 1] Error at end: Expect ';' after value.\n"
+        );
+    }
+
+    #[test]
+    fn plain_format_is_the_classic_text() {
+        assert_eq!(
+            reported(
+                &LoxResult::new(2, "Unexpected character."),
+                ReportFormat::Plain
+            ),
+            "[line 2] Error : Unexpected character.\n"
+        );
+        assert_eq!(
+            reported(&LoxResult::system_error("disk full"), ReportFormat::Plain),
+            "Error: disk full\n"
+        );
+        assert_eq!(
+            reported(&LoxResult::Exit { code: 0 }, ReportFormat::Plain),
+            ""
+        );
+    }
+
+    #[test]
+    fn color_format_highlights_location_and_severity() {
+        let token = Token::new(TokenType::Identifier, "x".to_string(), None, 3, 14);
+        assert_eq!(
+            reported(
+                &LoxResult::runtime_error(&token, "Undefined variable."),
+                ReportFormat::Color
+            ),
+            "\x1b[33m[line 3, col 14]\x1b[0m \x1b[31mError\x1b[0m at 'x': Undefined variable.\n"
+        );
+        assert_eq!(
+            reported(&LoxResult::system_error("disk full"), ReportFormat::Color),
+            "\x1b[31mError\x1b[0m: disk full\n"
+        );
+    }
+
+    #[test]
+    fn json_format_writes_one_object_per_error() {
+        let token = Token::new(TokenType::String, "\"a\"".to_string(), None, 3, 14);
+        assert_eq!(
+            reported(
+                &LoxResult::pares_error(token, "Say \"hi\"."),
+                ReportFormat::Json
+            ),
+            "{\"line\":3,\"col\":14,\"kind\":\"parse\",\"message\":\"Say \\\"hi\\\".\"}\n"
+        );
+        assert_eq!(
+            reported(&LoxResult::new(2, "bad"), ReportFormat::Json),
+            "{\"line\":2,\"col\":null,\"kind\":\"generic\",\"message\":\"bad\"}\n"
+        );
+        assert_eq!(
+            reported(&LoxResult::system_error("disk full"), ReportFormat::Json),
+            "{\"line\":null,\"col\":null,\"kind\":\"system\",\"message\":\"disk full\"}\n"
+        );
+        assert_eq!(
+            reported(&LoxResult::Break { label: None }, ReportFormat::Json),
+            ""
+        );
+    }
+
+    #[test]
+    fn snippets_point_at_the_token() {
+        let source = "var a = 1;\n\tprint a + nil;\n";
+        let token = Token::new(TokenType::Plus, "+".to_string(), None, 2, 10);
+        assert_eq!(
+            LoxResult::snippet(source, &token).unwrap(),
+            "    \tprint a + nil;\n    \t        ^"
+        );
+        assert_eq!(LoxResult::snippet(source, &Token::eof(3, 1)), None);
+        assert_eq!(
+            LoxResult::snippet("print 1", &Token::eof(1, 8)).unwrap(),
+            "    print 1\n           ^"
+        );
     }
 }
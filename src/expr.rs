@@ -1,26 +1,50 @@
 use crate::error::*;
 use crate::token::*;
 use crate::object::*;
+use crate::stmt::*;
 use std::rc::Rc;
+#[derive(Clone)]
 pub enum Expr {
+    Array(Rc<ArrayExpr>),
     Assign(Rc<AssignExpr>),
     Binary(Rc<BinaryExpr>),
+    Block(Rc<BlockExpr>),
     Call(Rc<CallExpr>),
+    Comma(Rc<CommaExpr>),
+    Function(Rc<FunctionExpr>),
+    Get(Rc<GetExpr>),
     Grouping(Rc<GroupingExpr>),
+    Index(Rc<IndexExpr>),
+    IndexSet(Rc<IndexSetExpr>),
     Literal(Rc<LiteralExpr>),
     Logical(Rc<LogicalExpr>),
+    Map(Rc<MapExpr>),
+    Set(Rc<SetExpr>),
+    Super(Rc<SuperExpr>),
+    This(Rc<ThisExpr>),
     Unary(Rc<UnaryExpr>),
     Variable(Rc<VariableExpr>),
 }
 impl PartialEq for Expr {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
+            (Expr::Array(expr1), Expr::Array(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Expr::Assign(expr1), Expr::Assign(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Expr::Binary(expr1), Expr::Binary(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::Block(expr1), Expr::Block(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Expr::Call(expr1), Expr::Call(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::Comma(expr1), Expr::Comma(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::Function(expr1), Expr::Function(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::Get(expr1), Expr::Get(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Expr::Grouping(expr1), Expr::Grouping(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::Index(expr1), Expr::Index(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::IndexSet(expr1), Expr::IndexSet(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Expr::Literal(expr1), Expr::Literal(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Expr::Logical(expr1), Expr::Logical(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::Map(expr1), Expr::Map(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::Set(expr1), Expr::Set(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::Super(expr1), Expr::Super(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Expr::This(expr1), Expr::This(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Expr::Unary(expr1), Expr::Unary(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Expr::Variable(expr1), Expr::Variable(expr2)) => Rc::ptr_eq(expr1, expr2),
           _=> false,
@@ -32,15 +56,25 @@ impl Eq for Expr{}
 
 use std::hash::*;
 impl Hash for Expr {
-    fn hash<H: Hasher>(&self, hasher: &mut H)
-    where H: Hasher, 
-       { match self {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+       match self {
+            Expr::Array(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Expr::Assign(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Expr::Binary(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::Block(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Expr::Call(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::Comma(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::Function(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::Get(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Expr::Grouping(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::Index(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::IndexSet(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Expr::Literal(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Expr::Logical(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::Map(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::Set(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::Super(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Expr::This(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Expr::Unary(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Expr::Variable(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
         }
@@ -49,17 +83,32 @@ impl Hash for Expr {
 impl Expr {
     pub fn accept<T>(&self, wrapper: Rc<Expr>, expr_visitor: &dyn ExprVisitor<T>) -> Result<T, LoxResult> {
         match self {
-            Expr::Assign(expr) => expr_visitor.visit_assign_expr(wrapper,&expr),
-            Expr::Binary(expr) => expr_visitor.visit_binary_expr(wrapper,&expr),
-            Expr::Call(expr) => expr_visitor.visit_call_expr(wrapper,&expr),
-            Expr::Grouping(expr) => expr_visitor.visit_grouping_expr(wrapper,&expr),
-            Expr::Literal(expr) => expr_visitor.visit_literal_expr(wrapper,&expr),
-            Expr::Logical(expr) => expr_visitor.visit_logical_expr(wrapper,&expr),
-            Expr::Unary(expr) => expr_visitor.visit_unary_expr(wrapper,&expr),
-            Expr::Variable(expr) => expr_visitor.visit_variable_expr(wrapper,&expr),
+            Expr::Array(expr) => expr_visitor.visit_array_expr(wrapper, expr),
+            Expr::Assign(expr) => expr_visitor.visit_assign_expr(wrapper, expr),
+            Expr::Binary(expr) => expr_visitor.visit_binary_expr(wrapper, expr),
+            Expr::Block(expr) => expr_visitor.visit_block_expr(wrapper, expr),
+            Expr::Call(expr) => expr_visitor.visit_call_expr(wrapper, expr),
+            Expr::Comma(expr) => expr_visitor.visit_comma_expr(wrapper, expr),
+            Expr::Function(expr) => expr_visitor.visit_function_expr(wrapper, expr),
+            Expr::Get(expr) => expr_visitor.visit_get_expr(wrapper, expr),
+            Expr::Grouping(expr) => expr_visitor.visit_grouping_expr(wrapper, expr),
+            Expr::Index(expr) => expr_visitor.visit_index_expr(wrapper, expr),
+            Expr::IndexSet(expr) => expr_visitor.visit_indexset_expr(wrapper, expr),
+            Expr::Literal(expr) => expr_visitor.visit_literal_expr(wrapper, expr),
+            Expr::Logical(expr) => expr_visitor.visit_logical_expr(wrapper, expr),
+            Expr::Map(expr) => expr_visitor.visit_map_expr(wrapper, expr),
+            Expr::Set(expr) => expr_visitor.visit_set_expr(wrapper, expr),
+            Expr::Super(expr) => expr_visitor.visit_super_expr(wrapper, expr),
+            Expr::This(expr) => expr_visitor.visit_this_expr(wrapper, expr),
+            Expr::Unary(expr) => expr_visitor.visit_unary_expr(wrapper, expr),
+            Expr::Variable(expr) => expr_visitor.visit_variable_expr(wrapper, expr),
         }
     }
 }
+pub struct ArrayExpr {
+    pub bracket: Token,
+    pub elements: Vec<Rc<Expr>>,
+}
 pub struct AssignExpr {
     pub name: Token,
     pub value: Rc<Expr>,
@@ -69,14 +118,40 @@ pub struct BinaryExpr {
     pub operator: Token,
     pub right: Rc<Expr>,
 }
+pub struct BlockExpr {
+    pub statements: Rc<Vec<Rc<Stmt>>>,
+    pub value: Option<Rc<Expr>>,
+}
 pub struct CallExpr {
     pub callee: Rc<Expr>,
     pub paren: Token,
     pub arguments: Vec<Rc<Expr>>,
 }
+pub struct CommaExpr {
+    pub left: Rc<Expr>,
+    pub right: Rc<Expr>,
+}
+pub struct FunctionExpr {
+    pub declaration: Rc<FunctionStmt>,
+}
+pub struct GetExpr {
+    pub object: Rc<Expr>,
+    pub name: Token,
+}
 pub struct GroupingExpr {
     pub expression: Rc<Expr>,
 }
+pub struct IndexExpr {
+    pub object: Rc<Expr>,
+    pub bracket: Token,
+    pub index: Rc<Expr>,
+}
+pub struct IndexSetExpr {
+    pub object: Rc<Expr>,
+    pub bracket: Token,
+    pub index: Rc<Expr>,
+    pub value: Rc<Expr>,
+}
 pub struct LiteralExpr {
     pub value: Option<Object>,
 }
@@ -85,6 +160,23 @@ pub struct LogicalExpr {
     pub operator: Token,
     pub right: Rc<Expr>,
 }
+pub struct MapExpr {
+    pub brace: Token,
+    pub keys: Vec<Rc<Expr>>,
+    pub values: Vec<Rc<Expr>>,
+}
+pub struct SetExpr {
+    pub object: Rc<Expr>,
+    pub name: Token,
+    pub value: Rc<Expr>,
+}
+pub struct SuperExpr {
+    pub keyword: Token,
+    pub method: Token,
+}
+pub struct ThisExpr {
+    pub keyword: Token,
+}
 pub struct UnaryExpr {
     pub operator: Token,
     pub right: Rc<Expr>,
@@ -93,12 +185,23 @@ pub struct VariableExpr {
     pub name: Token,
 }
 pub trait ExprVisitor<T> {
+    fn visit_array_expr(&self, wrapper: Rc<Expr>, expr: &ArrayExpr) -> Result<T,LoxResult>;
     fn visit_assign_expr(&self, wrapper: Rc<Expr>, expr: &AssignExpr) -> Result<T,LoxResult>;
     fn visit_binary_expr(&self, wrapper: Rc<Expr>, expr: &BinaryExpr) -> Result<T,LoxResult>;
+    fn visit_block_expr(&self, wrapper: Rc<Expr>, expr: &BlockExpr) -> Result<T,LoxResult>;
     fn visit_call_expr(&self, wrapper: Rc<Expr>, expr: &CallExpr) -> Result<T,LoxResult>;
+    fn visit_comma_expr(&self, wrapper: Rc<Expr>, expr: &CommaExpr) -> Result<T,LoxResult>;
+    fn visit_function_expr(&self, wrapper: Rc<Expr>, expr: &FunctionExpr) -> Result<T,LoxResult>;
+    fn visit_get_expr(&self, wrapper: Rc<Expr>, expr: &GetExpr) -> Result<T,LoxResult>;
     fn visit_grouping_expr(&self, wrapper: Rc<Expr>, expr: &GroupingExpr) -> Result<T,LoxResult>;
+    fn visit_index_expr(&self, wrapper: Rc<Expr>, expr: &IndexExpr) -> Result<T,LoxResult>;
+    fn visit_indexset_expr(&self, wrapper: Rc<Expr>, expr: &IndexSetExpr) -> Result<T,LoxResult>;
     fn visit_literal_expr(&self, wrapper: Rc<Expr>, expr: &LiteralExpr) -> Result<T,LoxResult>;
     fn visit_logical_expr(&self, wrapper: Rc<Expr>, expr: &LogicalExpr) -> Result<T,LoxResult>;
+    fn visit_map_expr(&self, wrapper: Rc<Expr>, expr: &MapExpr) -> Result<T,LoxResult>;
+    fn visit_set_expr(&self, wrapper: Rc<Expr>, expr: &SetExpr) -> Result<T,LoxResult>;
+    fn visit_super_expr(&self, wrapper: Rc<Expr>, expr: &SuperExpr) -> Result<T,LoxResult>;
+    fn visit_this_expr(&self, wrapper: Rc<Expr>, expr: &ThisExpr) -> Result<T,LoxResult>;
     fn visit_unary_expr(&self, wrapper: Rc<Expr>, expr: &UnaryExpr) -> Result<T,LoxResult>;
     fn visit_variable_expr(&self, wrapper: Rc<Expr>, expr: &VariableExpr) -> Result<T,LoxResult>;
 }
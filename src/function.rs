@@ -1,9 +1,10 @@
 use crate::callable::LoxCallable;
-use crate::environment::Environment;
+use crate::environment::{Environment, ScopeKind};
 use crate::error::LoxResult;
 use crate::interpreter::Interpreter;
-use crate::object::Object;
+use crate::object::{Freezable, Object};
 use crate::stmt::{FunctionStmt, Stmt};
+use crate::symbol::Symbol;
 use crate::token::Token;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -11,38 +12,100 @@ use std::rc::Rc;
 pub struct LoxFunction {
     name: Token,
     params: Rc<Vec<Token>>,
+    variadic: bool,
+    is_lambda: bool,
     body: Rc<Vec<Rc<Stmt>>>,
     closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
 }
 
 impl LoxFunction {
-    pub fn new(declaration: &FunctionStmt, closure: &Rc<RefCell<Environment>>) -> Self {
+    pub fn new(
+        declaration: &FunctionStmt,
+        closure: &Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
         LoxFunction {
             name: declaration.name.clone(),
             params: Rc::clone(&declaration.params),
+            variadic: declaration.variadic,
+            is_lambda: declaration.is_lambda,
             body: Rc::clone(&declaration.body),
             closure: Rc::clone(closure),
+            is_initializer,
+        }
+    }
+
+    // Returns a copy of this method whose closure has `this` bound to the given instance.
+    pub fn bind(&self, instance: &Object) -> LoxFunction {
+        let mut environment = Environment::new_with_enclosing(Rc::clone(&self.closure));
+        environment.define(Symbol::intern("this"), instance.clone());
+        LoxFunction {
+            name: self.name.clone(),
+            params: Rc::clone(&self.params),
+            variadic: self.variadic,
+            is_lambda: self.is_lambda,
+            body: Rc::clone(&self.body),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
         }
     }
 }
 
 impl LoxCallable for LoxFunction {
-    fn call(&self, interpreter: &Interpreter, args: Vec<Object>) -> Result<Object, LoxResult> {
-        let mut env = Environment::new_with_enclosing(Rc::clone(&self.closure));
+    fn call(&self, interpreter: &Interpreter, mut args: Vec<Object>) -> Result<Object, LoxResult> {
+        let mut env = Environment::new_with_enclosing(Rc::clone(&self.closure))
+            .with_kind(ScopeKind::Function(self.name()));
+        // a rest parameter soaks up whatever is left after the fixed ones
+        let rest = if self.variadic {
+            let fixed = self.arity().min(args.len());
+            Some(args.split_off(fixed))
+        } else {
+            None
+        };
         for (param, arg) in self.params.iter().zip(args) {
-            env.define(param.lexeme.clone(), arg);
+            env.define(param.symbol(), arg);
+        }
+        if let (Some(rest), Some(param)) = (rest, self.params.last()) {
+            env.define(param.symbol(), Object::List(Rc::new(Freezable::new(rest))));
         }
-        match interpreter.execute_block(&self.body, env) {
-            Err(LoxResult::ReturnValue { value: val }) => Ok(val),
-            Err(e) => Err(e),
-            Ok(_) => Ok(Object::Nil),
+        let result = match interpreter.execute_function_body(&self.body, env) {
+            Err(LoxResult::ReturnValue { value: val }) => val,
+            Err(e) => return Err(e),
+            Ok(_) => Object::Nil,
+        };
+        // an initializer always hands back the instance, even after a bare `return;`
+        if self.is_initializer {
+            // `this` is the only variable in the scope bind() made
+            return self.closure.borrow().get_at(0, 0);
         }
+        Ok(result)
     }
     fn arity(&self) -> usize {
-        self.params.len()
+        if self.variadic {
+            self.params.len() - 1
+        } else {
+            self.params.len()
+        }
+    }
+
+    fn max_arity(&self) -> usize {
+        if self.variadic {
+            usize::MAX
+        } else {
+            self.params.len()
+        }
     }
 
     fn to_string(&self) -> String {
-        self.name.lexeme.to_string()
+        format!("<fn {}>", self.name.lexeme)
+    }
+
+    fn name(&self) -> String {
+        if self.is_lambda {
+            "<anonymous>".to_string()
+        } else {
+            self.name.lexeme.clone()
+        }
     }
 }
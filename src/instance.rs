@@ -0,0 +1,57 @@
+use crate::callable::Callable;
+use crate::class::LoxClass;
+use crate::error::LoxResult;
+use crate::object::Object;
+use crate::token::Token;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+pub struct LoxInstance {
+    klass: LoxClass,
+    fields: HashMap<String, Object>,
+}
+
+impl LoxInstance {
+    pub fn new(klass: &LoxClass) -> Self {
+        LoxInstance {
+            klass: klass.clone(),
+            fields: HashMap::new(),
+        }
+    }
+
+    // Fields shadow methods; a method is bound to the instance it was read from.
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: &Token) -> Result<Object, LoxResult> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        let method = instance.borrow().klass.find_method(&name.lexeme);
+        if let Some(method) = method {
+            let bound = method.bind(&Object::Instance(Rc::clone(instance)));
+            return Ok(Object::Func(Callable {
+                func: Rc::new(bound),
+            }));
+        }
+        Err(LoxResult::runtime_error(
+            name,
+            &format!("Undefined property '{}'.", name.lexeme),
+        ))
+    }
+
+    pub fn set(&mut self, name: &Token, value: Object) {
+        self.fields.insert(name.lexeme.clone(), value);
+    }
+}
+
+impl fmt::Display for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.klass.name())
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
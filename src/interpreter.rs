@@ -1,60 +1,552 @@
-use crate::callable::{Callable, LoxCallable, NativeClock};
-use crate::environment::Environment;
+use crate::callable::*;
+use crate::class::LoxClass;
+use crate::environment::{Environment, ScopeKind};
 use crate::error::LoxResult;
 use crate::expr::*;
 use crate::function::LoxFunction;
-use crate::object::Object;
+use crate::instance::LoxInstance;
+use crate::lox::Lox;
+use crate::object::{checked_index, format_number, Freezable, Object};
+use crate::resolver::Resolver;
+use crate::span::{Span, Spans};
 use crate::stmt::{
-    BlockStmt, BreakStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt,
-    StmtVisitor, VarStmt, WhileStmt,
+    BlockStmt, BreakStmt, ClassStmt, ContinueStmt, ExpressionStmt, FunctionStmt, IfStmt,
+    ImportStmt, MultiVarStmt, PrintStmt, ReturnStmt, Stmt, StmtVisitor, TryStmt, VarStmt,
+    WhileStmt,
 };
+use crate::symbol::Symbol;
 use crate::token_type::TokenType;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
+use crate::token::Token;
 use std::ops::Deref;
 use std::rc::Rc;
-use crate::token::Token;
+
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+// The generator starts from the same seed every run, so a script that never calls
+// randomSeed() is still reproducible.
+const DEFAULT_RANDOM_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+// A step in a traceback: a line the program had reached and the function it is in, as the
+// environment tags of the scopes around that line name it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub line: usize,
+}
+
+// What natives may reach outside the interpreter. Everything is allowed by default, which is
+// what the CLI wants; an embedder running untrusted scripts turns off what they must not touch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    // readFile() and writeFile()
+    pub allow_io: bool,
+    // clock(), clockMillis() and now()
+    pub allow_clock: bool,
+    // exit()
+    pub allow_exit: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            allow_io: true,
+            allow_clock: true,
+            allow_exit: true,
+        }
+    }
+}
+
+impl Capabilities {
+    // Nothing outside the interpreter: scripts can only compute and print.
+    pub fn none() -> Self {
+        Capabilities {
+            allow_io: false,
+            allow_clock: false,
+            allow_exit: false,
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Io => self.allow_io,
+            Capability::Clock => self.allow_clock,
+            Capability::Exit => self.allow_exit,
+        }
+    }
+}
+
+// One of the switches in Capabilities, as a native names what it needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Io,
+    Clock,
+    Exit,
+}
+
+impl Capability {
+    fn description(self) -> &'static str {
+        match self {
+            Capability::Io => "file I/O",
+            Capability::Clock => "clock access",
+            Capability::Exit => "exit",
+        }
+    }
+}
 
 pub struct Interpreter {
     pub globals: Rc<RefCell<Environment>>,
     //An environment typically stores variables and their values during program execution
     environment: RefCell<Rc<RefCell<Environment>>>,
     nesting_level: RefCell<usize>,
-    locals: RefCell<HashMap<Rc<Expr>, usize>>,
+    // Lox calls currently in progress, outermost first, capped at max_depth so runaway
+    // recursion becomes a Lox error instead of overflowing the native stack. Each keeps the
+    // closing parenthesis of its call, where calls made on its behalf report errors.
+    call_stack: RefCell<Vec<(Frame, Token)>>,
+    // The calls that were in progress when the last error was raised, kept until taken.
+    traceback: RefCell<Option<Vec<Frame>>>,
+    max_depth: RefCell<usize>,
+    // In strict mode reading a variable declared without an initializer, before anything is
+    // assigned to it, is an error rather than nil.
+    strict: RefCell<bool>,
+    // With strict arithmetic, an operator producing infinity or NaN is an error instead of a
+    // value that quietly spoils every comparison after it.
+    strict_arithmetic: RefCell<bool>,
+    capabilities: RefCell<Capabilities>,
+    // xorshift64* state behind random(); never zero, which would stick at zero forever.
+    random_state: RefCell<u64>,
+    // (distance, slot) for every variable the resolver found in a local scope
+    locals: RefCell<HashMap<Rc<Expr>, (usize, usize)>>,
+    // Where each node of the programs run so far was parsed from, for tools built on top.
+    spans: RefCell<Spans>,
+    // Canonical path of every file run or imported so far. Importing one of them again does
+    // nothing, which is also what stops import cycles.
+    imported: RefCell<HashSet<PathBuf>>,
+    // The files whose statements are running, innermost import last; relative import paths
+    // start from the last one's directory.
+    scripts: RefCell<Vec<PathBuf>>,
+    // Where `print` writes: stdout unless with_output says otherwise.
+    output: RefCell<Box<dyn Write>>,
+    // Where eprint() writes: stderr unless set_error_output says otherwise.
+    error_output: RefCell<Box<dyn Write>>,
+}
+
+// The natives every interpreter starts with, by global name.
+fn natives() -> Vec<(&'static str, Rc<dyn LoxCallable>)> {
+    vec![
+        ("clock", Rc::new(NativeClock {})),
+        ("clockMillis", Rc::new(NativeClockMillis {})),
+        ("exit", Rc::new(NativeExit {})),
+        ("now", Rc::new(NativeNow {})),
+        ("sleep", Rc::new(NativeSleep {})),
+        ("readLine", Rc::new(NativeReadLine {})),
+        ("readFile", Rc::new(NativeReadFile {})),
+        ("writeFile", Rc::new(NativeWriteFile {})),
+        ("eprint", Rc::new(NativeEprint {})),
+        ("write", Rc::new(NativeWrite {})),
+        ("printf", Rc::new(NativePrintf {})),
+        ("equals_ignore_case", Rc::new(NativeEqualsIgnoreCase {})),
+        ("compare_ignore_case", Rc::new(NativeCompareIgnoreCase {})),
+        ("assert", Rc::new(NativeAssert {})),
+        ("assert_eq", Rc::new(NativeAssertEq {})),
+        ("compose", Rc::new(NativeCompose {})),
+        ("sqrt", Rc::new(NativeSqrt {})),
+        ("floor", Rc::new(NativeFloor {})),
+        ("abs", Rc::new(NativeAbs {})),
+        ("pow", Rc::new(NativePow {})),
+        ("random", Rc::new(NativeRandom {})),
+        ("randomInt", Rc::new(NativeRandomInt {})),
+        ("randomSeed", Rc::new(NativeRandomSeed {})),
+        ("len", Rc::new(NativeLen {})),
+        ("push", Rc::new(NativePush {})),
+        ("pop", Rc::new(NativePop {})),
+        ("freeze", Rc::new(NativeFreeze {})),
+        ("keys", Rc::new(NativeKeys {})),
+        ("has", Rc::new(NativeHas {})),
+        ("substring", Rc::new(NativeSubstring {})),
+        ("charAt", Rc::new(NativeCharAt {})),
+        ("toUpper", Rc::new(NativeToUpper {})),
+        ("toLower", Rc::new(NativeToLower {})),
+        ("trim", Rc::new(NativeTrim {})),
+        ("split", Rc::new(NativeSplit {})),
+        ("typeof", Rc::new(NativeTypeOf {})),
+        ("str", Rc::new(NativeStr {})),
+        ("num", Rc::new(NativeNum {})),
+    ]
+}
+
+// A global scope holding only the natives.
+fn builtin_globals() -> Environment {
+    let mut globals = Environment::new();
+    for (name, func) in natives() {
+        globals.define(Symbol::intern(name), Object::Func(Callable { func }));
+    }
+    globals
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        let globals = Rc::new(RefCell::new(Environment::new()));
-        globals.borrow_mut().define(
-            "clock".to_string(),
-            Object::Func(Callable {
-                func: Rc::new(NativeClock {}),
-            }),
-        );
+        Interpreter::with_output(io::stdout())
+    }
+
+    pub fn with_output(writer: impl Write + 'static) -> Self {
+        let globals = Rc::new(RefCell::new(builtin_globals()));
         Self {
             environment: RefCell::new(Rc::clone(&globals)),
             nesting_level: RefCell::new(0),
+            call_stack: RefCell::new(Vec::new()),
+            traceback: RefCell::new(None),
+            max_depth: RefCell::new(DEFAULT_MAX_DEPTH),
+            strict: RefCell::new(false),
+            strict_arithmetic: RefCell::new(false),
+            capabilities: RefCell::new(Capabilities::default()),
+            random_state: RefCell::new(DEFAULT_RANDOM_SEED),
             globals: Rc::clone(&globals),
             locals: RefCell::new(HashMap::new()),
+            spans: RefCell::new(Spans::default()),
+            imported: RefCell::new(HashSet::new()),
+            scripts: RefCell::new(Vec::new()),
+            output: RefCell::new(Box::new(writer)),
+            error_output: RefCell::new(Box::new(io::stderr())),
+        }
+    }
+    // Defines a global function backed by host Rust code. Calls go through the same arity
+    // check as every other callable, so `f` always receives exactly `arity` arguments.
+    pub fn define_native(&self, name: &str, arity: usize, f: NativeFn) {
+        let func = Rc::new(HostNative {
+            name: name.to_string(),
+            arity,
+            function: f,
+        });
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(name), Object::Func(Callable { func }));
+    }
+
+    // Records the file the program being run was read from, so its imports are found relative
+    // to it and an import of the file itself does nothing.
+    pub fn set_script_path(&self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.imported.borrow_mut().insert(path.clone());
+        self.scripts.replace(vec![path]);
+    }
+
+    pub fn set_error_output(&self, writer: impl Write + 'static) {
+        self.error_output.replace(Box::new(writer));
+    }
+
+    // Writes text to the output writer with no newline after it, for write() and printf().
+    // It is flushed at once, since no newline will come along to flush a terminal's buffer.
+    pub fn write_output(&self, text: &str) -> Result<(), LoxResult> {
+        let mut output = self.output.borrow_mut();
+        write!(output, "{}", text)
+            .and_then(|_| output.flush())
+            .map_err(|err| LoxResult::system_error(&format!("could not print: {}", err)))
+    }
+
+    // Writes a line to the error writer, for eprint().
+    pub fn write_error_line(&self, text: &str) -> Result<(), LoxResult> {
+        writeln!(self.error_output.borrow_mut(), "{}", text)
+            .map_err(|err| LoxResult::system_error(&format!("could not print: {}", err)))
+    }
+
+    // The calls, outermost first, that the last error unwound through; None if it was raised
+    // outside any call or has already been taken.
+    pub fn take_traceback(&self) -> Option<Vec<Frame>> {
+        self.traceback.take()
+    }
+
+    // The globals the program has defined, sorted by name, leaving out the built-in natives.
+    pub fn defined_globals(&self) -> Vec<(String, Object)> {
+        let builtins: HashSet<Symbol> = natives()
+            .into_iter()
+            .map(|(name, _)| Symbol::intern(name))
+            .collect();
+        let mut globals: Vec<(String, Object)> = self
+            .globals
+            .borrow()
+            .bindings()
+            .into_iter()
+            .filter(|(name, _)| !builtins.contains(name))
+            .map(|(name, value)| (name.name(), value))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+
+    // Forgets everything the program has done: the globals go back to just the built-in
+    // natives, which drops any added with define_native, and no file counts as imported.
+    // Settings such as strict mode and the output writers are kept.
+    pub fn reset(&self) {
+        *self.globals.borrow_mut() = builtin_globals();
+        self.environment.replace(Rc::clone(&self.globals));
+        self.nesting_level.replace(0);
+        self.call_stack.borrow_mut().clear();
+        self.traceback.replace(None);
+        self.locals.borrow_mut().clear();
+        self.spans.borrow_mut().clear();
+        self.imported.borrow_mut().clear();
+        self.scripts.borrow_mut().clear();
+    }
+
+    // Restarts the random sequence. Seeds are scrambled with splitmix64 so that nearby seeds
+    // give unrelated sequences and no seed leaves the state at zero.
+    pub fn seed_random(&self, seed: u64) {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        self.random_state
+            .replace(if z == 0 { DEFAULT_RANDOM_SEED } else { z });
+    }
+
+    // The next 64 bits from the xorshift64* generator.
+    pub fn next_random(&self) -> u64 {
+        let mut state = self.random_state.borrow_mut();
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn set_max_depth(&self, max_depth: usize) {
+        self.max_depth.replace(max_depth);
+    }
+
+    pub fn set_strict(&self, strict: bool) {
+        self.strict.replace(strict);
+    }
+
+    pub fn is_strict(&self) -> bool {
+        *self.strict.borrow()
+    }
+
+    pub fn set_strict_arithmetic(&self, strict_arithmetic: bool) {
+        self.strict_arithmetic.replace(strict_arithmetic);
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        let interpreter = Interpreter::new();
+        interpreter.set_capabilities(capabilities);
+        interpreter
+    }
+
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        self.capabilities.replace(capabilities);
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        *self.capabilities.borrow()
+    }
+
+    // Natives call this before reaching outside the interpreter; the check happens at call
+    // time, so changing the capabilities affects natives that are already bound.
+    pub fn require(&self, capability: Capability) -> Result<(), LoxResult> {
+        if self.capabilities.borrow().allows(capability) {
+            Ok(())
+        } else {
+            Err(LoxResult::system_error(&format!(
+                "{} is disabled.",
+                capability.description()
+            )))
         }
     }
+
     pub fn evaluate(&self, expr: Rc<Expr>) -> Result<Object, LoxResult> {
         expr.accept(expr.clone(), self)
     }
-    fn is_truthy(&self, object: &Object) -> bool {
+    // The result of an arithmetic operator, checked for overflow and NaN in strict arithmetic.
+    fn arithmetic(&self, operator: &Token, n: f64) -> Result<Object, LoxResult> {
+        if *self.strict_arithmetic.borrow() && !n.is_finite() {
+            return Err(LoxResult::runtime_error(
+                operator,
+                "arithmetic produced a non-finite value",
+            ));
+        }
+        Ok(Object::Number(n))
+    }
+
+    // Bitwise operators work on numbers holding whole values that fit in an i64.
+    fn integer_operand(&self, operator: &Token, operand: &Object) -> Result<i64, LoxResult> {
+        match operand {
+            Object::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(*n as i64),
+            _ => Err(LoxResult::runtime_error(
+                operator,
+                "operand is not an integer",
+            )),
+        }
+    }
+    fn position(
+        &self,
+        bracket: &Token,
+        index: &Object,
+        len: usize,
+        kind: &str,
+    ) -> Result<usize, LoxResult> {
+        checked_index(index, len, kind)
+            .map_err(|message| LoxResult::runtime_error(bracket, &message))
+    }
+
+    fn map_key(&self, bracket: &Token, key: &Object) -> Result<String, LoxResult> {
+        match key {
+            Object::String(key) => Ok(key.clone()),
+            _ => Err(LoxResult::runtime_error(
+                bracket,
+                "Map keys must be strings.",
+            )),
+        }
+    }
+
+    // An error from a list or map, such as a refused mutation, pinned to the brackets that
+    // caused it.
+    fn located(bracket: &Token, err: LoxResult) -> LoxResult {
+        LoxResult::runtime_error(bracket, err.message().unwrap_or_default())
+    }
+
+    // Any error raised in the try block, down through the calls it makes, is caught; break,
+    // continue, return and exit pass through untouched.
+    fn run_try_catch(&self, stmt: &TryStmt) -> Result<(), LoxResult> {
+        let try_env = Environment::new_with_enclosing(self.environment.borrow().clone());
+        let message = match self.execute_block(&stmt.try_body, try_env) {
+            Err(err) if !err.is_control_flow() => err.message().unwrap_or_default().to_string(),
+            result => return result,
+        };
+        // the error was handled, so there is no traceback left to print
+        self.traceback.replace(None);
+        let mut catch_env = Environment::new_with_enclosing(self.environment.borrow().clone());
+        catch_env.define(stmt.error.symbol(), Object::String(message));
+        self.execute_block(&stmt.catch_body, catch_env)
+    }
+
+    fn run_loop(&self, stmt: &WhileStmt) -> Result<(), LoxResult> {
+        // a do-while skips the check before its first pass
+        let mut first_pass = stmt.do_while;
+        while std::mem::take(&mut first_pass)
+            || self.is_truthy(&self.evaluate(stmt.condition.clone())?)
+        {
+            match self.execute_loop_body(&stmt.body) {
+                Ok(()) => (),
+                Err(LoxResult::Continue { label }) if Self::is_target(stmt, &label) => (),
+                Err(LoxResult::Break { label }) if Self::is_target(stmt, &label) => break,
+                Err(err) => return Err(err),
+            }
+            if let Some(increment) = &stmt.increment {
+                self.evaluate(increment.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    // A braced body gets a scope tagged as the loop's own.
+    fn execute_loop_body(&self, body: &Rc<Stmt>) -> Result<(), LoxResult> {
+        match body.deref() {
+            Stmt::Block(block) => {
+                let environment =
+                    Environment::new_with_enclosing(self.environment.borrow().clone())
+                        .with_kind(ScopeKind::Loop);
+                self.execute_block(&block.statements, environment)
+            }
+            _ => self.execute(body.clone()),
+        }
+    }
+
+    // An unlabelled break or continue applies to the innermost loop; a labelled one passes
+    // through loops until it reaches the one with that label.
+    fn is_target(stmt: &WhileStmt, label: &Option<String>) -> bool {
+        match label {
+            None => true,
+            Some(label) => stmt
+                .label
+                .as_ref()
+                .is_some_and(|name| &name.lexeme == label),
+        }
+    }
+
+    // Only nil and false are falsey; 0, "" and empty lists are all truthy.
+    pub fn is_truthy(&self, object: &Object) -> bool {
         !matches!(object, Object::Nil | Object::Bool(false))
     }
-    pub fn interpret(&self, stmt: &[Rc<Stmt>]) -> bool {
-        let mut had_error = false;
-        for statement in stmt {
-            if self.execute(statement.clone()).is_err() {
-                had_error = true;
-                break;
+    // Everything a call goes through, whoever makes it: the arity check, the depth limit and a
+    // frame on the call stack for tracebacks. Errors from these checks point at `paren`.
+    pub fn call_function(
+        &self,
+        function: &Rc<dyn LoxCallable>,
+        arguments: Vec<Object>,
+        paren: &Token,
+    ) -> Result<Object, LoxResult> {
+        if arguments.len() < function.arity() || arguments.len() > function.max_arity() {
+            let expected = if function.arity() == function.max_arity() {
+                function.arity().to_string()
+            } else if function.max_arity() == usize::MAX {
+                format!("at least {}", function.arity())
+            } else {
+                format!("{} to {}", function.arity(), function.max_arity())
+            };
+            return Err(LoxResult::runtime_error(
+                paren,
+                &format!(
+                    "Function '{}' expected {} arguments but got {}.",
+                    function.name(),
+                    expected,
+                    arguments.len()
+                ),
+            ));
+        }
+        if self.call_stack.borrow().len() >= *self.max_depth.borrow() {
+            return Err(LoxResult::runtime_error(paren, "Stack overflow"));
+        }
+        let caller = self.environment.borrow().borrow().function_name();
+        self.call_stack.borrow_mut().push((
+            Frame {
+                function: caller,
+                line: paren.line,
+            },
+            paren.clone(),
+        ));
+        let result = function.call(self, arguments);
+        // an error from a native, or one without a line, is recorded here, by the innermost
+        // call it leaves, with the whole stack still in place
+        if let Err(err) = &result {
+            if !err.is_control_flow() && self.traceback.borrow().is_none() {
+                self.traceback.replace(Some(self.frames()));
             }
         }
-        had_error
+        self.call_stack.borrow_mut().pop();
+        result
+    }
+
+    // The closing parenthesis of the innermost call in progress, for a callable that makes
+    // calls of its own, such as a composed function, to report them at.
+    pub fn call_site(&self) -> Option<Token> {
+        self.call_stack
+            .borrow()
+            .last()
+            .map(|(_, paren)| paren.clone())
+    }
+
+    fn frames(&self) -> Vec<Frame> {
+        self.call_stack
+            .borrow()
+            .iter()
+            .map(|(frame, _)| frame.clone())
+            .collect()
+    }
+
+    // Runs the statements in order, stopping at the first runtime error.
+    pub fn interpret(&self, statements: &[Rc<Stmt>]) -> Result<(), LoxResult> {
+        statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement.clone()))
     }
 
     fn execute(&self, stmt: Rc<Stmt>) -> Result<(), LoxResult> {
@@ -77,23 +569,85 @@ impl Interpreter {
         result
     }
 
-    pub fn resolve(&self, expr: Rc<Expr>, depth: usize) {
-        self.locals.borrow_mut().insert(expr, depth);
+    // A function body starts outside any loop, whatever loop the call happens in, so `break`
+    // can't escape the function.
+    pub fn execute_function_body(
+        &self,
+        statements: &Rc<Vec<Rc<Stmt>>>,
+        environment: Environment,
+    ) -> Result<(), LoxResult> {
+        let outer_nesting = self.nesting_level.replace(0);
+        let function = environment.function_name();
+        let result = self.execute_block(statements, environment);
+        self.nesting_level.replace(outer_nesting);
+        // the body the error was raised in ends the traceback, at the line it points to
+        if let Some(line) = result.as_ref().err().and_then(LoxResult::line) {
+            if self.traceback.borrow().is_none() {
+                let mut frames = self.frames();
+                frames.push(Frame { function, line });
+                self.traceback.replace(Some(frames));
+            }
+        }
+        result
+    }
+
+    // Runs an imported file's statements at the top level, so its declarations become globals
+    // whatever scope the import statement sits in.
+    fn run_import(&self, source: String) -> Result<(), LoxResult> {
+        let stmts = match Lox::scan_and_parse(source, false) {
+            Ok(Some((stmts, spans))) => {
+                self.add_spans(spans);
+                Rc::new(stmts)
+            }
+            Ok(None) => Rc::new(Vec::new()),
+            Err(mut errors) => return Err(errors.swap_remove(0)),
+        };
+        Resolver::new(self).resolve(&stmts)?;
+        let previous = self.environment.replace(Rc::clone(&self.globals));
+        let outer_nesting = self.nesting_level.replace(0);
+        let result = self.interpret(&stmts);
+        self.nesting_level.replace(outer_nesting);
+        self.environment.replace(previous);
+        result
+    }
+
+    pub fn resolve(&self, expr: Rc<Expr>, depth: usize, slot: usize) {
+        self.locals.borrow_mut().insert(expr, (depth, slot));
+    }
+
+    pub fn add_spans(&self, spans: Spans) {
+        self.spans.borrow_mut().extend(spans);
+    }
+
+    pub fn span_of(&self, expr: &Expr) -> Option<Span> {
+        self.spans.borrow().expr(expr)
+    }
+
+    pub fn span_of_stmt(&self, stmt: &Stmt) -> Option<Span> {
+        self.spans.borrow().stmt(stmt)
     }
 
     fn lookup_variable(&self, name: &Token, expr: Rc<Expr>) -> Result<Object, LoxResult> {
-        if let Some(distance) = self.locals.borrow().get(&expr) {
+        let value = if let Some(&(distance, slot)) = self.locals.borrow().get(&expr) {
             self.environment
                 .borrow()
                 .borrow()
-                .get_at(*distance, &name.lexeme)
-        }else{
-            self.globals.borrow().get(name)
+                .lookup_at(distance, slot)?
+        } else {
+            self.globals.borrow().lookup(name)?
+        };
+        match value {
+            Some(value) => Ok(value),
+            None if *self.strict.borrow() => Err(LoxResult::runtime_error(
+                name,
+                &format!("Use of uninitialized variable '{}'", name.lexeme),
+            )),
+            None => Ok(Object::Nil),
         }
     }
 }
 impl StmtVisitor<()> for Interpreter {
-    fn visit_block_stmt(&self, wrapper: Rc<Stmt>, stmt: &BlockStmt) -> Result<(), LoxResult> {
+    fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<(), LoxResult> {
         let new_env = Environment::new_with_enclosing(self.environment.borrow().clone());
         self.execute_block(&stmt.statements, new_env)
     }
@@ -108,19 +662,65 @@ impl StmtVisitor<()> for Interpreter {
         }
     }
 
-    fn visit_expression_stmt(
-        &self,
-        wrapper: Rc<Stmt>,
-        stmt: &ExpressionStmt,
-    ) -> Result<(), LoxResult> {
+    fn visit_expression_stmt(&self, _: Rc<Stmt>, stmt: &ExpressionStmt) -> Result<(), LoxResult> {
         self.evaluate(stmt.expression.clone())?;
         Ok(())
     }
 
-    fn visit_function_stmt(&self, wrapper: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), LoxResult> {
-        let func = LoxFunction::new(stmt, self.environment.borrow().deref());
+    fn visit_class_stmt(&self, _: Rc<Stmt>, stmt: &ClassStmt) -> Result<(), LoxResult> {
+        let superclass = match &stmt.superclass {
+            Some(superclass_expr) => match self.evaluate(superclass_expr.clone())? {
+                Object::Class(superclass) => Some(superclass),
+                _ => {
+                    let token = match superclass_expr.deref() {
+                        Expr::Variable(variable) => &variable.name,
+                        _ => &stmt.name,
+                    };
+                    return Err(LoxResult::runtime_error(
+                        token,
+                        "Superclass must be a class.",
+                    ));
+                }
+            },
+            None => None,
+        };
+
+        // methods of a subclass close over an extra scope that binds `super`
+        let previous = superclass.as_ref().map(|superclass| {
+            let mut environment =
+                Environment::new_with_enclosing(self.environment.borrow().clone());
+            environment.define(
+                Symbol::intern("super"),
+                Object::Class(Rc::clone(superclass)),
+            );
+            self.environment.replace(Rc::new(RefCell::new(environment)))
+        });
+
+        let mut methods = HashMap::new();
+        for method in stmt.methods.iter() {
+            if let Stmt::Function(method) = method.deref() {
+                let is_initializer = method.name.lexeme == "init";
+                let function =
+                    LoxFunction::new(method, self.environment.borrow().deref(), is_initializer);
+                methods.insert(method.name.lexeme.clone(), Rc::new(function));
+            }
+        }
+        if let Some(previous) = previous {
+            self.environment.replace(previous);
+        }
+
+        let class = LoxClass::new(&stmt.name.lexeme, superclass, methods);
+        self.environment
+            .borrow()
+            .borrow_mut()
+            .define(stmt.name.symbol(), Object::Class(Rc::new(class)));
+        Ok(())
+    }
+
+    fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), LoxResult> {
+        let func = LoxFunction::new(stmt, self.environment.borrow().deref(), false);
         self.environment.borrow().borrow_mut().define(
-            stmt.name.lexeme.to_string(),
+            stmt.name.symbol(),
             Object::Func(Callable {
                 func: Rc::new(func),
             }),
@@ -128,23 +728,88 @@ impl StmtVisitor<()> for Interpreter {
         Ok(())
     }
 
-    fn visit_break_stmt(&self, wrapper: Rc<Stmt>, stmt: &BreakStmt) -> Result<(), LoxResult> {
+    fn visit_break_stmt(&self, _: Rc<Stmt>, stmt: &BreakStmt) -> Result<(), LoxResult> {
         if *self.nesting_level.borrow() == 0 {
             return Err(LoxResult::runtime_error(
-                &stmt.token.clone(),
+                &stmt.token,
                 "Can't break outside of loop",
             ));
         }
-        Err(LoxResult::Break)
+        Err(LoxResult::Break {
+            label: stmt.label.as_ref().map(|label| label.lexeme.clone()),
+        })
+    }
+
+    fn visit_continue_stmt(&self, _: Rc<Stmt>, stmt: &ContinueStmt) -> Result<(), LoxResult> {
+        if *self.nesting_level.borrow() == 0 {
+            return Err(LoxResult::runtime_error(
+                &stmt.token,
+                "Can't continue outside of loop",
+            ));
+        }
+        Err(LoxResult::Continue {
+            label: stmt.label.as_ref().map(|label| label.lexeme.clone()),
+        })
+    }
+
+    fn visit_import_stmt(&self, _: Rc<Stmt>, stmt: &ImportStmt) -> Result<(), LoxResult> {
+        let Some(Object::String(relative)) = &stmt.path.literal else {
+            return Err(LoxResult::runtime_error(
+                &stmt.path,
+                "Import path must be a string.",
+            ));
+        };
+        let path = match self
+            .scripts
+            .borrow()
+            .last()
+            .and_then(|script| script.parent())
+        {
+            Some(dir) => dir.join(relative),
+            None => PathBuf::from(relative),
+        };
+        let unreadable = || {
+            LoxResult::runtime_error(
+                &stmt.path,
+                &format!("Could not read imported file '{}'.", path.display()),
+            )
+        };
+        let canonical = path.canonicalize().map_err(|_| unreadable())?;
+        if !self.imported.borrow_mut().insert(canonical.clone()) {
+            return Ok(());
+        }
+        let source = std::fs::read_to_string(&canonical).map_err(|_| unreadable())?;
+
+        self.scripts.borrow_mut().push(canonical);
+        let result = self.run_import(source);
+        self.scripts.borrow_mut().pop();
+        // The error's line and column belong to the imported file, so it is reported at the
+        // import statement with the original diagnostic in the message.
+        result.map_err(|err| {
+            if err.is_control_flow() {
+                return err;
+            }
+            let mut diagnostic = Vec::new();
+            let _ = err.report_to(&mut diagnostic, "");
+            LoxResult::runtime_error(
+                &stmt.path,
+                &format!(
+                    "Error in imported file '{}': {}",
+                    path.display(),
+                    String::from_utf8_lossy(&diagnostic).trim_end()
+                ),
+            )
+        })
     }
 
-    fn visit_print_stmt(&self, wrapper: Rc<Stmt>, stmt: &PrintStmt) -> Result<(), LoxResult> {
+    fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<(), LoxResult> {
         let value = self.evaluate(stmt.expression.clone())?;
-        println!("{:?}", value.to_string());
+        writeln!(self.output.borrow_mut(), "{}", value)
+            .map_err(|err| LoxResult::system_error(&format!("could not print: {}", err)))?;
         Ok(())
     }
 
-    fn visit_return_stmt(&self, wrapper: Rc<Stmt>, stmt: &ReturnStmt) -> Result<(), LoxResult> {
+    fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<(), LoxResult> {
         if let Some(value) = &stmt.value {
             Err(LoxResult::return_value(self.evaluate(value.clone())?))
         } else {
@@ -153,39 +818,79 @@ impl StmtVisitor<()> for Interpreter {
     }
 
     fn visit_var_stmt(&self, _: Rc<Stmt>, stmt: &VarStmt) -> Result<(), LoxResult> {
-        let value = if let Some(initializer) = stmt.initializer.clone() {
-            self.evaluate(initializer)?
-        } else {
-            Object::Nil
+        match stmt.initializer.clone() {
+            Some(initializer) => {
+                let value = self.evaluate(initializer)?;
+                let environment = self.environment.borrow();
+                let mut environment = environment.borrow_mut();
+                if stmt.is_const {
+                    environment.define_constant(stmt.name.symbol(), value);
+                } else {
+                    environment.define(stmt.name.symbol(), value);
+                }
+            }
+            None => self
+                .environment
+                .borrow()
+                .borrow_mut()
+                .define_uninitialized(stmt.name.symbol()),
+        }
+        Ok(())
+    }
+
+    // The finally block runs however the try and catch blocks end, and what it does wins: an
+    // error, return or break in it replaces whatever the try or catch blocks were passing on.
+    fn visit_try_stmt(&self, _: Rc<Stmt>, stmt: &TryStmt) -> Result<(), LoxResult> {
+        let result = self.run_try_catch(stmt);
+        let Some(finally_body) = &stmt.finally_body else {
+            return result;
         };
+        // a pending error keeps its traceback unless the finally block raises one of its own
+        let traceback = self.traceback.take();
+        let finally_env = Environment::new_with_enclosing(self.environment.borrow().clone());
+        self.execute_block(finally_body, finally_env)?;
+        self.traceback.replace(traceback);
+        result
+    }
 
-        self.environment
-            .borrow()
-            .borrow_mut()
-            .define(stmt.name.lexeme.to_string(), value);
-        Ok(())
+    fn visit_multivar_stmt(&self, _: Rc<Stmt>, stmt: &MultiVarStmt) -> Result<(), LoxResult> {
+        stmt.declarations
+            .iter()
+            .try_for_each(|declaration| self.execute(declaration.clone()))
     }
 
-    fn visit_while_stmt(&self, wrapper: Rc<Stmt>, stmt: &WhileStmt) -> Result<(), LoxResult> {
+    fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<(), LoxResult> {
+        // The count must come back down however the loop ends: normally, by a break or return
+        // aimed further out, or by a runtime error.
         *self.nesting_level.borrow_mut() += 1;
-        while self.is_truthy(&self.evaluate(stmt.condition.clone())?) {
-            self.execute(stmt.body.clone())?;
-        }
+        let result = self.run_loop(stmt);
         *self.nesting_level.borrow_mut() -= 1;
-        Ok(())
+        result
     }
 }
 
 impl ExprVisitor<Object> for Interpreter {
+    fn visit_array_expr(&self, _: Rc<Expr>, expr: &ArrayExpr) -> Result<Object, LoxResult> {
+        let mut elements = Vec::new();
+        for element in expr.elements.iter() {
+            elements.push(self.evaluate(element.clone())?);
+        }
+        Ok(Object::List(Rc::new(Freezable::new(elements))))
+    }
+
     fn visit_assign_expr(&self, wrapper: Rc<Expr>, expr: &AssignExpr) -> Result<Object, LoxResult> {
         let value = self.evaluate(expr.value.clone())?;
-        if let Some(distance) = self.locals.borrow().get(&wrapper) {
-            self.environment
-                .borrow()
+        if let Some(&(distance, slot)) = self.locals.borrow().get(&wrapper) {
+            self.environment.borrow().borrow_mut().assign_at(
+                distance,
+                slot,
+                &expr.name,
+                value.clone(),
+            )?;
+        } else {
+            self.globals
                 .borrow_mut()
-                .assign_at(*distance, &expr.name, value.clone())?;
-        }else{
-            self.globals.borrow_mut().assign(&expr.name, value.clone())?;
+                .assign(&expr.name, value.clone())?;
         }
         Ok(value)
     }
@@ -195,19 +900,23 @@ impl ExprVisitor<Object> for Interpreter {
         let right = self.evaluate(expr.right.clone())?;
         match expr.operator.ttype {
             TokenType::Minus => match (left, right) {
-                (Object::Number(n1), Object::Number(n2)) => Ok(Object::Number(n1 - n2)),
+                (Object::Number(n1), Object::Number(n2)) => {
+                    self.arithmetic(&expr.operator, n1 - n2)
+                }
                 _ => Err(LoxResult::new(
                     expr.operator.line,
                     "invalid expression: operands must be two numbers",
                 )),
             },
             TokenType::Plus => match (left, right) {
-                (Object::Number(n1), Object::Number(n2)) => Ok(Object::Number(n1 + n2)),
+                (Object::Number(n1), Object::Number(n2)) => {
+                    self.arithmetic(&expr.operator, n1 + n2)
+                }
                 (Object::String(s1), Object::Number(n2)) => {
-                    Ok(Object::String(format!("{}{}", s1, n2)))
+                    Ok(Object::String(format!("{}{}", s1, format_number(n2))))
                 }
                 (Object::Number(n1), Object::String(s2)) => {
-                    Ok(Object::String(format!("{}{}", n1, s2)))
+                    Ok(Object::String(format!("{}{}", format_number(n1), s2)))
                 }
                 (Object::String(s1), Object::String(s2)) => {
                     Ok(Object::String(format!("{}{}", s1, s2)))
@@ -222,7 +931,7 @@ impl ExprVisitor<Object> for Interpreter {
                     if n2 == 0.0 {
                         Err(LoxResult::new(expr.operator.line, "division by zero"))
                     } else {
-                        Ok(Object::Number(n1 / n2))
+                        self.arithmetic(&expr.operator, n1 / n2)
                     }
                 }
                 _ => Err(LoxResult::new(
@@ -230,15 +939,34 @@ impl ExprVisitor<Object> for Interpreter {
                     "invalid expression:operands must be numbers",
                 )),
             },
+            // `7 div 2` is 3: division rounded down, so `-7 div 2` is -4
+            TokenType::Div => match (left, right) {
+                (Object::Number(n1), Object::Number(n2)) => {
+                    if n2 == 0.0 {
+                        Err(LoxResult::runtime_error(&expr.operator, "division by zero"))
+                    } else {
+                        self.arithmetic(&expr.operator, (n1 / n2).floor())
+                    }
+                }
+                _ => Err(LoxResult::runtime_error(
+                    &expr.operator,
+                    "operands must be numbers",
+                )),
+            },
             TokenType::Star => match (left, right) {
-                (Object::Number(n1), Object::Number(n2)) => Ok(Object::Number(n1 * n2)),
+                (Object::Number(n1), Object::Number(n2)) => {
+                    self.arithmetic(&expr.operator, n1 * n2)
+                }
                 _ => Err(LoxResult::new(
                     expr.operator.line,
                     "invalid expression:operands must be numbers",
                 )),
             },
 
-            TokenType::Greater => {
+            TokenType::Greater
+            | TokenType::Less
+            | TokenType::GreaterEqual
+            | TokenType::LessEqual => {
                 // if object are not of equal type return err
                 if left.get_type() != right.get_type() {
                     return Err(LoxResult::new(
@@ -246,83 +974,236 @@ impl ExprVisitor<Object> for Interpreter {
                         "invalid expression:operands are different types",
                     ));
                 }
-                Ok(Object::Bool(left > right))
-            }
-            TokenType::Less => {
-                if left.get_type() != right.get_type() {
-                    return Err(LoxResult::new(
-                        expr.operator.line,
-                        "invalid expression:operands are different types",
-                    ));
-                }
-                Ok(Object::Bool(left < right))
-            }
-            TokenType::GreaterEqual => {
-                if left.get_type() != right.get_type() {
-                    return Err(LoxResult::new(
-                        expr.operator.line,
-                        "invalid expression:operands are different types",
-                    ));
-                }
-                Ok(Object::Bool(left >= right))
-            }
-            TokenType::LessEqual => {
-                if left.get_type() != right.get_type() {
-                    return Err(LoxResult::new(
-                        expr.operator.line,
-                        "invalid expression:operands are different types",
-                    ));
-                }
-                Ok(Object::Bool(left <= right))
-            }
-            TokenType::BangEqual => {
-                if left.get_type() != right.get_type() {
-                    return Err(LoxResult::new(
-                        expr.operator.line,
-                        "invalid expression:operands are different types",
+                // Functions, classes, instances, lists and maps have no order. NaN doesn't
+                // either, but comparing it is simply false, as in IEEE arithmetic.
+                let numbers = matches!((&left, &right), (Object::Number(_), Object::Number(_)));
+                if !numbers && left.partial_cmp(&right).is_none() {
+                    return Err(LoxResult::runtime_error(
+                        &expr.operator,
+                        &format!("operands are not comparable: {}", left.get_type()),
                     ));
                 }
-                Ok(Object::Bool(left != right))
+                Ok(Object::Bool(match expr.operator.ttype {
+                    TokenType::Greater => left > right,
+                    TokenType::Less => left < right,
+                    TokenType::GreaterEqual => left >= right,
+                    _ => left <= right,
+                }))
             }
-            TokenType::EqualEqual => {
-                if left.get_type() != right.get_type() {
-                    return Err(LoxResult::new(
-                        expr.operator.line,
-                        "invalid expression:operands are different types",
-                    ));
-                }
-                Ok(Object::Bool(left == right))
+            // Values of different types are never equal.
+            TokenType::BangEqual => Ok(Object::Bool(!left.lox_equals(&right))),
+            TokenType::EqualEqual => Ok(Object::Bool(left.lox_equals(&right))),
+            TokenType::Ampersand
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::LessLess
+            | TokenType::GreaterGreater => {
+                let (l, r) = (
+                    self.integer_operand(&expr.operator, &left)?,
+                    self.integer_operand(&expr.operator, &right)?,
+                );
+                let result = match expr.operator.ttype {
+                    TokenType::Ampersand => l & r,
+                    TokenType::Pipe => l | r,
+                    TokenType::Caret => l ^ r,
+                    _ => {
+                        let shift = u32::try_from(r)
+                            .ok()
+                            .filter(|shift| *shift < i64::BITS)
+                            .ok_or_else(|| {
+                                LoxResult::runtime_error(
+                                    &expr.operator,
+                                    "shift amount out of range",
+                                )
+                            })?;
+                        if expr.operator.ttype == TokenType::LessLess {
+                            l << shift
+                        } else {
+                            l >> shift
+                        }
+                    }
+                };
+                Ok(Object::Number(result as f64))
             }
             _ => Err(LoxResult::runtime_error(&expr.operator, "invalid operator")),
         }
     }
 
-    fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<Object, LoxResult> {
+    fn visit_comma_expr(&self, _: Rc<Expr>, expr: &CommaExpr) -> Result<Object, LoxResult> {
+        self.evaluate(expr.left.clone())?;
+        self.evaluate(expr.right.clone())
+    }
+
+    fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<Object, LoxResult> {
         let callee = self.evaluate(expr.callee.clone())?;
         let mut arguments = Vec::new();
         for arg in &expr.arguments {
             arguments.push(self.evaluate(arg.clone())?);
         }
-        if let Object::Func(function) = callee {
-            if arguments.len() != function.arity() {
+        let function: Rc<dyn LoxCallable> = match callee {
+            Object::Func(function) => function.func,
+            Object::Class(class) => class,
+            _ => {
                 return Err(LoxResult::runtime_error(
                     &expr.paren,
+                    "can only call functions and classes",
+                ))
+            }
+        };
+        self.call_function(&function, arguments, &expr.paren)
+    }
+
+    // A missing map key reads as nil.
+    fn visit_index_expr(&self, _: Rc<Expr>, expr: &IndexExpr) -> Result<Object, LoxResult> {
+        let object = self.evaluate(expr.object.clone())?;
+        let index = self.evaluate(expr.index.clone())?;
+        match object {
+            Object::List(list) => {
+                let list = list.borrow();
+                let position = self.position(&expr.bracket, &index, list.len(), "list")?;
+                Ok(list[position].clone())
+            }
+            Object::Map(map) => {
+                let key = self.map_key(&expr.bracket, &index)?;
+                Ok(map.borrow().get(&key).cloned().unwrap_or(Object::Nil))
+            }
+            // Strings index by character, giving a one-character string.
+            Object::String(string) => {
+                let chars: Vec<char> = string.chars().collect();
+                let position = self.position(&expr.bracket, &index, chars.len(), "string")?;
+                Ok(Object::String(chars[position].to_string()))
+            }
+            _ => Err(LoxResult::runtime_error(
+                &expr.bracket,
+                "Only lists, maps and strings can be indexed.",
+            )),
+        }
+    }
+
+    fn visit_indexset_expr(&self, _: Rc<Expr>, expr: &IndexSetExpr) -> Result<Object, LoxResult> {
+        let object = self.evaluate(expr.object.clone())?;
+        let index = self.evaluate(expr.index.clone())?;
+        let value = self.evaluate(expr.value.clone())?;
+        match object {
+            Object::List(list) => {
+                let position = self.position(&expr.bracket, &index, list.borrow().len(), "list")?;
+                list.mutate()
+                    .map_err(|err| Self::located(&expr.bracket, err))?[position] = value.clone();
+            }
+            Object::Map(map) => {
+                let key = self.map_key(&expr.bracket, &index)?;
+                map.mutate()
+                    .map_err(|err| Self::located(&expr.bracket, err))?
+                    .insert(key, value.clone());
+            }
+            Object::String(_) => {
+                return Err(LoxResult::runtime_error(
+                    &expr.bracket,
+                    "Strings can't be modified.",
+                ))
+            }
+            _ => {
+                return Err(LoxResult::runtime_error(
+                    &expr.bracket,
+                    "Only lists and maps can be indexed.",
+                ))
+            }
+        }
+        Ok(value)
+    }
+
+    fn visit_map_expr(&self, _: Rc<Expr>, expr: &MapExpr) -> Result<Object, LoxResult> {
+        let mut map = HashMap::new();
+        for (key, value) in expr.keys.iter().zip(expr.values.iter()) {
+            let key = self.evaluate(key.clone())?;
+            let key = self.map_key(&expr.brace, &key)?;
+            map.insert(key, self.evaluate(value.clone())?);
+        }
+        Ok(Object::Map(Rc::new(Freezable::new(map))))
+    }
+
+    fn visit_function_expr(&self, _: Rc<Expr>, expr: &FunctionExpr) -> Result<Object, LoxResult> {
+        let func = LoxFunction::new(&expr.declaration, self.environment.borrow().deref(), false);
+        Ok(Object::Func(Callable {
+            func: Rc::new(func),
+        }))
+    }
+
+    fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<Object, LoxResult> {
+        match self.evaluate(expr.object.clone())? {
+            Object::Instance(instance) => LoxInstance::get(&instance, &expr.name),
+            other => builtin_member(&other, &expr.name.lexeme).ok_or_else(|| {
+                LoxResult::runtime_error(
+                    &expr.name,
                     &format!(
-                        "expected {} arguments but got {}",
-                        function.arity(),
-                        arguments.len()
+                        "unknown property '{}' on {}",
+                        expr.name.lexeme,
+                        other.get_type()
                     ),
-                ));
+                )
+            }),
+        }
+    }
+
+    fn visit_set_expr(&self, _: Rc<Expr>, expr: &SetExpr) -> Result<Object, LoxResult> {
+        let instance = match self.evaluate(expr.object.clone())? {
+            Object::Instance(instance) => instance,
+            _ => {
+                return Err(LoxResult::runtime_error(
+                    &expr.name,
+                    "Only instances have fields.",
+                ))
             }
-            function.func.call(self, arguments)
-        } else {
-            Err(LoxResult::runtime_error(
-                &expr.paren,
-                "can only call functions and classes",
-            ))
+        };
+        let value = self.evaluate(expr.value.clone())?;
+        instance.borrow_mut().set(&expr.name, value.clone());
+        Ok(value)
+    }
+
+    fn visit_super_expr(&self, wrapper: Rc<Expr>, expr: &SuperExpr) -> Result<Object, LoxResult> {
+        let (distance, slot) = *self.locals.borrow().get(&wrapper).unwrap();
+        let environment = self.environment.borrow();
+        let superclass = match environment.borrow().get_at(distance, slot)? {
+            Object::Class(superclass) => superclass,
+            _ => {
+                return Err(LoxResult::runtime_error(
+                    &expr.keyword,
+                    "Superclass must be a class.",
+                ))
+            }
+        };
+        // `this` is always alone in the scope just inside the one binding `super`
+        let object = environment.borrow().get_at(distance - 1, 0)?;
+        match superclass.find_method(&expr.method.lexeme) {
+            Some(method) => Ok(Object::Func(Callable {
+                func: Rc::new(method.bind(&object)),
+            })),
+            None => Err(LoxResult::runtime_error(
+                &expr.method,
+                &format!("Undefined property '{}'.", expr.method.lexeme),
+            )),
         }
     }
 
+    fn visit_this_expr(&self, wrapper: Rc<Expr>, expr: &ThisExpr) -> Result<Object, LoxResult> {
+        self.lookup_variable(&expr.keyword, wrapper)
+    }
+
+    fn visit_block_expr(&self, _: Rc<Expr>, expr: &BlockExpr) -> Result<Object, LoxResult> {
+        let environment = Environment::new_with_enclosing(self.environment.borrow().clone());
+        let previous = self.environment.replace(Rc::new(RefCell::new(environment)));
+        let result = expr
+            .statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement.clone()))
+            .and_then(|_| match &expr.value {
+                Some(value) => self.evaluate(value.clone()),
+                None => Ok(Object::Nil),
+            });
+        self.environment.replace(previous);
+        result
+    }
+
     fn visit_grouping_expr(&self, _: Rc<Expr>, expr: &GroupingExpr) -> Result<Object, LoxResult> {
         self.evaluate(expr.expression.clone())
     }
@@ -330,13 +1211,17 @@ impl ExprVisitor<Object> for Interpreter {
         Ok(expr.value.clone().unwrap())
     }
 
+    // `or` and `and` return one of their operands rather than a bool, and only evaluate the
+    // right one when the left doesn't already decide the result.
     fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<Object, LoxResult> {
         let left = self.evaluate(expr.left.clone())?;
-        if expr.operator.ttype == TokenType::Or {
-            if self.is_truthy(&left) {
-                return Ok(left);
-            }
-        } else if !self.is_truthy(&left) {
+        let short_circuits = match expr.operator.ttype {
+            TokenType::Or => self.is_truthy(&left),
+            // unlike `or`, only nil falls through to the right: false and 0 are kept
+            TokenType::QuestionQuestion => left != Object::Nil,
+            _ => !self.is_truthy(&left),
+        };
+        if short_circuits {
             return Ok(left);
         }
         self.evaluate(expr.right.clone())
@@ -358,7 +1243,11 @@ impl ExprVisitor<Object> for Interpreter {
         }
     }
 
-    fn visit_variable_expr(&self, wrapper: Rc<Expr>, expr: &VariableExpr) -> Result<Object, LoxResult> {
+    fn visit_variable_expr(
+        &self,
+        wrapper: Rc<Expr>,
+        expr: &VariableExpr,
+    ) -> Result<Object, LoxResult> {
         self.lookup_variable(&expr.name, wrapper)
     }
 }
@@ -366,16 +1255,70 @@ impl ExprVisitor<Object> for Interpreter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
     use crate::token::Token;
 
     fn make_literal(o: Object) -> Rc<Expr> {
         Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: Some(o) })))
     }
 
+    fn run_source(interpreter: &Interpreter, source: &str) -> Result<(), LoxResult> {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens()?;
+        let mut parser = Parser::new(tokens);
+        let statements = Rc::new(parser.parse()?);
+        if !parser.success() {
+            return Err(LoxResult::GenericError {
+                line: 0,
+                message: "could not interpret".to_string(),
+            });
+        }
+        Resolver::new(interpreter).resolve(&statements)?;
+        statements
+            .iter()
+            .try_for_each(|statement| interpreter.execute(statement.clone()))
+    }
+
+    // A writer the test can still read after the interpreter has taken it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> Object {
+        interpreter
+            .globals
+            .borrow()
+            .get(&Token::new(
+                TokenType::Identifier,
+                name.to_string(),
+                None,
+                1,
+                1,
+            ))
+            .unwrap()
+    }
+
     #[test]
     fn test_unary_minus() {
         let expr = UnaryExpr {
-            operator: Token::new(TokenType::Minus, "-".to_string(), None, 1),
+            operator: Token::new(TokenType::Minus, "-".to_string(), None, 1, 1),
             right: make_literal(Object::Number(4.0)),
         };
         let interpreter = Interpreter::new();
@@ -389,7 +1332,7 @@ mod tests {
     #[test]
     fn test_unary_bang() {
         let expr = UnaryExpr {
-            operator: Token::new(TokenType::Bang, "!".to_string(), None, 1),
+            operator: Token::new(TokenType::Bang, "!".to_string(), None, 1, 1),
             right: make_literal(Object::Bool(true)),
         };
         let interpreter = Interpreter::new();
@@ -405,7 +1348,7 @@ mod tests {
     fn test_binary_subtraction() {
         let binary_expr = BinaryExpr {
             left: make_literal(Object::Number(4.0)),
-            operator: Token::new(TokenType::Minus, "-".to_string(), None, 1),
+            operator: Token::new(TokenType::Minus, "-".to_string(), None, 1, 1),
             right: make_literal(Object::Number(3.0)),
         };
 
@@ -421,7 +1364,7 @@ mod tests {
     fn test_binary_addition() {
         let binary_expr = BinaryExpr {
             left: make_literal(Object::Number(4.0)),
-            operator: Token::new(TokenType::Plus, "+".to_string(), None, 1),
+            operator: Token::new(TokenType::Plus, "+".to_string(), None, 1, 1),
             right: make_literal(Object::Number(3.0)),
         };
 
@@ -440,7 +1383,7 @@ mod tests {
             left: Rc::new(Expr::Literal(Rc::new(LiteralExpr {
                 value: Some(Object::String("hello".to_string())),
             }))),
-            operator: Token::new(TokenType::Plus, "+".to_string(), None, 1),
+            operator: Token::new(TokenType::Plus, "+".to_string(), None, 1, 1),
             right: Rc::new(Expr::Literal(Rc::new(LiteralExpr {
                 value: Some(Object::String("world".to_string())),
             }))),
@@ -459,7 +1402,7 @@ mod tests {
     fn test_binary_slash() {
         let binary_expr = BinaryExpr {
             left: make_literal(Object::Number(4.0)),
-            operator: Token::new(TokenType::Slash, "/".to_string(), None, 1),
+            operator: Token::new(TokenType::Slash, "/".to_string(), None, 1, 1),
             right: make_literal(Object::Number(2.0)),
         };
 
@@ -475,7 +1418,7 @@ mod tests {
     fn test_binary_slash_zero() {
         let binary_expr = BinaryExpr {
             left: make_literal(Object::Number(4.0)),
-            operator: Token::new(TokenType::Slash, "/".to_string(), None, 1),
+            operator: Token::new(TokenType::Slash, "/".to_string(), None, 1, 1),
             right: make_literal(Object::Number(0.0)),
         };
 
@@ -491,7 +1434,7 @@ mod tests {
     fn test_binary_star() {
         let binary_expr = BinaryExpr {
             left: make_literal(Object::Number(4.0)),
-            operator: Token::new(TokenType::Star, "*".to_string(), None, 1),
+            operator: Token::new(TokenType::Star, "*".to_string(), None, 1, 1),
             right: make_literal(Object::Number(2.0)),
         };
 
@@ -508,7 +1451,7 @@ mod tests {
     fn test_binary_greater_lesser_greater_equal() {
         let mut binary_expr = BinaryExpr {
             left: make_literal(Object::Number(4.0)),
-            operator: Token::new(TokenType::Greater, ">".to_string(), None, 1),
+            operator: Token::new(TokenType::Greater, ">".to_string(), None, 1, 1),
             right: make_literal(Object::Number(2.0)),
         };
 
@@ -520,7 +1463,7 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), Object::Bool(true));
 
-        binary_expr.operator = Token::new(TokenType::Less, "<".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::Less, "<".to_string(), None, 1, 1);
 
         let result1 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -529,7 +1472,7 @@ mod tests {
         assert!(result1.is_ok());
         assert_eq!(result1.unwrap(), Object::Bool(false));
 
-        binary_expr.operator = Token::new(TokenType::GreaterEqual, ">=".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::GreaterEqual, ">=".to_string(), None, 1, 1);
 
         let result2 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -538,7 +1481,7 @@ mod tests {
         assert!(result2.is_ok());
         assert_eq!(result2.unwrap(), Object::Bool(true));
 
-        binary_expr.operator = Token::new(TokenType::LessEqual, "<=".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::LessEqual, "<=".to_string(), None, 1, 1);
 
         let result3 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -547,7 +1490,7 @@ mod tests {
         assert!(result3.is_ok());
         assert_eq!(result3.unwrap(), Object::Bool(false));
 
-        binary_expr.operator = Token::new(TokenType::EqualEqual, "==".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::EqualEqual, "==".to_string(), None, 1, 1);
 
         let result4 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -556,7 +1499,7 @@ mod tests {
         assert!(result4.is_ok());
         assert_eq!(result4.unwrap(), Object::Bool(false));
 
-        binary_expr.operator = Token::new(TokenType::BangEqual, "!=".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::BangEqual, "!=".to_string(), None, 1, 1);
 
         let result5 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -571,7 +1514,7 @@ mod tests {
             left: Rc::new(Expr::Literal(Rc::new(LiteralExpr {
                 value: Some(Object::String("def".to_string())),
             }))),
-            operator: Token::new(TokenType::Greater, ">".to_string(), None, 1),
+            operator: Token::new(TokenType::Greater, ">".to_string(), None, 1, 1),
             right: Rc::new(Expr::Literal(Rc::new(LiteralExpr {
                 value: Some(Object::String("abc".to_string())),
             }))),
@@ -585,7 +1528,7 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), Object::Bool(true));
 
-        binary_expr.operator = Token::new(TokenType::Less, "<".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::Less, "<".to_string(), None, 1, 1);
 
         let result1 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -594,7 +1537,7 @@ mod tests {
         assert!(result1.is_ok());
         assert_eq!(result1.unwrap(), Object::Bool(false));
 
-        binary_expr.operator = Token::new(TokenType::GreaterEqual, ">=".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::GreaterEqual, ">=".to_string(), None, 1, 1);
 
         let result2 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -603,7 +1546,7 @@ mod tests {
         assert!(result2.is_ok());
         assert_eq!(result2.unwrap(), Object::Bool(true));
 
-        binary_expr.operator = Token::new(TokenType::LessEqual, "<=".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::LessEqual, "<=".to_string(), None, 1, 1);
 
         let result3 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -612,7 +1555,7 @@ mod tests {
         assert!(result3.is_ok());
         assert_eq!(result3.unwrap(), Object::Bool(false));
 
-        binary_expr.operator = Token::new(TokenType::EqualEqual, "==".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::EqualEqual, "==".to_string(), None, 1, 1);
 
         let result4 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -621,7 +1564,7 @@ mod tests {
         assert!(result4.is_ok());
         assert_eq!(result4.unwrap(), Object::Bool(false));
 
-        binary_expr.operator = Token::new(TokenType::BangEqual, "!=".to_string(), None, 1);
+        binary_expr.operator = Token::new(TokenType::BangEqual, "!=".to_string(), None, 1, 1);
 
         let result5 = interpreter.visit_binary_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -634,7 +1577,7 @@ mod tests {
     fn test_binary_nil() {
         let binary_expr = BinaryExpr {
             left: make_literal(Object::Nil),
-            operator: Token::new(TokenType::EqualEqual, "==".to_string(), None, 1),
+            operator: Token::new(TokenType::EqualEqual, "==".to_string(), None, 1, 1),
             right: make_literal(Object::Nil),
         };
 
@@ -650,7 +1593,7 @@ mod tests {
     fn test_binary_error_case() {
         let binary_expr = BinaryExpr {
             left: make_literal(Object::Number(4.0)),
-            operator: Token::new(TokenType::Greater, ">".to_string(), None, 1),
+            operator: Token::new(TokenType::Greater, ">".to_string(), None, 1, 1),
             right: make_literal(Object::Bool(true)),
         };
 
@@ -666,10 +1609,16 @@ mod tests {
     fn test_var_statement() {
         let interpreter = Interpreter::new();
         let var_stmt = VarStmt {
-            name: Token::new(TokenType::Identifier, "a".to_string(), None, 1),
+            name: Token::new(TokenType::Identifier, "a".to_string(), None, 1, 1),
             initializer: Some(make_literal(Object::Number(4.0))),
+            is_const: false,
         };
-        let result = interpreter.visit_var_stmt(Rc::new(Stmt::Block(Rc::new(BlockStmt { statements: Rc::new(vec![]) }))), &var_stmt);
+        let result = interpreter.visit_var_stmt(
+            Rc::new(Stmt::Block(Rc::new(BlockStmt {
+                statements: Rc::new(vec![]),
+            }))),
+            &var_stmt,
+        );
         assert!(result.is_ok());
         let val = interpreter
             .environment
@@ -682,7 +1631,7 @@ mod tests {
     fn test_var_expr_undefined() {
         let interpreter = Interpreter::new();
         let var_expr = VariableExpr {
-            name: Token::new(TokenType::Identifier, "a".to_string(), None, 1),
+            name: Token::new(TokenType::Identifier, "a".to_string(), None, 1, 1),
         };
         let val = interpreter.visit_variable_expr(
             Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: None }))),
@@ -690,4 +1639,1646 @@ mod tests {
         );
         assert!(val.is_err())
     }
+
+    #[test]
+    fn assert_eq_accepts_an_optional_message() {
+        let interpreter = Interpreter::new();
+        let result = run_source(
+            &interpreter,
+            "assert_eq(1 + 2, 3); assert_eq(\"a\", \"a\", \"same\"); var done = true;",
+        );
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "done"), Object::Bool(true));
+    }
+
+    #[test]
+    fn assert_eq_rejects_wrong_argument_count() {
+        let interpreter = Interpreter::new();
+        match run_source(&interpreter, "assert_eq(1);") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(
+                    message,
+                    "Function 'assert_eq' expected 2 to 3 arguments but got 1."
+                )
+            }
+            _ => panic!("expected an arity error"),
+        }
+    }
+
+    #[test]
+    fn nested_function_cannot_redeclare_parameter_in_function_scope() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun f(x) {
+                fun x() { return \"fn\"; }
+            }
+        ";
+        assert!(run_source(&interpreter, source).is_err());
+    }
+
+    #[test]
+    fn block_declarations_shadow_parameter_only_inside_block() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var inner_fun;
+            var inner_var;
+            var outer;
+            fun f(x) {
+                {
+                    fun x() { return \"fn\"; }
+                    inner_fun = x();
+                }
+                {
+                    var x = 2;
+                    inner_var = x;
+                }
+                outer = x;
+            }
+            f(1);
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "inner_fun"),
+            Object::String("fn".to_string())
+        );
+        assert_eq!(global(&interpreter, "inner_var"), Object::Number(2.0));
+        assert_eq!(global(&interpreter, "outer"), Object::Number(1.0));
+    }
+
+    #[test]
+    fn closure_captures_variable_in_scope_at_declaration() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var a = \"global\";
+            var seen = \"\";
+            {
+                fun show() { seen = seen + a + \" \"; }
+                show();
+                var a = \"block\";
+                show();
+            }
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "seen"),
+            Object::String("global global ".to_string())
+        );
+    }
+
+    #[test]
+    fn closure_keeps_captured_local_after_function_returns() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun make_counter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+            var counter = make_counter();
+            counter();
+            var second = counter();
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "second"), Object::Number(2.0));
+    }
+
+    #[test]
+    fn compose_applies_second_function_first() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun increment(x) { return x + 1; }
+            fun double(x) { return x * 2; }
+            var result = compose(increment, double)(5);
+            var reversed = compose(double, increment)(5);
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "result"), Object::Number(11.0));
+        assert_eq!(global(&interpreter, "reversed"), Object::Number(12.0));
+    }
+
+    #[test]
+    fn compose_rejects_non_unary_callables() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun add(a, b) { return a + b; }
+            fun increment(x) { return x + 1; }
+            compose(add, increment);
+        ";
+        assert!(run_source(&interpreter, source).is_err());
+        assert!(run_source(&interpreter, "compose(1, increment);").is_err());
+    }
+
+    #[test]
+    fn composed_calls_are_checked_like_any_other() {
+        let interpreter = Interpreter::new();
+        interpreter.set_max_depth(50);
+        let source = "
+            var looped;
+            fun again(x) { return looped(x); }
+            looped = compose(again, again);
+            looped(1);
+        ";
+        match run_source(&interpreter, source) {
+            Err(LoxResult::RuntimeError { message, .. }) => assert_eq!(message, "Stack overflow"),
+            other => panic!("expected the depth limit, got {:?}", other),
+        }
+        interpreter.take_traceback();
+
+        let source = "
+            fun fail(x) {
+                return x + nil;
+            }
+            fun id(x) { return x; }
+            compose(id, fail)(1);
+        ";
+        assert!(run_source(&interpreter, source).is_err());
+        let frames = interpreter.take_traceback().unwrap();
+        let calls: Vec<(&str, usize)> = frames
+            .iter()
+            .map(|frame| (frame.function.as_str(), frame.line))
+            .collect();
+        assert_eq!(calls, vec![("<script>", 6), ("<script>", 6), ("fail", 3)]);
+        assert!(interpreter.call_stack.borrow().is_empty());
+    }
+
+    #[test]
+    fn class_constructs_instance_with_fields() {
+        let interpreter = Interpreter::new();
+        let source = "
+            class Point {}
+            var p = Point();
+            p.x = 1;
+            p.y = p.x + 1;
+            var x = p.x;
+            var y = p.y;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "x"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "y"), Object::Number(2.0));
+        assert_eq!(global(&interpreter, "p").to_string(), "Point instance");
+        assert_eq!(global(&interpreter, "Point").to_string(), "Point");
+    }
+
+    #[test]
+    fn methods_bind_this_and_initializer_sets_fields() {
+        let interpreter = Interpreter::new();
+        let source = "
+            class Counter {
+                init(start) {
+                    this.count = start;
+                }
+                increment() {
+                    this.count = this.count + 1;
+                    return this;
+                }
+            }
+            var counter = Counter(10);
+            var bump = counter.increment;
+            bump();
+            var count = counter.increment().count;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "count"), Object::Number(12.0));
+    }
+
+    #[test]
+    fn initializer_arity_is_checked_and_init_returns_instance() {
+        let interpreter = Interpreter::new();
+        let source = "
+            class Box {
+                init(value) {
+                    this.value = value;
+                    return;
+                }
+            }
+            var b = Box(1);
+            var again = b.init(2);
+            var same = again == b;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "same"), Object::Bool(true));
+        assert!(run_source(&interpreter, "Box();").is_err());
+    }
+
+    #[test]
+    fn reading_undefined_property_is_an_error() {
+        let interpreter = Interpreter::new();
+        assert!(run_source(&interpreter, "class A {} A().missing;").is_err());
+        assert!(run_source(&interpreter, "var n = 1; n.field = 2;").is_err());
+    }
+
+    #[test]
+    fn subclass_inherits_and_calls_super_method() {
+        let interpreter = Interpreter::new();
+        let source = "
+            class A {
+                method() { return \"A\"; }
+                inherited() { return \"inherited\"; }
+            }
+            class B < A {
+                method() { return \"B then \" + super.method(); }
+            }
+            var overridden = B().method();
+            var inherited = B().inherited();
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "overridden"),
+            Object::String("B then A".to_string())
+        );
+        assert_eq!(
+            global(&interpreter, "inherited"),
+            Object::String("inherited".to_string())
+        );
+    }
+
+    #[test]
+    fn super_binds_this_to_current_instance() {
+        let interpreter = Interpreter::new();
+        let source = "
+            class Base {
+                init(name) { this.name = name; }
+                describe() { return \"I am \" + this.name; }
+            }
+            class Derived < Base {
+                init(name) { super.init(name + \"!\"); }
+                describe() { return super.describe(); }
+            }
+            var description = Derived(\"d\").describe();
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "description"),
+            Object::String("I am d!".to_string())
+        );
+    }
+
+    #[test]
+    fn superclass_must_be_a_class() {
+        let interpreter = Interpreter::new();
+        match run_source(&interpreter, "var NotAClass = 1; class B < NotAClass {}") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Superclass must be a class.")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn print_formats_values_without_quotes() {
+        let interpreter = Interpreter::new();
+        let source = "
+            class Point {}
+            fun f() {}
+            var s = \"hello\";
+            var n = 42;
+            var fraction = 2.5;
+            var b = true;
+            var nothing = nil;
+            var klass = Point;
+            var instance = Point();
+            var func = f;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        let printed = |name: &str| format!("{}", global(&interpreter, name));
+        assert_eq!(printed("s"), "hello");
+        assert_eq!(printed("n"), "42");
+        assert_eq!(printed("fraction"), "2.5");
+        assert_eq!(printed("b"), "true");
+        assert_eq!(printed("nothing"), "nil");
+        assert_eq!(printed("klass"), "Point");
+        assert_eq!(printed("instance"), "Point instance");
+        assert_eq!(printed("func"), "<fn f>");
+    }
+
+    #[test]
+    fn equality_across_types_is_false() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var number_vs_string = 1 == \"1\";
+            var nil_vs_false = nil == false;
+            var bool_vs_number = true != 1;
+            var nil_vs_nil = nil == nil;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "number_vs_string"),
+            Object::Bool(false)
+        );
+        assert_eq!(global(&interpreter, "nil_vs_false"), Object::Bool(false));
+        assert_eq!(global(&interpreter, "bool_vs_number"), Object::Bool(true));
+        assert_eq!(global(&interpreter, "nil_vs_nil"), Object::Bool(true));
+    }
+
+    #[test]
+    fn break_does_not_cross_function_boundary() {
+        let interpreter = Interpreter::new();
+        let source = "
+            while (true) {
+                fun escape() { break; }
+                escape();
+            }
+        ";
+        match run_source(&interpreter, source) {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Can't break outside of loop")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn bitwise_operators_work_on_integers() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var masked = 6 & 3;
+            var shifted = 1 << 4;
+            var mixed = 1 | 6 ^ 3 & 2 >> 1;
+            var negative = -8 >> 1;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "masked"), Object::Number(2.0));
+        assert_eq!(global(&interpreter, "shifted"), Object::Number(16.0));
+        assert_eq!(global(&interpreter, "mixed"), Object::Number(7.0));
+        assert_eq!(global(&interpreter, "negative"), Object::Number(-4.0));
+    }
+
+    #[test]
+    fn bitwise_operators_reject_fractions() {
+        let interpreter = Interpreter::new();
+        match run_source(&interpreter, "var x = 3.5 & 1;") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "operand is not an integer")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+        match run_source(&interpreter, "var y = 1 << 64;") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "shift amount out of range")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn host_natives_are_callable_from_scripts() {
+        let interpreter = Interpreter::new();
+        interpreter.define_native(
+            "double",
+            1,
+            Rc::new(|args: &[Object]| match args[0] {
+                Object::Number(n) => Ok(Object::Number(n * 2.0)),
+                _ => Err(LoxResult::system_error("double expects a number")),
+            }),
+        );
+        assert!(run_source(&interpreter, "var result = double(21);").is_ok());
+        assert_eq!(global(&interpreter, "result"), Object::Number(42.0));
+        match run_source(&interpreter, "double(1, 2);") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Function 'double' expected 1 arguments but got 2.")
+            }
+            _ => panic!("expected an arity error"),
+        }
+    }
+
+    #[test]
+    fn lists_support_indexing_and_mutation() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var list = [1, \"two\", 3];
+            var second = list[1];
+            list[0] = 10;
+            push(list, 4);
+            var last = pop(list);
+            push(list, [5]);
+            var size = len(list);
+            var shown = \"\" + list[3][0];
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "second"),
+            Object::String("two".to_string())
+        );
+        assert_eq!(global(&interpreter, "last"), Object::Number(4.0));
+        assert_eq!(global(&interpreter, "size"), Object::Number(4.0));
+        assert_eq!(
+            global(&interpreter, "list").to_string(),
+            "[10, \"two\", 3, [5]]"
+        );
+    }
+
+    #[test]
+    fn list_index_errors_name_the_index() {
+        let interpreter = Interpreter::new();
+        match run_source(&interpreter, "var l = [1, 2, 3]; print l[3];") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Index 3 out of bounds for list of length 3.")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+        match run_source(&interpreter, "var m = [1]; m[-1] = 0;") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Index -1 out of bounds for list of length 1.")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+        match run_source(&interpreter, "var n = 1; print n[0];") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Only lists, maps and strings can be indexed.")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn maps_support_lookup_insert_and_overwrite() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var m = {\"a\": 1, \"b\": 2};
+            var a = m[\"a\"];
+            m[\"c\"] = 3;
+            m[\"a\"] = 10;
+            var missing = m[\"zzz\"];
+            var names = keys(m);
+            var has_c = has(m, \"c\");
+            var has_d = has(m, \"d\");
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "a"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "missing"), Object::Nil);
+        assert_eq!(
+            global(&interpreter, "names").to_string(),
+            "[\"a\", \"b\", \"c\"]"
+        );
+        assert_eq!(global(&interpreter, "has_c"), Object::Bool(true));
+        assert_eq!(global(&interpreter, "has_d"), Object::Bool(false));
+        assert_eq!(
+            global(&interpreter, "m").to_string(),
+            "{\"a\": 10, \"b\": 2, \"c\": 3}"
+        );
+    }
+
+    #[test]
+    fn map_keys_must_be_strings() {
+        let interpreter = Interpreter::new();
+        match run_source(&interpreter, "var m = {}; m[1] = 2;") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Map keys must be strings.")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn unbounded_recursion_is_a_runtime_error() {
+        // the default depth needs more than a test thread's stack, as in main
+        let test = || {
+            let interpreter = Interpreter::new();
+            match run_source(&interpreter, "fun f() { f(); } f();") {
+                Err(LoxResult::RuntimeError { message, .. }) => {
+                    assert_eq!(message, "Stack overflow")
+                }
+                _ => panic!("expected a stack overflow error"),
+            }
+            // the depth unwinds with the error, so later calls still work
+            assert!(run_source(&interpreter, "fun g(n) { return n; } var x = g(1);").is_ok());
+        };
+        std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn max_depth_is_configurable() {
+        let interpreter = Interpreter::new();
+        interpreter.set_max_depth(10);
+        let source = "fun count(n) { if (n == 0) return 0; return 1 + count(n - 1); }";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert!(run_source(&interpreter, "var nine = count(9);").is_ok());
+        assert!(run_source(&interpreter, "var eleven = count(11);").is_err());
+    }
+
+    #[test]
+    fn lambdas_are_values() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var add = fun (a, b) { return a + b; };
+            var sum = add(1, 2);
+            fun apply(f, x) { return f(x); }
+            var offset = 10;
+            var shifted = apply(fun (x) { return x + offset; }, 5);
+            fun named() { return \"still works\"; }
+            var plain = named();
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "sum"), Object::Number(3.0));
+        assert_eq!(global(&interpreter, "shifted"), Object::Number(15.0));
+        assert_eq!(
+            global(&interpreter, "plain"),
+            Object::String("still works".to_string())
+        );
+    }
+
+    #[test]
+    fn strings_index_by_character() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var e = \"hello\"[1];
+            var accent = \"héllo\"[1];
+            var middle = substring(\"hello\", 1, 3);
+            var last = charAt(\"héllo\", 4);
+            var size = len(\"héllo\");
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "e"), Object::String("e".to_string()));
+        assert_eq!(
+            global(&interpreter, "accent"),
+            Object::String("é".to_string())
+        );
+        assert_eq!(
+            global(&interpreter, "middle"),
+            Object::String("el".to_string())
+        );
+        assert_eq!(
+            global(&interpreter, "last"),
+            Object::String("o".to_string())
+        );
+        assert_eq!(global(&interpreter, "size"), Object::Number(5.0));
+    }
+
+    #[test]
+    fn string_index_errors_name_index_and_length() {
+        let interpreter = Interpreter::new();
+        match run_source(&interpreter, "print \"hello\"[5];") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Index 5 out of bounds for string of length 5.")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+        match run_source(&interpreter, "var s = \"abc\"; s[0] = \"x\";") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Strings can't be modified.")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn return_is_not_reported_as_an_error() {
+        let interpreter = Interpreter::new();
+        let stmt = Rc::new(Stmt::Return(Rc::new(ReturnStmt {
+            token: Token::new(TokenType::Return, "return".to_string(), None, 1, 1),
+            value: Some(make_literal(Object::Number(1.0))),
+        })));
+        let signal = interpreter.execute(stmt).unwrap_err();
+        assert!(signal.is_control_flow());
+        let mut stderr = Vec::new();
+        signal.report_to(&mut stderr, "").unwrap();
+        assert!(stderr.is_empty());
+    }
+
+    #[test]
+    fn continue_still_runs_the_for_increment() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var iterations = 0;
+            var odd = 0;
+            for (var i = 0; i < 10; i = i + 1) {
+                iterations = iterations + 1;
+                if (i & 1 == 0) continue;
+                odd = odd + 1;
+            }
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "iterations"), Object::Number(10.0));
+        assert_eq!(global(&interpreter, "odd"), Object::Number(5.0));
+    }
+
+    #[test]
+    fn break_leaves_while_and_for_loops() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var w = 0;
+            while (true) {
+                w = w + 1;
+                if (w == 3) break;
+            }
+            var f = 0;
+            for (var i = 0; i < 10; i = i + 1) {
+                if (i == 4) break;
+                f = f + i;
+            }
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "w"), Object::Number(3.0));
+        assert_eq!(global(&interpreter, "f"), Object::Number(6.0));
+    }
+
+    #[test]
+    fn break_only_exits_the_innermost_loop() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var trail = \"\";
+            for (var i = 0; i < 3; i = i + 1) {
+                while (true) {
+                    trail = trail + \"w\";
+                    break;
+                    trail = trail + \"x\";
+                }
+                trail = trail + str(i);
+            }
+            var after = true;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "trail"),
+            Object::String("w0w1w2".to_string())
+        );
+        assert_eq!(global(&interpreter, "after"), Object::Bool(true));
+    }
+
+    #[test]
+    fn functions_return_nil_unless_given_a_value() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun nothing() { var x = 1; }
+            fun bare() { while (true) { { return; } } }
+            fun five() { if (true) { return 5; } }
+            var shown = str(nothing());
+            var none = nothing();
+            var early = bare();
+            var value = five();
+            var sum = five() + 1;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "shown"),
+            Object::String("nil".to_string())
+        );
+        assert_eq!(global(&interpreter, "none"), Object::Nil);
+        assert_eq!(global(&interpreter, "early"), Object::Nil);
+        assert_eq!(global(&interpreter, "value"), Object::Number(5.0));
+        assert_eq!(global(&interpreter, "sum"), Object::Number(6.0));
+    }
+
+    #[test]
+    fn postfix_increments_yield_the_old_value() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var i = 0;
+            i++;
+            var before = i++;
+            var j = 10;
+            var down = j--;
+            j--;
+            fun counter() {
+                var n = 0;
+                fun next() { return n++; }
+                return next;
+            }
+            var next = counter();
+            next();
+            var second = next();
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "i"), Object::Number(2.0));
+        assert_eq!(global(&interpreter, "before"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "down"), Object::Number(10.0));
+        assert_eq!(global(&interpreter, "j"), Object::Number(8.0));
+        assert_eq!(global(&interpreter, "second"), Object::Number(1.0));
+    }
+
+    #[test]
+    fn strings_are_quoted_inside_containers_and_nil_prints_alike_everywhere() {
+        let output = SharedBuffer::default();
+        let interpreter = Interpreter::with_output(output.clone());
+        let source = "print nil; print [nil]; print {\"k\": nil}; print \"a\"; \
+                      print [\"a\", nil, {\"k\": nil}]; print [\"say \\\"hi\\\"\\n\"];";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            output.contents(),
+            "nil\n[nil]\n{\"k\": nil}\na\n[\"a\", nil, {\"k\": nil}]\n[\"say \\\"hi\\\"\\n\"]\n"
+        );
+    }
+
+    #[test]
+    fn a_function_in_a_map_on_an_instance_can_be_called_through_a_chain() {
+        let interpreter = Interpreter::new();
+        let source = "
+            class Calculator {}
+            fun add(a, b) { return a + b; }
+            var calc = Calculator();
+            calc.methods = {\"add\": add, \"mul\": fun (a, b) { return a * b; }};
+            var sum = calc.methods[\"add\"](2, 3);
+            var product = calc.methods[\"mul\"](4, 5);
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "sum"), Object::Number(5.0));
+        assert_eq!(global(&interpreter, "product"), Object::Number(20.0));
+    }
+
+    #[test]
+    fn print_writes_to_the_output_writer() {
+        let output = SharedBuffer::default();
+        let interpreter = Interpreter::with_output(output.clone());
+        assert!(run_source(&interpreter, "print 1; print \"hi\";").is_ok());
+        assert_eq!(output.contents(), "1\nhi\n");
+    }
+
+    #[test]
+    fn coalesce_replaces_only_nil() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var from_nil = nil ?? 5;
+            var from_false = false ?? 5;
+            var from_zero = 0 ?? 5;
+            var chained = nil ?? nil ?? \"last\";
+            var calls = 0;
+            fun fallback() { calls = calls + 1; return 1; }
+            var kept = 2 ?? fallback();
+            var used = nil ?? fallback();
+            var loose = false or nil ?? 3;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "from_nil"), Object::Number(5.0));
+        assert_eq!(global(&interpreter, "from_false"), Object::Bool(false));
+        assert_eq!(global(&interpreter, "from_zero"), Object::Number(0.0));
+        assert_eq!(
+            global(&interpreter, "chained"),
+            Object::String("last".to_string())
+        );
+        assert_eq!(global(&interpreter, "kept"), Object::Number(2.0));
+        assert_eq!(global(&interpreter, "used"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "calls"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "loose"), Object::Number(3.0));
+    }
+
+    #[test]
+    fn div_rounds_the_quotient_down() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var whole = 7 div 2;
+            var negative = -7 div 2;
+            var fraction = 7.5 div 0.5;
+            var precedence = 1 + 9 div 2 * 2;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "whole"), Object::Number(3.0));
+        assert_eq!(global(&interpreter, "negative"), Object::Number(-4.0));
+        assert_eq!(global(&interpreter, "fraction"), Object::Number(15.0));
+        assert_eq!(global(&interpreter, "precedence"), Object::Number(9.0));
+        match run_source(&interpreter, "1 div 0;") {
+            Err(LoxResult::RuntimeError { token, message }) => {
+                assert_eq!(token.lexeme, "div");
+                assert_eq!(message, "division by zero");
+            }
+            _ => panic!("expected a division by zero error"),
+        }
+    }
+
+    #[test]
+    fn eprint_writes_to_the_error_writer() {
+        let output = SharedBuffer::default();
+        let errors = SharedBuffer::default();
+        let interpreter = Interpreter::with_output(output.clone());
+        interpreter.set_error_output(errors.clone());
+        let source = "var result = eprint(\"oops\"); eprint([1, 2]); print \"fine\";";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(errors.contents(), "oops\n[1, 2]\n");
+        assert_eq!(output.contents(), "fine\n");
+        assert_eq!(global(&interpreter, "result"), Object::Nil);
+    }
+
+    #[test]
+    fn strict_mode_rejects_reading_uninitialized_variables() {
+        let interpreter = Interpreter::new();
+        assert!(run_source(&interpreter, "var loose; var copy = loose;").is_ok());
+        assert_eq!(global(&interpreter, "copy"), Object::Nil);
+
+        interpreter.set_strict(true);
+        for source in ["var x; print x;", "{ var y; print y; }"] {
+            match run_source(&interpreter, source) {
+                Err(LoxResult::RuntimeError { message, .. }) => {
+                    assert!(message.starts_with("Use of uninitialized variable"))
+                }
+                _ => panic!("expected {:?} to fail in strict mode", source),
+            }
+        }
+        let source = "
+            var explicit = nil;
+            var later;
+            later = 2;
+            var read = explicit;
+            { var inner; inner = 3; read = inner + later; }
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "read"), Object::Number(5.0));
+    }
+
+    #[test]
+    fn comma_evaluates_both_sides_and_yields_the_right() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var value = (1, 2);
+            var log = \"\";
+            fun note(s) { log = log + s; return s; }
+            var last = (note(\"a\"), note(\"b\"), note(\"c\"));
+            var j = 10;
+            for (var i = 0; i < 3; i++, j--) {}
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "value"), Object::Number(2.0));
+        assert_eq!(
+            global(&interpreter, "log"),
+            Object::String("abc".to_string())
+        );
+        assert_eq!(
+            global(&interpreter, "last"),
+            Object::String("c".to_string())
+        );
+        assert_eq!(global(&interpreter, "j"), Object::Number(7.0));
+    }
+
+    #[test]
+    fn errors_record_the_calls_they_unwind_through() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun b() {
+                return nil + 1;
+            }
+            fun a() {
+                return b();
+            }
+            a();
+        ";
+        assert!(run_source(&interpreter, source).is_err());
+        let frames = interpreter.take_traceback().unwrap();
+        let calls: Vec<(&str, usize)> = frames
+            .iter()
+            .map(|frame| (frame.function.as_str(), frame.line))
+            .collect();
+        assert_eq!(calls, vec![("<script>", 8), ("a", 6), ("b", 3)]);
+        assert!(interpreter.call_stack.borrow().is_empty());
+        assert_eq!(interpreter.take_traceback(), None);
+
+        // an error outside any call has no traceback
+        assert!(run_source(&interpreter, "nil + 1;").is_err());
+        assert_eq!(interpreter.take_traceback(), None);
+    }
+
+    #[test]
+    fn not_is_an_alias_for_bang() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var done = false;
+            var a = not true;
+            var b = not not nil;
+            var c = not done and !false;
+            var d = not 1 == false;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "a"), Object::Bool(false));
+        assert_eq!(global(&interpreter, "b"), Object::Bool(false));
+        assert_eq!(global(&interpreter, "c"), Object::Bool(true));
+        assert_eq!(global(&interpreter, "d"), Object::Bool(true));
+    }
+
+    #[test]
+    fn rest_parameter_collects_the_remaining_arguments() {
+        let interpreter = Interpreter::new();
+        let source = "fun sum(...nums) { return nums; }
+            var all = sum(1, 2, 3);
+            var none = sum();
+            fun tail(first, ...rest) { return rest; }
+            var after = tail(1, 2, 3);";
+        assert!(run_source(&interpreter, source).is_ok());
+        let list = |name: &str| match global(&interpreter, name) {
+            Object::List(list) => list.borrow().clone(),
+            other => panic!("expected a list, got {:?}", other),
+        };
+        assert_eq!(
+            list("all"),
+            vec![
+                Object::Number(1.0),
+                Object::Number(2.0),
+                Object::Number(3.0)
+            ]
+        );
+        assert!(list("none").is_empty());
+        assert_eq!(
+            list("after"),
+            vec![Object::Number(2.0), Object::Number(3.0)]
+        );
+        match run_source(&interpreter, "tail();") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(
+                    message,
+                    "Function 'tail' expected at least 1 arguments but got 0."
+                )
+            }
+            _ => panic!("expected an arity error"),
+        }
+    }
+
+    #[test]
+    fn do_while_runs_its_body_before_checking_the_condition() {
+        let interpreter = Interpreter::new();
+        let source = "var once = 0;
+            do once = once + 1; while (false);
+            var i = 0;
+            var odd = 0;
+            do {
+                i = i + 1;
+                if (i > 7) break;
+                if (i - (i div 2) * 2 == 0) continue;
+                odd = odd + 1;
+            } while (true);";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "once"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "i"), Object::Number(8.0));
+        assert_eq!(global(&interpreter, "odd"), Object::Number(4.0));
+        assert_eq!(*interpreter.nesting_level.borrow(), 0);
+    }
+
+    #[test]
+    fn constants_are_readable_but_not_assignable() {
+        let interpreter = Interpreter::new();
+        let source = "const PI = 3;
+            var area = PI * 2 * 2;
+            fun local() { const E = 2.5; return E + 1; }
+            var e = local();";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "area"), Object::Number(12.0));
+        assert_eq!(global(&interpreter, "e"), Object::Number(3.5));
+        for source in [
+            "PI = 3;",
+            "{ const inner = 1; inner = 2; }",
+            "fun f() { const c = 1; fun g() { c = 2; } g(); } f();",
+        ] {
+            match run_source(&interpreter, source) {
+                Err(LoxResult::RuntimeError { message, .. }) => {
+                    assert!(
+                        message.starts_with("Cannot assign to constant"),
+                        "{}",
+                        message
+                    )
+                }
+                _ => panic!("expected {:?} to fail", source),
+            }
+        }
+        assert_eq!(global(&interpreter, "PI"), Object::Number(3.0));
+    }
+
+    #[test]
+    fn strict_arithmetic_rejects_non_finite_results() {
+        let interpreter = Interpreter::new();
+        let source = "var big = 1e308 * 10; var gap = big - big;";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "big"), Object::Number(f64::INFINITY));
+        assert!(matches!(global(&interpreter, "gap"), Object::Number(n) if n.is_nan()));
+
+        interpreter.set_strict_arithmetic(true);
+        for source in [
+            "1e308 * 10;",
+            "1e308 + 1e308;",
+            "-1e308 - 1e308;",
+            "1e308 / 1e-308;",
+        ] {
+            match run_source(&interpreter, source) {
+                Err(LoxResult::RuntimeError { message, .. }) => {
+                    assert_eq!(message, "arithmetic produced a non-finite value")
+                }
+                _ => panic!("expected {:?} to fail with strict arithmetic", source),
+            }
+        }
+        assert!(run_source(&interpreter, "var fine = 1e300 * 10;").is_ok());
+    }
+
+    #[test]
+    fn strings_and_lists_have_built_in_members() {
+        let interpreter = Interpreter::new();
+        let source = "var size = \"abc\".length;
+            var accented = \"héllo\".length;
+            var shout = \"abc\".upper();
+            var numbers = [];
+            numbers.push(1);
+            var push = numbers.push;
+            push(2);
+            var count = numbers.length;
+            var last = numbers.pop();";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "size"), Object::Number(3.0));
+        assert_eq!(global(&interpreter, "accented"), Object::Number(5.0));
+        assert_eq!(
+            global(&interpreter, "shout"),
+            Object::String("ABC".to_string())
+        );
+        assert_eq!(global(&interpreter, "count"), Object::Number(2.0));
+        assert_eq!(global(&interpreter, "last"), Object::Number(2.0));
+        for (source, expected) in [
+            ("\"abc\".nope;", "unknown property 'nope' on string"),
+            ("numbers.upper;", "unknown property 'upper' on list"),
+            ("(1).length;", "unknown property 'length' on number"),
+        ] {
+            match run_source(&interpreter, source) {
+                Err(LoxResult::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                _ => panic!("expected {:?} to fail", source),
+            }
+        }
+    }
+
+    #[test]
+    fn for_clauses_can_each_be_left_out() {
+        let interpreter = Interpreter::new();
+        let source = "var forever = 0;
+            for (;;) { forever = forever + 1; if (forever == 3) break; }
+            var stopped = -1;
+            for (var i = 0;; i = i + 1) { if (i == 4) { stopped = i; break; } }
+            var outside = 0;
+            for (; outside < 3;) outside = outside + 1;
+            var stepped = 0;
+            for (;; stepped = stepped + 1) { if (stepped > 5) break; continue; }";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "forever"), Object::Number(3.0));
+        assert_eq!(global(&interpreter, "stopped"), Object::Number(4.0));
+        assert_eq!(global(&interpreter, "outside"), Object::Number(3.0));
+        assert_eq!(global(&interpreter, "stepped"), Object::Number(6.0));
+    }
+
+    #[test]
+    fn arity_errors_name_the_callee() {
+        let interpreter = Interpreter::new();
+        let message = |source: &str| match run_source(&interpreter, source) {
+            Err(LoxResult::RuntimeError { message, .. }) => message,
+            _ => panic!("expected an arity error"),
+        };
+        assert_eq!(
+            message("fun foo(a, b) {} foo(1, 2, 3);"),
+            "Function 'foo' expected 2 arguments but got 3."
+        );
+        assert_eq!(
+            message("var f = fun (a) {}; f();"),
+            "Function '<anonymous>' expected 1 arguments but got 0."
+        );
+        assert_eq!(
+            message("fun anonymous(a) { return a; } anonymous();"),
+            "Function 'anonymous' expected 1 arguments but got 0."
+        );
+        assert_eq!(
+            message("clock(1);"),
+            "Function 'clock' expected 0 arguments but got 1."
+        );
+    }
+
+    #[test]
+    fn one_var_statement_declares_several_variables() {
+        let interpreter = Interpreter::new();
+        let source = "var a = 1, b = a + 1, c; var sum; { var x = 10, y = x * 2; sum = x + y; }";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "a"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "b"), Object::Number(2.0));
+        assert_eq!(global(&interpreter, "c"), Object::Nil);
+        assert_eq!(global(&interpreter, "sum"), Object::Number(30.0));
+    }
+
+    #[test]
+    fn try_catches_a_runtime_error() {
+        let interpreter = Interpreter::new();
+        let source = "var message; var after = false; \
+                      try { var x = 1 div 0; after = true; } catch (e) { message = e; }";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "message"),
+            Object::String("division by zero".to_string())
+        );
+        assert_eq!(global(&interpreter, "after"), Object::Bool(false));
+
+        // an error deep in a call is caught too, and leaves no traceback behind
+        let source = "fun fail() { return nil + 1; } var caught = false; \
+                      try { fail(); } catch (e) { caught = true; }";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "caught"), Object::Bool(true));
+        assert_eq!(interpreter.take_traceback(), None);
+        assert!(interpreter.call_stack.borrow().is_empty());
+    }
+
+    #[test]
+    fn try_lets_control_flow_through() {
+        let interpreter = Interpreter::new();
+        let source = "fun early() { try { return 1; } catch (e) { return 2; } } \
+                      var result = early(); var count = 0; \
+                      while (true) { try { count = count + 1; break; } catch (e) { count = -1; } }";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "result"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "count"), Object::Number(1.0));
+        // an error in the catch block is not caught by that same try
+        assert!(run_source(&interpreter, "try { 1 div 0; } catch (e) { nil + 1; }").is_err());
+    }
+
+    #[test]
+    fn a_return_in_try_still_runs_finally() {
+        let interpreter = Interpreter::new();
+        let source = "var log = \"\"; \
+                      fun early() { try { return 1; } catch (e) { return 2; } finally { log = log + \"f\"; } } \
+                      var result = early(); \
+                      try { 1 div 0; } catch (e) { log = log + \"c\"; } finally { log = log + \"f\"; }";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "result"), Object::Number(1.0));
+        assert_eq!(
+            global(&interpreter, "log"),
+            Object::String("fcf".to_string())
+        );
+    }
+
+    #[test]
+    fn finally_overrides_a_pending_return() {
+        let interpreter = Interpreter::new();
+        let source = "fun early() { try { return 1; } catch (e) {} finally { return 2; } } \
+                      var result = early();";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "result"), Object::Number(2.0));
+
+        // an error raised in finally supersedes the return the try block was making
+        let source = "fun fails() { try { return 1; } catch (e) {} finally { nil + 1; } } fails();";
+        assert!(run_source(&interpreter, source).is_err());
+    }
+
+    #[test]
+    fn a_traceback_names_the_function_an_error_was_raised_in() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun tally(items) {
+                var total = 0;
+                for (var i = 0; i < 3; i = i + 1) {
+                    { total = total + items[i]; }
+                }
+                return total;
+            }
+            tally([1, nil, 3]);
+        ";
+        assert!(run_source(&interpreter, source).is_err());
+        let frames = interpreter.take_traceback().unwrap();
+        // the error is two scopes deep inside the loop, but the function's scope names it
+        assert_eq!(frames.last().unwrap().function, "tally");
+        assert_eq!(frames.last().unwrap().line, 5);
+
+        assert!(run_source(&interpreter, "var f = fun () { nil + 1; }; f();").is_err());
+        let frames = interpreter.take_traceback().unwrap();
+        assert_eq!(frames.last().unwrap().function, "<anonymous>");
+    }
+
+    #[test]
+    fn a_frozen_value_can_be_read_but_not_changed() {
+        let interpreter = Interpreter::new();
+        let source = "var xs = freeze([1, 2]); var m = {\"a\": 1}; freeze(m); \
+                      var first = xs[0]; var size = len(xs); var a = m[\"a\"];";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "first"), Object::Number(1.0));
+        assert_eq!(global(&interpreter, "size"), Object::Number(2.0));
+        assert_eq!(global(&interpreter, "a"), Object::Number(1.0));
+
+        let cases = [
+            "push(xs, 3);",
+            "xs.push(3);",
+            "pop(xs);",
+            "xs[0] = 5;",
+            "m[\"b\"] = 2;",
+        ];
+        for source in cases {
+            match run_source(&interpreter, source) {
+                Err(LoxResult::RuntimeError { message, .. })
+                | Err(LoxResult::SystemError { message }) => {
+                    assert_eq!(message, "Cannot mutate frozen value.", "for {}", source)
+                }
+                other => panic!("expected {} to be refused, got {:?}", source, other),
+            }
+        }
+        assert_eq!(global(&interpreter, "xs").to_string(), "[1, 2]");
+        assert!(run_source(&interpreter, "freeze(1);").is_err());
+    }
+
+    #[test]
+    fn a_locked_down_interpreter_only_computes() {
+        let interpreter = Interpreter::with_capabilities(Capabilities::none());
+        let cases = [
+            ("clock();", "clock access is disabled."),
+            ("now();", "clock access is disabled."),
+            ("readFile(\"notes.txt\");", "file I/O is disabled."),
+            ("exit(0);", "exit is disabled."),
+        ];
+        for (source, expected) in cases {
+            match run_source(&interpreter, source) {
+                Err(LoxResult::SystemError { message }) => assert_eq!(message, expected),
+                other => panic!("expected {} to be refused, got {:?}", source, other),
+            }
+        }
+        assert!(run_source(&interpreter, "var total = len([1, 2]) + sqrt(9);").is_ok());
+        assert_eq!(global(&interpreter, "total"), Object::Number(5.0));
+
+        // turning a capability back on takes effect for natives already bound
+        interpreter.set_capabilities(Capabilities {
+            allow_clock: true,
+            ..Capabilities::none()
+        });
+        assert!(run_source(&interpreter, "clock();").is_ok());
+        assert_eq!(Interpreter::new().capabilities(), Capabilities::default());
+    }
+
+    #[test]
+    fn lists_and_maps_compare_by_contents() {
+        let interpreter = Interpreter::new();
+        let source = "var pair = [1, 2] == [1, 2]; \
+                      var longer = [1, 2] == [1, 2, 3]; \
+                      var nested = [1, [2, [3]]] == [1, [2, [3]]]; \
+                      var unlike = [1, [2, [3]]] != [1, [2, [4]]]; \
+                      var maps = {\"a\": 1, \"b\": [2]} == {\"b\": [2], \"a\": 1}; \
+                      var missing = {\"a\": 1} == {\"b\": 1}; \
+                      fun f() {} var functions = [f] == [f] and f != fun () {};";
+        assert!(run_source(&interpreter, source).is_ok());
+        for (name, expected) in [
+            ("pair", true),
+            ("longer", false),
+            ("nested", true),
+            ("unlike", true),
+            ("maps", true),
+            ("missing", false),
+            ("functions", true),
+        ] {
+            assert_eq!(
+                global(&interpreter, name),
+                Object::Bool(expected),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn comparing_self_referential_lists_terminates() {
+        let interpreter = Interpreter::new();
+        let source = "var a = [1]; push(a, a); var b = [1]; push(b, b); var c = [2]; push(c, c); \
+                      var same = a == b; var itself = a == a; var different = a == c;";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "same"), Object::Bool(true));
+        assert_eq!(global(&interpreter, "itself"), Object::Bool(true));
+        assert_eq!(global(&interpreter, "different"), Object::Bool(false));
+    }
+
+    #[test]
+    fn write_and_printf_add_no_newline() {
+        let output = SharedBuffer::default();
+        let interpreter = Interpreter::with_output(output.clone());
+        let source = "write(\"a\"); write(\"b\"); var result = printf(\"{}+{}={}\", 1, 2, 3); \
+                      write([nil]); printf(\"!\");";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(output.contents(), "ab1+2=3[nil]!");
+        assert_eq!(global(&interpreter, "result"), Object::Nil);
+
+        for source in ["printf(\"{} {}\", 1);", "printf(\"{}\", 1, 2);"] {
+            match run_source(&interpreter, source) {
+                Err(LoxResult::SystemError { message }) => {
+                    assert!(message.starts_with("printf format has "), "{}", message)
+                }
+                other => panic!("expected a placeholder mismatch, got {:?}", other),
+            }
+        }
+        assert_eq!(output.contents(), "ab1+2=3[nil]!");
+    }
+
+    #[test]
+    fn block_expressions_are_worth_their_final_expression() {
+        let interpreter = Interpreter::new();
+        let source = "fun compute() { return 21; } \
+                      var doubled = { var x = compute(); x * 2 }; \
+                      var nested = { var a = 1; { var b = 2; a + b } * 10 }; \
+                      var empty = { var unused = 1; }; \
+                      var x = \"outer\"; var inner = { var x = \"inner\"; x };";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "doubled"), Object::Number(42.0));
+        assert_eq!(global(&interpreter, "nested"), Object::Number(30.0));
+        assert_eq!(global(&interpreter, "empty"), Object::Nil);
+        assert_eq!(
+            global(&interpreter, "inner"),
+            Object::String("inner".to_string())
+        );
+        assert_eq!(
+            global(&interpreter, "x"),
+            Object::String("outer".to_string())
+        );
+        // names declared in the block are gone after it
+        assert!(run_source(
+            &interpreter,
+            "var leak = { var hidden = 1; hidden }; hidden;"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn continue_outside_a_loop_is_an_error() {
+        let interpreter = Interpreter::new();
+        assert!(run_source(&interpreter, "continue;").is_err());
+    }
+
+    #[test]
+    fn logical_operators_return_an_operand() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var zero = 0 or 2;
+            var fallback = nil or \"x\";
+            var empty = \"\" and 5;
+            var falsey = false and 1;
+            var none = nil or false;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "zero"), Object::Number(0.0));
+        assert_eq!(
+            global(&interpreter, "fallback"),
+            Object::String("x".to_string())
+        );
+        assert_eq!(global(&interpreter, "empty"), Object::Number(5.0));
+        assert_eq!(global(&interpreter, "falsey"), Object::Bool(false));
+        assert_eq!(global(&interpreter, "none"), Object::Bool(false));
+    }
+
+    #[test]
+    fn logical_operators_skip_the_untaken_operand() {
+        let interpreter = Interpreter::new();
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = calls.clone();
+        interpreter.define_native(
+            "sideEffect",
+            0,
+            Rc::new(move |_: &[Object]| {
+                counter.set(counter.get() + 1);
+                Ok(Object::Bool(true))
+            }),
+        );
+        let source = "
+            false and sideEffect();
+            nil and sideEffect();
+            true or sideEffect();
+            0 or sideEffect();
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(calls.get(), 0);
+        assert!(run_source(
+            &interpreter,
+            "true and sideEffect(); false or sideEffect();"
+        )
+        .is_ok());
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn dangling_else_runs_with_the_inner_if() {
+        let cases = [
+            (true, true, "1"),
+            (true, false, "2"),
+            (false, true, "nil"),
+            (false, false, "nil"),
+        ];
+        for (a, b, expected) in cases {
+            let interpreter = Interpreter::new();
+            let source = format!(
+                "var result; if ({}) if ({}) result = 1; else result = 2;",
+                a, b
+            );
+            assert!(run_source(&interpreter, &source).is_ok());
+            assert_eq!(
+                global(&interpreter, "result").to_string(),
+                expected,
+                "a = {}, b = {}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn tight_loop_resolves_interned_names() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var total = 0;
+            fun sum(n) {
+                var acc = 0;
+                for (var i = 0; i < n; i = i + 1) {
+                    var acc2 = acc + i;
+                    acc = acc2;
+                }
+                return acc;
+            }
+            for (var round = 0; round < 10; round = round + 1) {
+                total = total + sum(100);
+            }
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "total"), Object::Number(49500.0));
+    }
+
+    #[test]
+    fn slots_resolve_closures_and_shadowed_names() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var a = \"global\";
+            var seen = [];
+            {
+                var a = \"outer\";
+                var b = \"b\";
+                fun make() {
+                    var c = a + b;
+                    {
+                        var a = \"inner\";
+                        push(seen, a + c);
+                    }
+                    return fun () {
+                        c = c + \"!\";
+                        return c;
+                    };
+                }
+                var counter = make();
+                counter();
+                push(seen, counter());
+                a = \"changed\";
+                push(seen, make()());
+            }
+            push(seen, a);
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "seen").to_string(),
+            "[\"innerouterb\", \"outerb!!\", \"innerchangedb\", \"changedb!\", \"global\"]"
+        );
+    }
+
+    #[test]
+    fn functions_print_their_names() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun greet() {}
+            class Box { open() {} }
+            var named = str(greet);
+            var native = str(clock);
+            var lambda = str(fun () {});
+            var method = str(Box().open);
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        let printed = |name| global(&interpreter, name).to_string();
+        assert_eq!(printed("named"), "<fn greet>");
+        assert_eq!(printed("native"), "<native clock>");
+        assert_eq!(printed("lambda"), "<fn anonymous>");
+        assert_eq!(printed("method"), "<fn open>");
+    }
+
+    #[test]
+    fn labelled_break_leaves_the_outer_loop() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var pairs = [];
+            outer: for (var i = 0; i < 3; i = i + 1) {
+                for (var j = 0; j < 3; j = j + 1) {
+                    if (i == 1 and j == 1) break outer;
+                    push(pairs, str(i) + str(j));
+                }
+            }
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "pairs").to_string(),
+            "[\"00\", \"01\", \"02\", \"10\"]"
+        );
+    }
+
+    #[test]
+    fn labelled_continue_moves_to_the_next_outer_iteration() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var pairs = [];
+            var i = 0;
+            rows: while (i < 3) {
+                i = i + 1;
+                for (var j = 0; j < 3; j = j + 1) {
+                    if (j == i) continue rows;
+                    push(pairs, str(i) + str(j));
+                }
+            }
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "pairs").to_string(),
+            "[\"10\", \"20\", \"21\", \"30\", \"31\", \"32\"]"
+        );
+    }
+
+    #[test]
+    fn ordering_unorderable_values_is_an_error() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun someFn() {}
+            fun otherFn() {}
+            var same = someFn == someFn;
+            var different = someFn == otherFn;
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(global(&interpreter, "same"), Object::Bool(true));
+        assert_eq!(global(&interpreter, "different"), Object::Bool(false));
+        match run_source(&interpreter, "someFn < otherFn;") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "operands are not comparable: function")
+            }
+            _ => panic!("expected a runtime error"),
+        }
+        assert!(run_source(&interpreter, "[1] >= [2];").is_err());
+    }
+
+    #[test]
+    fn return_leaves_nested_statements_with_its_value() {
+        let interpreter = Interpreter::new();
+        let source = "
+            var trail = [];
+            fun fromIf(x) {
+                if (x > 0) {
+                    return \"positive\";
+                }
+                push(trail, \"after if\");
+                return \"other\";
+            }
+            fun fromWhile() {
+                var i = 0;
+                while (true) {
+                    i = i + 1;
+                    if (i == 3) return i;
+                }
+                push(trail, \"after while\");
+            }
+            fun fromBlock() {
+                {
+                    {
+                        return \"inner\";
+                    }
+                    push(trail, \"after inner block\");
+                }
+                push(trail, \"after outer block\");
+            }
+            fun fromFor() {
+                for (var i = 0; i < 10; i = i + 1) {
+                    for (var j = 0; j < 10; j = j + 1) {
+                        if (i * j == 6) return str(i) + str(j);
+                    }
+                }
+            }
+            var a = fromIf(1);
+            var b = fromWhile();
+            var c = fromBlock();
+            var d = fromFor();
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert_eq!(
+            global(&interpreter, "a"),
+            Object::String("positive".to_string())
+        );
+        assert_eq!(global(&interpreter, "b"), Object::Number(3.0));
+        assert_eq!(
+            global(&interpreter, "c"),
+            Object::String("inner".to_string())
+        );
+        assert_eq!(global(&interpreter, "d"), Object::String("16".to_string()));
+        assert_eq!(global(&interpreter, "trail").to_string(), "[]");
+        // the loops the returns left don't make a later top-level break look like it's in one
+        assert!(run_source(&interpreter, "break;").is_err());
+    }
+
+    #[test]
+    fn leaving_a_loop_early_restores_the_nesting_level() {
+        let interpreter = Interpreter::new();
+        let source = "
+            fun early() {
+                while (true) return 1;
+            }
+            early();
+        ";
+        assert!(run_source(&interpreter, source).is_ok());
+        assert!(run_source(&interpreter, "break;").is_err());
+        // a labelled break leaves the inner loop without finishing it
+        assert!(run_source(
+            &interpreter,
+            "outer: while (true) { while (true) { break outer; } }"
+        )
+        .is_ok());
+        assert!(run_source(&interpreter, "break;").is_err());
+        // so does a runtime error
+        assert!(run_source(&interpreter, "while (true) { nil + 1; }").is_err());
+        match run_source(&interpreter, "break;") {
+            Err(LoxResult::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Can't break outside of loop")
+            }
+            _ => panic!("expected break outside of loop to fail"),
+        }
+        assert_eq!(*interpreter.nesting_level.borrow(), 0);
+    }
 }
@@ -0,0 +1,24 @@
+pub mod ast_json;
+pub mod ast_printer;
+pub mod callable;
+pub mod class;
+pub mod environment;
+pub mod error;
+pub mod expr;
+pub mod function;
+pub mod instance;
+pub mod interpreter;
+pub mod lox;
+pub mod object;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod span;
+pub mod stmt;
+pub mod symbol;
+pub mod token;
+pub mod token_type;
+
+pub use error::{LoxResult, ReportFormat};
+pub use lox::Lox;
+pub use object::Object;
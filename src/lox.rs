@@ -0,0 +1,448 @@
+use crate::ast_json::to_json;
+use crate::ast_printer::AstPrinter;
+use crate::error::{LoxResult, ReportFormat};
+use crate::interpreter::{Frame, Interpreter};
+use crate::object::Object;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::span::Spans;
+use crate::stmt::{ExpressionStmt, Stmt};
+use std::cell::RefCell;
+use std::io::{self, stdout, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+type Program = Rc<Vec<Rc<Stmt>>>;
+// Parsed statements, with where each of their nodes came from.
+type Parsed = (Vec<Rc<Stmt>>, Spans);
+
+// A traceback longer than twice this shows only this many calls from each end.
+const TRACEBACK_EDGE: usize = 10;
+
+const REPL_HELP: &str = "\
+:help         list these commands
+:type <expr>  evaluate an expression and print its type
+:env          list the globals defined so far
+:reset        forget every global and start over
+exit, quit    leave the REPL";
+
+// The entry point for running Lox code. The interpreter, and so every global, lives as long as
+// the Lox value, so separate run_source calls share state. Errors are written to the
+// diagnostics writer, stderr unless with_diagnostics says otherwise, once each as they reach
+// the top level; `print` writes to stdout unless with_output says otherwise.
+pub struct Lox {
+    interpreter: Interpreter,
+    diagnostics: RefCell<Box<dyn Write>>,
+    report_format: RefCell<ReportFormat>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Lox::new()
+    }
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Lox::with_diagnostics(io::stderr())
+    }
+
+    pub fn with_diagnostics(writer: impl Write + 'static) -> Self {
+        Lox {
+            interpreter: Interpreter::new(),
+            diagnostics: RefCell::new(Box::new(writer)),
+            report_format: RefCell::new(ReportFormat::Plain),
+        }
+    }
+
+    // Sends the output of `print` statements to the writer instead of stdout.
+    pub fn with_output(writer: impl Write + 'static) -> Self {
+        Lox {
+            interpreter: Interpreter::with_output(writer),
+            diagnostics: RefCell::new(Box::new(io::stderr())),
+            report_format: RefCell::new(ReportFormat::Plain),
+        }
+    }
+
+    pub fn set_report_format(&self, format: ReportFormat) {
+        *self.report_format.borrow_mut() = format;
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    // Runs a whole program. Errors are reported to the diagnostics writer and also handed
+    // back: every syntax error, or the first resolution or runtime error.
+    pub fn run_source(&self, source: &str) -> Result<(), Vec<LoxResult>> {
+        let result = match self.parse(source.to_string(), false) {
+            Ok(Some(stmts)) => self
+                .execute(stmts, false)
+                .map(|_| ())
+                .map_err(|err| vec![err]),
+            Ok(None) => Ok(()),
+            Err(errors) => Err(errors),
+        };
+        self.report(source, result)
+    }
+
+    // Runs a script file; the imports in it are found relative to the file.
+    pub fn run_file(&self, path: &str) -> Result<(), Vec<LoxResult>> {
+        let source = self.read_file(path)?;
+        self.interpreter.set_script_path(Path::new(path));
+        self.run_source(&source)
+    }
+
+    // The contents of a script file, or a reported error if it can't be read.
+    pub fn read_file(&self, path: &str) -> Result<String, Vec<LoxResult>> {
+        std::fs::read_to_string(path).or_else(|_| {
+            let message = format!("Could not read file '{}'.", path);
+            self.report("", Err(vec![LoxResult::system_error(&message)]))
+        })
+    }
+
+    // Parses the source and prints its syntax tree instead of running it.
+    pub fn print_ast(&self, source: &str) -> Result<(), Vec<LoxResult>> {
+        let result = Self::scan_and_parse(source.to_string(), false).map(|parsed| {
+            for stmt in parsed.iter().flat_map(|(stmts, _)| stmts) {
+                if let Ok(tree) = AstPrinter.print_stmt(stmt) {
+                    println!("{}", tree);
+                }
+            }
+        });
+        self.report(source, result)
+    }
+
+    // Parses the source and prints its syntax tree as one JSON array of statements.
+    pub fn print_json(&self, source: &str) -> Result<(), Vec<LoxResult>> {
+        let result = Self::scan_and_parse(source.to_string(), false).and_then(|stmts| {
+            let stmts = stmts.map(|(stmts, _)| stmts).unwrap_or_default();
+            let json = to_json(&stmts).map_err(|err| vec![err])?;
+            println!("{}", json);
+            Ok(())
+        });
+        self.report(source, result)
+    }
+
+    // Scans the source and prints each token on its own line, without parsing it.
+    pub fn print_tokens(&self, source: &str) -> Result<(), Vec<LoxResult>> {
+        let mut scanner = Scanner::new(source.to_string());
+        let result = match scanner.scan_tokens() {
+            Ok(tokens) => {
+                for token in tokens {
+                    println!("{}:{} {}", token.line, token.column, token);
+                }
+                Ok(())
+            }
+            Err(_) => Err(scanner.errors().to_vec()),
+        };
+        self.report(source, result)
+    }
+
+    // The source is the text the errors came from, for quoting the offending line.
+    fn report<T>(
+        &self,
+        source: &str,
+        result: Result<T, Vec<LoxResult>>,
+    ) -> Result<T, Vec<LoxResult>> {
+        if let Err(errors) = &result {
+            let mut diagnostics = self.diagnostics.borrow_mut();
+            let format = *self.report_format.borrow();
+            for err in errors {
+                // a diagnostics writer that fails has nowhere left to report to
+                let _ = err.report_format_to(&mut *diagnostics, "", source, format);
+                // a traceback is prose, so JSON output leaves it out rather than break the
+                // one-object-per-line shape
+                if let Some(frames) = self.interpreter.take_traceback() {
+                    if format != ReportFormat::Json {
+                        let _ = Self::write_traceback(&mut *diagnostics, &frames);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    // One line per frame, outermost first: the line reached and the function it is in.
+    fn write_traceback(out: &mut dyn Write, frames: &[Frame]) -> io::Result<()> {
+        writeln!(out, "Traceback (outermost call first):")?;
+        let elided = frames.len().saturating_sub(2 * TRACEBACK_EDGE);
+        for (i, frame) in frames.iter().enumerate() {
+            if elided > 0 && i >= TRACEBACK_EDGE && i < TRACEBACK_EDGE + elided {
+                if i == TRACEBACK_EDGE {
+                    writeln!(out, "  ... {} more calls ...", elided)?;
+                }
+                continue;
+            }
+            writeln!(out, "  [line {}] in {}", frame.line, frame.function)?;
+        }
+        Ok(())
+    }
+
+    // Ends with Err holding LoxResult::Exit when a statement calls exit().
+    pub fn run_prompt(&self) -> Result<(), Vec<LoxResult>> {
+        let stdin = io::stdin();
+        self.repl(stdin.lock(), &mut stdout())
+    }
+
+    // Reads statements line by line until end of input, `exit`/`quit`, or a call to exit();
+    // blank lines are skipped and a line starting with ':' is a REPL command. A statement left
+    // unfinished at the end of a line keeps reading with a `... ` prompt. A single expression
+    // echoes its value, and its trailing ';' may be left off.
+    fn repl(&self, input: impl BufRead, output: &mut impl Write) -> Result<(), Vec<LoxResult>> {
+        let mut buffer = String::new();
+        write!(output, "> ").unwrap();
+        output.flush().unwrap();
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match line.trim() {
+                "exit" | "quit" if buffer.is_empty() => break,
+                "" if buffer.is_empty() => (),
+                command if buffer.is_empty() && command.starts_with(':') => {
+                    self.meta_command(command, output)?
+                }
+                _ => {
+                    buffer.push_str(&line);
+                    buffer.push('\n');
+                    match self.parse(buffer.clone(), true) {
+                        Ok(None) => {
+                            write!(output, "... ").unwrap();
+                            output.flush().unwrap();
+                            continue;
+                        }
+                        Ok(Some(stmts)) => match self.execute(stmts, true) {
+                            Err(exit @ LoxResult::Exit { .. }) => return Err(vec![exit]),
+                            result => {
+                                let result = result.map_err(|err| vec![err]);
+                                if let Ok(Some(value)) = self.report(&buffer, result) {
+                                    writeln!(output, "{}", value).unwrap();
+                                }
+                            }
+                        },
+                        Err(errors) => {
+                            let _ = self.report::<()>(&buffer, Err(errors));
+                        }
+                    }
+                    buffer.clear();
+                }
+            }
+            write!(output, "> ").unwrap();
+            output.flush().unwrap();
+        }
+        Ok(())
+    }
+
+    // A REPL line starting with ':' is a command to the REPL rather than Lox code. The only
+    // error handed back is the exit of an exit() inside `:type`.
+    fn meta_command(&self, command: &str, output: &mut impl Write) -> Result<(), Vec<LoxResult>> {
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, argument)| (name, argument.trim()));
+        match name {
+            ":help" => writeln!(output, "{}", REPL_HELP).unwrap(),
+            ":type" => match self.evaluate_expression(argument) {
+                Ok(value) => writeln!(output, "{}", value.get_type()).unwrap(),
+                Err(errors) if matches!(errors.as_slice(), [LoxResult::Exit { .. }]) => {
+                    return Err(errors)
+                }
+                Err(_) => (),
+            },
+            ":env" => {
+                for (name, value) in self.interpreter.defined_globals() {
+                    writeln!(output, "{} = {}", name, value).unwrap();
+                }
+            }
+            ":reset" => self.interpreter.reset(),
+            _ => writeln!(output, "Unknown command '{}'. Type :help for a list.", name).unwrap(),
+        }
+        Ok(())
+    }
+
+    // Evaluates source holding a single expression, reporting any error.
+    fn evaluate_expression(&self, source: &str) -> Result<Object, Vec<LoxResult>> {
+        let mut scanner = Scanner::new(source.to_string());
+        let result = match scanner.scan_tokens() {
+            Ok(tokens) => Parser::new(tokens)
+                .parse_expression()
+                .and_then(|expr| {
+                    // resolved as a statement, so any function literal in it gets its scopes
+                    let statement = Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
+                        expression: Rc::clone(&expr),
+                    })));
+                    Resolver::new(&self.interpreter).resolve(&Rc::new(vec![statement]))?;
+                    self.interpreter.take_traceback();
+                    self.interpreter.evaluate(expr)
+                })
+                .map_err(|err| vec![err]),
+            Err(_) => Err(scanner.errors().to_vec()),
+        };
+        self.report(source, result)
+    }
+
+    // Scans, parses and resolves the source, reporting any resolver warnings. In REPL mode,
+    // input that ends mid-statement gives Ok(None) so the caller can read more lines.
+    fn parse(&self, source: String, is_repl: bool) -> Result<Option<Program>, Vec<LoxResult>> {
+        let Some((stmts, spans)) = Self::scan_and_parse(source.clone(), is_repl)? else {
+            return Ok(None);
+        };
+        self.interpreter.add_spans(spans);
+        let resolver = Resolver::new(&self.interpreter);
+        let s = Rc::new(stmts);
+        resolver.resolve(&s).map_err(|err| vec![err])?;
+        let mut diagnostics = self.diagnostics.borrow_mut();
+        let format = *self.report_format.borrow();
+        for warning in resolver.take_warnings() {
+            let _ = warning.report_warning_to(&mut *diagnostics, &source, format);
+        }
+        Ok(Some(s))
+    }
+
+    // The statements and their spans, or every scanner error, or else every parser error; None
+    // when a REPL parse ran out of input.
+    pub(crate) fn scan_and_parse(
+        source: String,
+        is_repl: bool,
+    ) -> Result<Option<Parsed>, Vec<LoxResult>> {
+        let mut scanner = Scanner::new(source);
+        let tokens = match scanner.scan_tokens() {
+            Ok(tokens) => tokens,
+            Err(_) => return Err(scanner.errors().to_vec()),
+        };
+        let mut parser = if is_repl {
+            Parser::for_repl(tokens)
+        } else {
+            Parser::new(tokens)
+        };
+        let stmts = parser.parse().map_err(|err| vec![err])?;
+        if parser.is_incomplete() {
+            return Ok(None);
+        }
+        if !parser.success() {
+            return Err(parser.errors().to_vec());
+        }
+        Ok(Some((stmts, parser.take_spans())))
+    }
+
+    // In REPL mode a lone expression statement is evaluated and its value returned, so the
+    // prompt can echo it.
+    fn execute(&self, stmts: Program, is_repl: bool) -> Result<Option<Object>, LoxResult> {
+        // drop the calls of an earlier error that nobody reported
+        self.interpreter.take_traceback();
+        if let [stmt] = stmts.as_slice() {
+            if let (true, Stmt::Expression(expr)) = (is_repl, stmt.as_ref()) {
+                return Ok(Some(self.interpreter.evaluate(expr.expression.clone())?));
+            }
+        }
+        self.interpreter.interpret(&stmts)?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repl_output(input: &str) -> String {
+        let mut output = Vec::new();
+        let _ = Lox::new().repl(input.as_bytes(), &mut output);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn repl_echoes_expression_values() {
+        assert_eq!(repl_output("var x = 1;\nx\n1 + 2;\n"), "> > 1\n> 3\n> ");
+    }
+
+    #[test]
+    fn repl_does_not_echo_statements() {
+        assert_eq!(repl_output("var y = 2;\nfun f() {}\n"), "> > > ");
+    }
+
+    #[test]
+    fn repl_skips_blank_lines() {
+        assert_eq!(repl_output("\n1 + 1\n"), "> > 2\n> ");
+    }
+
+    #[test]
+    fn repl_stops_at_exit() {
+        assert_eq!(repl_output("1\nexit\n2\n"), "> 1\n> ");
+        assert_eq!(repl_output("quit\n3\n"), "> ");
+        assert_eq!(repl_output("1\nexit(0);\n2\n"), "> 1\n> ");
+    }
+
+    #[test]
+    fn repl_reads_multiline_statements() {
+        assert_eq!(
+            repl_output("fun f(a) {\n  return a * 2;\n}\nf(21)\n"),
+            "> ... ... > 42\n> "
+        );
+    }
+
+    #[test]
+    fn repl_type_prints_the_type_of_an_expression() {
+        assert_eq!(
+            repl_output(":type 1 + 2\n:type \"a\"\n:type clock\n"),
+            "> number\n> string\n> function\n> "
+        );
+        // a bad expression is reported, not echoed
+        assert_eq!(repl_output(":type 1 +\n"), "> > ");
+    }
+
+    #[test]
+    fn repl_env_lists_defined_globals() {
+        assert_eq!(
+            repl_output("var b = 2;\nvar a = \"one\";\n:env\n"),
+            "> > > a = one\nb = 2\n> "
+        );
+    }
+
+    #[test]
+    fn repl_reset_forgets_globals() {
+        assert_eq!(
+            repl_output("var a = 1;\n:reset\n:env\nclock != nil\n"),
+            "> > > > true\n> "
+        );
+    }
+
+    #[test]
+    fn repl_lists_commands_and_rejects_unknown_ones() {
+        let output = repl_output(":help\n:nope\n");
+        assert!(output.contains(":type <expr>"), "{}", output);
+        assert!(
+            output.ends_with("> Unknown command ':nope'. Type :help for a list.\n> "),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn repl_drops_input_after_a_syntax_error() {
+        assert_eq!(repl_output("var = 1;\n1\n"), "> > 1\n> ");
+    }
+
+    #[test]
+    fn long_tracebacks_are_elided_in_the_middle() {
+        let frames: Vec<Frame> = (1..=25)
+            .map(|line| Frame {
+                function: "<fn f>".to_string(),
+                line,
+            })
+            .collect();
+        let mut out = Vec::new();
+        Lox::write_traceback(&mut out, &frames).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1 + 2 * TRACEBACK_EDGE + 1);
+        assert_eq!(lines[1], "  [line 1] in <fn f>");
+        assert_eq!(lines[TRACEBACK_EDGE + 1], "  ... 5 more calls ...");
+        assert_eq!(lines.last(), Some(&"  [line 25] in <fn f>"));
+    }
+
+    #[test]
+    fn repl_allows_redeclaring_globals() {
+        assert_eq!(repl_output("var a = 1;\nvar a = 2;\na\n"), "> > > 2\n> ");
+    }
+}
@@ -1,98 +1,76 @@
-use std::{env::args, io::stdout, io::Write};
-mod error;
-mod expr;
-pub mod parser;
-mod scanner;
-mod token;
-mod token_type;
+use ast_interpreter::{Lox, LoxResult, ReportFormat};
+use std::env::args;
+use std::io::{self, IsTerminal};
 
-mod ast_printer;
-mod callable;
-mod environment;
-mod function;
-mod interpreter;
-mod object;
-mod stmt;
-mod resolver;
-
-use crate::error::LoxResult;
-use crate::interpreter::Interpreter;
-use crate::parser::Parser;
-use scanner::Scanner;
-use std::io::{self, BufRead};
-use std::rc::Rc;
+// Each Lox call nests several Rust frames, and debug builds run out of the default 8MB main
+// stack well before DEFAULT_MAX_DEPTH calls, so the interpreter gets a thread with more room.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
 
 fn main() {
-    let args: Vec<String> = args().collect();
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("could not start interpreter thread")
+        .join()
+        .expect("interpreter thread panicked");
+}
+
+fn run() {
+    let mut args: Vec<String> = args().collect();
     let lox = Lox::new();
-    match args.len() {
-        1 => {
-            lox.run_prompt();
-        }
-        2 => {
-            lox.run_file(&args[1]).expect("could not run file");
-        }
-        _ => {
-            println!("Incorrect Usage: lox-ast [script]");
-            std::process::exit(64);
+    lox.set_report_format(error_format(&mut args));
+    let result = match args.len() {
+        1 => lox.run_prompt(),
+        2 => lox.run_file(&args[1]),
+        3 if args[1] == "--ast" => lox
+            .read_file(&args[2])
+            .and_then(|source| lox.print_ast(&source)),
+        3 if args[1] == "--json" => lox
+            .read_file(&args[2])
+            .and_then(|source| lox.print_json(&source)),
+        3 if args[1] == "--tokens" => lox
+            .read_file(&args[2])
+            .and_then(|source| lox.print_tokens(&source)),
+        _ => usage(),
+    };
+    if let Err(errors) = result {
+        if let [LoxResult::Exit { code }] = errors.as_slice() {
+            std::process::exit(*code);
         }
+        // 70 for an error while running, 65 for bad input, as in jlox.
+        let runtime = errors.iter().any(|err| {
+            matches!(
+                err,
+                LoxResult::RuntimeError { .. } | LoxResult::SystemError { .. }
+            )
+        });
+        std::process::exit(if runtime { 70 } else { 65 });
     }
 }
 
-struct Lox {
-    interpreter: Interpreter,
+fn usage() -> ! {
+    println!(
+        "Incorrect Usage: lox-ast [--error-format plain|color|json] [--ast | --json | --tokens] [script]"
+    );
+    std::process::exit(64);
 }
 
-impl Lox {
-    pub fn new() -> Self {
-        Lox {
-            interpreter: Interpreter::new(),
-        }
-    }
-
-    pub fn run_file(&self, path: &str) -> io::Result<()> {
-        let buf = std::fs::read_to_string(path)?;
-        match self.run(buf) {
-            Ok(_) => (),
-            Err(_) => {
-                std::process::exit(65);
-            }
-        }
-
-        Ok(())
-    }
-    pub fn run_prompt(&self) {
-        let stdin = io::stdin();
-        print!("> ");
-        stdout().flush().unwrap();
-        for line in stdin.lock().lines() {
-            if let Ok(line) = line {
-                if line.is_empty() {
-                    break;
-                }
-                let _ = self.run(line);
-            }
-            print!("> ");
-            stdout().flush().unwrap();
-        }
-    }
-
-    fn run(&self, source: String) -> Result<(), error::LoxResult> {
-        let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens()?;
-        let mut parser = Parser::new(tokens);
-        let stmts = parser.parse()?;
-        if parser.success() {
-            let resolver = resolver::Resolver::new(&self.interpreter);
-            let s = Rc::new(stmts);
-            resolver.resolve(&Rc::clone(&s))?;
-            self.interpreter.interpret(&Rc::clone(&s));
-            Ok(())
-        } else {
-            Err(LoxResult::GenericError {
-                line: 0,
-                message: "could not interpret".to_string(),
-            })
-        }
+// Takes `--error-format <name>` out of the arguments. Colour is only worth sending to a
+// terminal, so it falls back to plain text when stderr is redirected.
+fn error_format(args: &mut Vec<String>) -> ReportFormat {
+    let Some(i) = args.iter().position(|arg| arg == "--error-format") else {
+        return ReportFormat::Plain;
+    };
+    let Some(format) = args
+        .get(i + 1)
+        .and_then(|name| ReportFormat::from_name(name))
+    else {
+        usage();
+    };
+    args.drain(i..i + 2);
+    if format == ReportFormat::Color && !io::stderr().is_terminal() {
+        ReportFormat::Plain
+    } else {
+        format
     }
 }
@@ -1,13 +1,57 @@
-use crate::callable::Callable;
+use crate::callable::{Callable, LoxCallable};
+use crate::class::LoxClass;
+use crate::error::LoxResult;
+use crate::instance::LoxInstance;
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::HashMap;
 use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum Object {
     String(String),
     Number(f64),
     Nil,
     Bool(bool),
     Func(Callable),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<Freezable<Vec<Object>>>),
+    // Keys are strings only for now; indexing with anything else is a runtime error.
+    Map(Rc<Freezable<HashMap<String, Object>>>),
+}
+
+// The contents of a list or map, shared by every value that refers to it. freeze() makes them
+// read-only for good; mutate() is the only way to change them, and it refuses from then on.
+#[derive(Debug)]
+pub struct Freezable<T> {
+    contents: RefCell<T>,
+    frozen: Cell<bool>,
+}
+
+impl<T> Freezable<T> {
+    pub fn new(contents: T) -> Self {
+        Freezable {
+            contents: RefCell::new(contents),
+            frozen: Cell::new(false),
+        }
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.contents.borrow()
+    }
+
+    pub fn mutate(&self) -> Result<RefMut<'_, T>, LoxResult> {
+        if self.frozen.get() {
+            return Err(LoxResult::system_error("Cannot mutate frozen value."));
+        }
+        Ok(self.contents.borrow_mut())
+    }
+
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
 }
 
 impl Object {
@@ -18,19 +62,218 @@ impl Object {
             Object::Nil => "nil".to_string(),
             Object::Bool(_) => "bool".to_string(),
             Object::Func(_) => "function".to_string(),
+            Object::Class(_) => "class".to_string(),
+            Object::Instance(_) => "instance".to_string(),
+            Object::List(_) => "list".to_string(),
+            Object::Map(_) => "map".to_string(),
+        }
+    }
+
+    // Equality as Lox's `==` sees it: lists and maps are equal when their contents are, all
+    // the way down, and everything else compares as eq does. A pair of aggregates met again
+    // while comparing them counts as equal, so a list holding itself doesn't loop forever.
+    pub fn lox_equals(&self, other: &Object) -> bool {
+        self.equals_within(other, &mut Vec::new())
+    }
+
+    // `seen` holds the pairs of aggregates already being compared further up.
+    fn equals_within(&self, other: &Object, seen: &mut Vec<(usize, usize)>) -> bool {
+        match (self, other) {
+            (Object::List(l1), Object::List(l2)) => {
+                let pair = (Rc::as_ptr(l1) as usize, Rc::as_ptr(l2) as usize);
+                if Rc::ptr_eq(l1, l2) || seen.contains(&pair) {
+                    return true;
+                }
+                seen.push(pair);
+                let (l1, l2) = (l1.borrow(), l2.borrow());
+                l1.len() == l2.len()
+                    && l1
+                        .iter()
+                        .zip(l2.iter())
+                        .all(|(a, b)| a.equals_within(b, seen))
+            }
+            (Object::Map(m1), Object::Map(m2)) => {
+                let pair = (Rc::as_ptr(m1) as usize, Rc::as_ptr(m2) as usize);
+                if Rc::ptr_eq(m1, m2) || seen.contains(&pair) {
+                    return true;
+                }
+                seen.push(pair);
+                let (m1, m2) = (m1.borrow(), m2.borrow());
+                m1.len() == m2.len()
+                    && m1
+                        .iter()
+                        .all(|(key, a)| m2.get(key).is_some_and(|b| a.equals_within(b, seen)))
+            }
+            _ => self == other,
         }
     }
 }
+
+// Classes, instances, lists and maps compare by identity, like functions. Lox's `==` looks
+// inside lists and maps with lox_equals instead.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::String(s1), Object::String(s2)) => s1 == s2,
+            (Object::Number(n1), Object::Number(n2)) => n1 == n2,
+            (Object::Nil, Object::Nil) => true,
+            (Object::Bool(b1), Object::Bool(b2)) => b1 == b2,
+            (Object::Func(f1), Object::Func(f2)) => f1 == f2,
+            (Object::Class(c1), Object::Class(c2)) => Rc::ptr_eq(c1, c2),
+            (Object::Instance(i1), Object::Instance(i2)) => Rc::ptr_eq(i1, i2),
+            (Object::List(l1), Object::List(l2)) => Rc::ptr_eq(l1, l2),
+            (Object::Map(m1), Object::Map(m2)) => Rc::ptr_eq(m1, m2),
+            _ => false,
+        }
+    }
+}
+
+// NaN is never equal to itself, so a NaN key can be inserted into a map but never found again.
+// Callers that build maps from Lox values should reject NaN keys up front.
+impl Eq for Object {}
+
+// Consistent with `eq`: values hash by content and everything else by the pointer it compares.
+impl Hash for Object {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        std::mem::discriminant(self).hash(hasher);
+        match self {
+            Object::String(s) => s.hash(hasher),
+            // 0.0 and -0.0 are equal, so they must hash alike despite their different bits
+            Object::Number(n) => {
+                let n = if *n == 0.0 { 0.0 } else { *n };
+                hasher.write_u64(n.to_bits());
+            }
+            Object::Nil => {}
+            Object::Bool(b) => b.hash(hasher),
+            Object::Func(f) => hasher.write_usize(Rc::as_ptr(&f.func) as *const () as usize),
+            Object::Class(c) => hasher.write_usize(Rc::as_ptr(c) as usize),
+            Object::Instance(i) => hasher.write_usize(Rc::as_ptr(i) as usize),
+            Object::List(l) => hasher.write_usize(Rc::as_ptr(l) as usize),
+            Object::Map(m) => hasher.write_usize(Rc::as_ptr(m) as usize),
+        }
+    }
+}
+
 impl Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.write_within(f, &mut Vec::new())
+    }
+}
+
+impl Object {
+    // `seen` holds the lists and maps being written further up. One met again is written as
+    // `[...]` or `{...}`, so a list holding itself doesn't recurse until the stack runs out.
+    fn write_within(&self, f: &mut std::fmt::Formatter, seen: &mut Vec<usize>) -> std::fmt::Result {
         match self {
             Object::String(s) => write!(f, "{}", s),
-            Object::Number(n) => write!(f, "{}", n),
+            Object::Number(n) => write!(f, "{}", format_number(*n)),
             Object::Nil => write!(f, "nil"),
             Object::Bool(b) => write!(f, "{}", b),
-            Object::Func(_) => write!(f, "function"),
+            Object::Func(func) => write!(f, "{}", LoxCallable::to_string(func)),
+            Object::Class(c) => write!(f, "{}", c.name()),
+            Object::Instance(i) => write!(f, "{}", i.borrow()),
+            Object::List(list) => {
+                let id = Rc::as_ptr(list) as usize;
+                if seen.contains(&id) {
+                    return write!(f, "[...]");
+                }
+                seen.push(id);
+                write!(f, "[")?;
+                for (i, element) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write_element(f, seen)?;
+                }
+                seen.pop();
+                write!(f, "]")
+            }
+            // Sorted by key so the output doesn't depend on hash order.
+            Object::Map(map) => {
+                let id = Rc::as_ptr(map) as usize;
+                if seen.contains(&id) {
+                    return write!(f, "{{...}}");
+                }
+                seen.push(id);
+                let map = map.borrow();
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                write!(f, "{{")?;
+                for (i, key) in keys.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", quote(key))?;
+                    map[key].write_element(f, seen)?;
+                }
+                seen.pop();
+                write!(f, "}}")
+            }
+        }
+    }
+
+    // Inside a list or map a string is written quoted, as a literal spells it, so ["a, b"] and
+    // ["a", "b"] print differently; at the top level, as `print` shows it, it is the bare
+    // text. nil is `nil` in both places.
+    fn write_element(
+        &self,
+        f: &mut std::fmt::Formatter,
+        seen: &mut Vec<usize>,
+    ) -> std::fmt::Result {
+        match self {
+            Object::String(s) => write!(f, "{}", quote(s)),
+            _ => self.write_within(f, seen),
+        }
+    }
+}
+
+// A string as a Lox literal, using the escapes the scanner reads back.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\0' => quoted.push_str("\\0"),
+            c => quoted.push(c),
         }
     }
+    quoted.push('"');
+    quoted
+}
+
+// Integral values print without a decimal point ("5", "-0") and others with the fewest digits
+// that round-trip. Very large and very small magnitudes switch to exponent form ("1e21") instead
+// of spelling out every zero.
+pub fn format_number(n: f64) -> String {
+    if n != 0.0 && (n.abs() >= 1e21 || n.abs() < 1e-6) {
+        format!("{:e}", n)
+    } else {
+        format!("{}", n)
+    }
+}
+
+// Validates an index into a list or string of `len` elements (characters for strings); `kind`
+// names the container in the error message.
+pub fn checked_index(index: &Object, len: usize, kind: &str) -> Result<usize, String> {
+    match index {
+        Object::Number(n) if n.fract() == 0.0 => {
+            if *n >= 0.0 && (*n as usize) < len {
+                Ok(*n as usize)
+            } else {
+                Err(format!(
+                    "Index {} out of bounds for {} of length {}.",
+                    format_number(*n),
+                    kind,
+                    len
+                ))
+            }
+        }
+        _ => Err("Index must be an integer.".to_string()),
+    }
 }
 
 impl PartialOrd for Object {
@@ -46,3 +289,94 @@ impl PartialOrd for Object {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integral_numbers_have_no_decimal_point() {
+        assert_eq!(Object::Number(5.0).to_string(), "5");
+        assert_eq!(Object::Number(10.0 / 2.0).to_string(), "5");
+        assert_eq!(Object::Number(-0.0).to_string(), "-0");
+    }
+
+    #[test]
+    fn fractions_use_minimal_digits() {
+        assert_eq!(Object::Number(5.5).to_string(), "5.5");
+        assert_eq!(Object::Number(0.1 + 0.2).to_string(), "0.30000000000000004");
+        assert_eq!(Object::Number(10.0 / 4.0).to_string(), "2.5");
+    }
+
+    #[test]
+    fn extreme_magnitudes_use_exponents() {
+        assert_eq!(Object::Number(123456789012.0).to_string(), "123456789012");
+        assert_eq!(Object::Number(1e21).to_string(), "1e21");
+        assert_eq!(Object::Number(-2.5e300).to_string(), "-2.5e300");
+        assert_eq!(Object::Number(1.5e-7).to_string(), "1.5e-7");
+    }
+
+    #[test]
+    fn values_work_as_hash_map_keys() {
+        let mut map: HashMap<Object, Object> = HashMap::new();
+        map.insert(Object::Number(1.0), Object::String("one".to_string()));
+        map.insert(Object::String("two".to_string()), Object::Number(2.0));
+        map.insert(Object::Bool(true), Object::Nil);
+        map.insert(Object::Nil, Object::Bool(false));
+
+        assert_eq!(
+            map.get(&Object::Number(1.0)),
+            Some(&Object::String("one".to_string()))
+        );
+        assert_eq!(
+            map.get(&Object::String("two".to_string())),
+            Some(&Object::Number(2.0))
+        );
+        assert_eq!(map.get(&Object::Bool(true)), Some(&Object::Nil));
+        assert_eq!(map.get(&Object::Nil), Some(&Object::Bool(false)));
+        assert_eq!(map.get(&Object::Bool(false)), None);
+        assert_eq!(map.get(&Object::String("1".to_string())), None);
+
+        map.insert(Object::Number(0.0), Object::Nil);
+        assert!(map.contains_key(&Object::Number(-0.0)));
+    }
+
+    #[test]
+    fn containers_holding_themselves_print_without_looping() {
+        let list = Rc::new(Freezable::new(vec![Object::Number(1.0)]));
+        let outer = Object::List(Rc::clone(&list));
+        list.mutate().unwrap().push(outer.clone());
+        assert_eq!(outer.to_string(), "[1, [...]]");
+
+        let map = Rc::new(Freezable::new(HashMap::new()));
+        let object = Object::Map(Rc::clone(&map));
+        map.mutate()
+            .unwrap()
+            .insert("self".to_string(), object.clone());
+        map.mutate()
+            .unwrap()
+            .insert("list".to_string(), outer.clone());
+        assert_eq!(
+            object.to_string(),
+            "{\"list\": [1, [...]], \"self\": {...}}"
+        );
+
+        // the same list twice, side by side, is not a cycle
+        let pair = Object::List(Rc::new(Freezable::new(vec![
+            Object::List(Rc::new(Freezable::new(vec![]))),
+            Object::Nil,
+        ])));
+        let twice = Object::List(Rc::new(Freezable::new(vec![pair.clone(), pair])));
+        assert_eq!(twice.to_string(), "[[[], nil], [[], nil]]");
+    }
+
+    #[test]
+    fn reference_values_hash_by_identity() {
+        let list = Object::List(Rc::new(Freezable::new(vec![])));
+        let same_contents = Object::List(Rc::new(Freezable::new(vec![])));
+        let mut map: HashMap<Object, Object> = HashMap::new();
+        map.insert(list.clone(), Object::Bool(true));
+        assert!(map.contains_key(&list));
+        assert!(!map.contains_key(&same_contents));
+    }
+}
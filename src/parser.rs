@@ -1,13 +1,15 @@
 use crate::error::LoxResult;
 use crate::expr::Expr::{Literal, Unary};
 use crate::expr::{
-    AssignExpr, BinaryExpr, CallExpr, Expr, GroupingExpr, LiteralExpr, LogicalExpr, UnaryExpr,
-    VariableExpr,
+    ArrayExpr, AssignExpr, BinaryExpr, BlockExpr, CallExpr, CommaExpr, Expr, FunctionExpr, GetExpr,
+    GroupingExpr, IndexExpr, IndexSetExpr, LiteralExpr, LogicalExpr, MapExpr, SetExpr, SuperExpr,
+    ThisExpr, UnaryExpr, VariableExpr,
 };
 use crate::object::Object;
+use crate::span::{Span, Spans};
 use crate::stmt::{
-    BlockStmt, BreakStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt,
-    VarStmt, WhileStmt,
+    BlockStmt, BreakStmt, ClassStmt, ContinueStmt, ExpressionStmt, FunctionStmt, IfStmt,
+    ImportStmt, MultiVarStmt, PrintStmt, ReturnStmt, Stmt, TryStmt, VarStmt, WhileStmt,
 };
 use crate::token::Token;
 use crate::token_type::TokenType;
@@ -17,7 +19,12 @@ use Expr::Binary;
 pub struct Parser<'a> {
     tokens: &'a Vec<Token>,
     current: usize,
-    had_error: bool,
+    errors: Vec<LoxResult>,
+    // In the REPL, running out of tokens mid-statement means the user has more to type, and a
+    // final expression statement may leave off its ';'.
+    repl: bool,
+    incomplete: bool,
+    spans: Spans,
 }
 
 // Example: !(2 + 3) * 4 == 5 - 6 / 3
@@ -62,52 +69,170 @@ impl<'a> Parser<'a> {
         Self {
             tokens,
             current: 0, // it's index into the vec tokens
-            had_error: false,
+            errors: Vec::new(),
+            repl: false,
+            incomplete: false,
+            spans: Spans::default(),
+        }
+    }
+
+    pub fn for_repl(tokens: &'a Vec<Token>) -> Self {
+        Self {
+            repl: true,
+            ..Parser::new(tokens)
         }
     }
 
     // This is the entry point for the parser.
+    // A failed declaration has already been recorded and synchronised past, so parsing carries on
+    // to find every error in one pass; callers check success() before running the statements.
     pub fn parse(&mut self) -> Result<Vec<Rc<Stmt>>, LoxResult> {
         let mut statements = Vec::new();
         while !self.is_at_end() {
-            statements.push(self.declaration()?)
+            if let Ok(statement) = self.declaration() {
+                statements.push(statement);
+            }
         }
         Ok(statements)
     }
 
-    //It simply calls equality(), which is the highest precedence level in the expression grammar.
+    // Parses input that must be exactly one expression, with no trailing ';', so an embedder
+    // can hand the result straight to Interpreter::evaluate.
+    pub fn parse_expression(&mut self) -> Result<Rc<Expr>, LoxResult> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            let peek = self.peek().clone();
+            return Err(self.error(peek, "Expect end of expression."));
+        }
+        Ok(Rc::new(expr))
+    }
+
+    //It simply calls comma(), which is the lowest precedence level in the expression grammar.
     fn expression(&mut self) -> Result<Expr, LoxResult> {
-        self.assignment()
+        self.comma()
+    }
+
+    // `a, b` evaluates a, then b, and is b. Lists whose items are separated by commas (arguments,
+    // list and map literals) and variable initializers parse each item with assignment()
+    // instead, so there a comma only ever separates items.
+    fn comma(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let mut expr = self.assignment()?;
+        while self.is_match(&[TokenType::Comma]) {
+            let right = self.assignment()?;
+            expr = self.mark(
+                start,
+                Expr::Comma(Rc::new(CommaExpr {
+                    left: Rc::new(expr),
+                    right: Rc::new(right),
+                })),
+            );
+        }
+        Ok(expr)
     }
 
     fn declaration(&mut self) -> Result<Rc<Stmt>, LoxResult> {
-        let result = if self.is_match(&[TokenType::Fun]) {
+        let start = self.current;
+        let result = if self.is_match(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.check(TokenType::Fun) && self.check_next(TokenType::Identifier) {
+            self.advance();
             self.function("function")
         } else if self.is_match(&[TokenType::Var]) {
-            self.var_declaration()
+            self.var_declaration(false)
+        } else if self.is_match(&[TokenType::Const]) {
+            self.var_declaration(true)
         } else {
             self.statement()
         };
 
-        if result.is_err() {
-            self.synchronise();
+        match result {
+            Ok(stmt) => Ok(self.mark_stmt(start, stmt)),
+            Err(err) => {
+                self.synchronise();
+                Err(err)
+            }
         }
-        result
+    }
+
+    fn class_declaration(&mut self) -> Result<Rc<Stmt>, LoxResult> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect class name.")?
+            .clone();
+
+        let superclass = if self.is_match(&[TokenType::Less]) {
+            let name = self
+                .consume(TokenType::Identifier, "Expect superclass name.")?
+                .clone();
+            Some(Rc::new(Expr::Variable(Rc::new(VariableExpr { name }))))
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let start = self.current;
+            let method = self.function("method")?;
+            methods.push(self.mark_stmt(start, method));
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(Rc::new(Stmt::Class(Rc::new(ClassStmt {
+            name,
+            superclass,
+            methods: Rc::new(methods),
+        }))))
     }
 
     fn statement(&mut self) -> Result<Rc<Stmt>, LoxResult> {
+        let start = self.current;
+        let stmt = self.unmarked_statement()?;
+        Ok(self.mark_stmt(start, stmt))
+    }
+
+    fn unmarked_statement(&mut self) -> Result<Rc<Stmt>, LoxResult> {
         if self.is_match(&[TokenType::Break]) {
-            let token = self.peek().clone();
+            let token = self.previous().clone();
+            let label = self.jump_label();
             self.consume(TokenType::Semicolon, "Expect ';' after 'break'.")?;
-            return Ok(Rc::new(Stmt::Break(Rc::new(BreakStmt { token }))));
+            return Ok(Rc::new(Stmt::Break(Rc::new(BreakStmt { token, label }))));
+        }
+        if self.is_match(&[TokenType::Continue]) {
+            let token = self.previous().clone();
+            let label = self.jump_label();
+            self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.")?;
+            return Ok(Rc::new(Stmt::Continue(Rc::new(ContinueStmt {
+                token,
+                label,
+            }))));
+        }
+        // `name:` labels the loop that follows, so `break name;` can leave it from a nested loop
+        if self.check(TokenType::Identifier) && self.check_next(TokenType::Colon) {
+            let label = self.advance().clone();
+            self.advance();
+            if self.is_match(&[TokenType::For]) {
+                return self.for_statement(Some(label));
+            }
+            if self.is_match(&[TokenType::While]) {
+                return self.while_statement(Some(label));
+            }
+            if self.is_match(&[TokenType::Do]) {
+                return self.do_statement(Some(label));
+            }
+            return Err(self.error(self.peek().clone(), "Expect loop after label."));
         }
         if self.is_match(&[TokenType::For]) {
-            return self.for_statement();
+            return self.for_statement(None);
         }
 
         if self.is_match(&[TokenType::If]) {
             return Ok(Rc::new(self.if_statement()?));
         }
+        if self.is_match(&[TokenType::Import]) {
+            return Ok(Rc::new(self.import_statement()?));
+        }
         if self.is_match(&[TokenType::Print]) {
             return Ok(Rc::new(self.print_statement()?));
         }
@@ -115,9 +240,15 @@ impl<'a> Parser<'a> {
         if self.is_match(&[TokenType::Return]) {
             return Ok(Rc::new(self.return_statement()?));
         }
+        if self.is_match(&[TokenType::Try]) {
+            return Ok(Rc::new(self.try_statement()?));
+        }
 
         if self.is_match(&[TokenType::While]) {
-            return Ok(self.while_statement()?);
+            return self.while_statement(None);
+        }
+        if self.is_match(&[TokenType::Do]) {
+            return self.do_statement(None);
         }
         if self.is_match(&[TokenType::LeftBrace]) {
             return Ok(Rc::new(Stmt::Block(Rc::new(BlockStmt {
@@ -141,16 +272,54 @@ impl<'a> Parser<'a> {
         })))
     }
 
-    fn for_statement(&mut self) -> Result<Rc<Stmt>, LoxResult> {
+    // try { ... } catch (name) { ... } finally { ... }, where finally is optional
+    fn try_statement(&mut self) -> Result<Stmt, LoxResult> {
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.")?;
+        let try_body = self.block()?;
+        self.consume(TokenType::Catch, "Expect 'catch' after try block.")?;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+        let error = self
+            .consume(TokenType::Identifier, "Expect error variable name.")?
+            .clone();
+        self.consume(TokenType::RightParen, "Expect ')' after error variable.")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' after catch clause.")?;
+        let catch_body = self.block()?;
+        let finally_body = if self.is_match(&[TokenType::Finally]) {
+            self.consume(TokenType::LeftBrace, "Expect '{' after 'finally'.")?;
+            Some(Rc::new(self.block()?))
+        } else {
+            None
+        };
+        Ok(Stmt::Try(Rc::new(TryStmt {
+            try_body: Rc::new(try_body),
+            error,
+            catch_body: Rc::new(catch_body),
+            finally_body,
+        })))
+    }
+
+    // The optional label after `break` or `continue`.
+    fn jump_label(&mut self) -> Option<Token> {
+        if self.is_match(&[TokenType::Identifier]) {
+            Some(self.previous().clone())
+        } else {
+            None
+        }
+    }
+
+    fn for_statement(&mut self, label: Option<Token>) -> Result<Rc<Stmt>, LoxResult> {
+        let start = self.current - 1;
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
 
+        let initializer_start = self.current;
         let initializer = if self.is_match(&[TokenType::Semicolon]) {
             None
         } else if self.is_match(&[TokenType::Var]) {
-            Some(self.var_declaration()?)
+            Some(self.var_declaration(false)?)
         } else {
             Some(self.expression_statement()?)
-        };
+        }
+        .map(|initializer| self.mark_stmt(initializer_start, initializer));
         let condition = if !self.check(TokenType::Semicolon) {
             Some(self.expression()?)
         } else {
@@ -165,19 +334,11 @@ impl<'a> Parser<'a> {
         };
 
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
-        let mut body = self.statement()?;
+        let body = self.statement()?;
 
-        if let Some(increment) = increment {
-            body = Rc::new(Stmt::Block(Rc::new(BlockStmt {
-                statements: Rc::new(vec![
-                    body,
-                    Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
-                        expression: Rc::new(increment),
-                    }))),
-                ]),
-            })));
-        }
-        body = Rc::new(Stmt::While(Rc::new(WhileStmt {
+        // The increment belongs to the loop rather than the end of the body, so it still runs
+        // when `continue` skips the rest of the body.
+        let mut body = Rc::new(Stmt::While(Rc::new(WhileStmt {
             condition: if let Some(condition) = condition {
                 Rc::new(condition)
             } else {
@@ -186,7 +347,11 @@ impl<'a> Parser<'a> {
                 })))
             },
             body,
+            increment: increment.map(Rc::new),
+            label,
+            do_while: false,
         })));
+        body = self.mark_stmt(start, body);
 
         if let Some(initializer) = initializer {
             body = Rc::new(Stmt::Block(Rc::new(BlockStmt {
@@ -201,6 +366,8 @@ impl<'a> Parser<'a> {
         self.consume(TokenType::RightParen, "Expect '(' after 'if'.")?;
 
         let then_branch = self.statement()?;
+        // A nested `if` in the then branch has already claimed any `else` that follows it, so
+        // a dangling `else` binds to the innermost `if`.
         let else_branch = if self.is_match(&[TokenType::Else]) {
             Some(self.statement()?)
         } else {
@@ -213,7 +380,7 @@ impl<'a> Parser<'a> {
         })))
     }
 
-    fn while_statement(&mut self) -> Result<Rc<Stmt>, LoxResult> {
+    fn while_statement(&mut self, label: Option<Token>) -> Result<Rc<Stmt>, LoxResult> {
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = Rc::new(self.expression()?);
         self.consume(TokenType::RightParen, "Expect ')' after 'while'.")?;
@@ -221,70 +388,173 @@ impl<'a> Parser<'a> {
         Ok(Rc::new(Stmt::While(Rc::new(WhileStmt {
             condition,
             body,
+            increment: None,
+            label,
+            do_while: false,
+        }))))
+    }
+
+    // `do <statement> while (<condition>);` is a while loop that checks its condition after
+    // the body rather than before it.
+    fn do_statement(&mut self, label: Option<Token>) -> Result<Rc<Stmt>, LoxResult> {
+        let body = self.statement()?;
+        self.consume(TokenType::While, "Expect 'while' after 'do' body.")?;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = Rc::new(self.expression()?);
+        self.consume(TokenType::RightParen, "Expect ')' after 'while'.")?;
+        self.consume(TokenType::Semicolon, "Expect ';' after do-while condition.")?;
+        Ok(Rc::new(Stmt::While(Rc::new(WhileStmt {
+            condition,
+            body,
+            increment: None,
+            label,
+            do_while: true,
         }))))
     }
 
     fn assignment(&mut self) -> Result<Expr, LoxResult> {
-        let expr = self.or()?;
+        let start = self.current;
+        let expr = self.coalesce()?;
 
         if self.is_match(&[TokenType::Equal]) {
             let equals = self.previous().clone();
             let value = Rc::new(self.assignment()?);
-            if let Expr::Variable(var) = expr {
-                return Ok(Expr::Assign(Rc::new(AssignExpr {
+            let target = match expr {
+                Expr::Variable(var) => Some(Expr::Assign(Rc::new(AssignExpr {
                     name: var.name.clone(),
                     value,
-                })));
+                }))),
+                Expr::Get(get) => Some(Expr::Set(Rc::new(SetExpr {
+                    object: Rc::clone(&get.object),
+                    name: get.name.clone(),
+                    value,
+                }))),
+                Expr::Index(index) => Some(Expr::IndexSet(Rc::new(IndexSetExpr {
+                    object: Rc::clone(&index.object),
+                    bracket: index.bracket.clone(),
+                    index: Rc::clone(&index.index),
+                    value,
+                }))),
+                _ => None,
+            };
+            if let Some(assignment) = target {
+                return Ok(self.mark(start, assignment));
             }
             return Err(self.error(equals, "Invalid assignment target."));
         }
         Ok(expr)
     }
 
+    // `a ?? b` is a unless a is nil. It binds more loosely than `or`, so `a or b ?? c` is
+    // `(a or b) ?? c`.
+    fn coalesce(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let mut expr = self.or()?;
+
+        while self.is_match(&[TokenType::QuestionQuestion]) {
+            let operator = self.previous().clone();
+            let right = Rc::new(self.or()?);
+            expr = self.mark(
+                start,
+                Expr::Logical(Rc::new(LogicalExpr {
+                    left: Rc::new(expr),
+                    operator,
+                    right,
+                })),
+            );
+        }
+        Ok(expr)
+    }
+
     fn or(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.and()?;
 
         while self.is_match(&[TokenType::Or]) {
             let operator = self.previous().clone();
             let right = Rc::new(self.and()?);
-            expr = Expr::Logical(Rc::new(LogicalExpr {
-                left: Rc::new(expr),
-                operator,
-                right,
-            }));
+            expr = self.mark(
+                start,
+                Expr::Logical(Rc::new(LogicalExpr {
+                    left: Rc::new(expr),
+                    operator,
+                    right,
+                })),
+            );
         }
         Ok(expr)
     }
     fn and(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.equality()?;
 
         while self.is_match(&[TokenType::And]) {
             let operator = self.previous().clone();
             let right = self.equality()?;
-            expr = Expr::Logical(Rc::new(LogicalExpr {
-                left: Rc::new(expr),
-                operator,
-                right: Rc::new(right),
-            }));
+            expr = self.mark(
+                start,
+                Expr::Logical(Rc::new(LogicalExpr {
+                    left: Rc::new(expr),
+                    operator,
+                    right: Rc::new(right),
+                })),
+            );
         }
         Ok(expr)
     }
 
-    fn var_declaration(&mut self) -> Result<Rc<Stmt>, LoxResult> {
+    // `var` and `const` share a syntax, except that a constant must be initialized. Several
+    // names may be declared at once, `var a = 1, b;`, which gives one VarStmt for each.
+    fn var_declaration(&mut self, is_const: bool) -> Result<Rc<Stmt>, LoxResult> {
+        let mut declarations = Vec::new();
+        loop {
+            let start = self.current;
+            let declaration = self.var_binding(is_const)?;
+            declarations.push(self.mark_stmt(start, declaration));
+            if !self.is_match(&[TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        )?;
+        if declarations.len() == 1 {
+            return Ok(declarations.remove(0));
+        }
+        Ok(Rc::new(Stmt::MultiVar(Rc::new(MultiVarStmt {
+            declarations: Rc::new(declarations),
+        }))))
+    }
+
+    // One `name` or `name = initializer` of a declaration.
+    fn var_binding(&mut self, is_const: bool) -> Result<Rc<Stmt>, LoxResult> {
         let name = self
             .consume(TokenType::Identifier, "Expect variable name.")?
             .clone();
         let initializer = if self.is_match(&[TokenType::Equal]) {
-            Some(Rc::new(self.expression()?))
+            Some(Rc::new(self.assignment()?))
+        } else if is_const {
+            let peek = self.peek().clone();
+            return Err(self.error(peek, "Expect '=' after constant name."));
         } else {
             None
         };
+        Ok(Rc::new(Stmt::Var(Rc::new(VarStmt {
+            name,
+            initializer,
+            is_const,
+        }))))
+    }
 
-        self.consume(
-            TokenType::Semicolon,
-            "Expect ';' after variable declaration.",
-        )?;
-        Ok(Rc::new(Stmt::Var(Rc::new(VarStmt { name, initializer }))))
+    // import "path/to/file.lox";
+    fn import_statement(&mut self) -> Result<Stmt, LoxResult> {
+        let path = self
+            .consume(TokenType::String, "Expect file path after 'import'.")?
+            .clone();
+        self.consume(TokenType::Semicolon, "Expect ';' after import path.")?;
+        Ok(Stmt::Import(Rc::new(ImportStmt { path })))
     }
 
     fn print_statement(&mut self) -> Result<Stmt, LoxResult> {
@@ -295,8 +565,12 @@ impl<'a> Parser<'a> {
 
     fn expression_statement(&mut self) -> Result<Rc<Stmt>, LoxResult> {
         let expr = Rc::new(self.expression()?);
-        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
-        Ok(Rc::new(Stmt::Expression(Rc::new(ExpressionStmt { expression: expr }))))
+        if !(self.repl && self.is_at_end()) {
+            self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        }
+        Ok(Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
+            expression: expr,
+        }))))
     }
 
     fn function(&mut self, kind: &str) -> Result<Rc<Stmt>, LoxResult> {
@@ -307,21 +581,32 @@ impl<'a> Parser<'a> {
             TokenType::LeftParen,
             &format!("Expect '(' after {} name.", kind),
         )?;
+        let declaration = self.function_body(fn_name, kind)?;
+        Ok(Rc::new(Stmt::Function(Rc::new(declaration))))
+    }
+
+    // Parses the parameter list and body of a function, starting just after the '('.
+    fn function_body(&mut self, name: Token, kind: &str) -> Result<FunctionStmt, LoxResult> {
         let mut parameters = Vec::new();
+        let mut variadic = false;
         if !self.check(TokenType::RightParen) {
-            parameters.push(
-                self.consume(TokenType::Identifier, "Expect parameter name.")?
-                    .clone(),
-            );
-            while self.is_match(&[TokenType::Comma]) {
-                if parameters.len() >= 255 && !self.had_error {
+            loop {
+                if parameters.len() >= 255 && self.success() {
                     let peek = self.peek().clone();
                     return Err(self.error(peek, "Can't have more than 255 parameters."));
                 }
+                if variadic {
+                    let peek = self.peek().clone();
+                    return Err(self.error(peek, "Rest parameter must be last."));
+                }
+                variadic = self.is_match(&[TokenType::DotDotDot]);
                 parameters.push(
                     self.consume(TokenType::Identifier, "Expect parameter name.")?
                         .clone(),
                 );
+                if !self.is_match(&[TokenType::Comma]) || self.check(TokenType::RightParen) {
+                    break;
+                }
             }
         }
         self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
@@ -330,11 +615,13 @@ impl<'a> Parser<'a> {
             &format!("Expect '{{' before {} body.", kind),
         )?;
         let body = self.block()?;
-        Ok(Rc::new(Stmt::Function(Rc::new(FunctionStmt {
-            name: fn_name,
+        Ok(FunctionStmt {
+            name,
             params: Rc::new(parameters),
+            variadic,
             body: Rc::new(body),
-        }))))
+            is_lambda: false,
+        })
     }
 
     fn block(&mut self) -> Result<Vec<Rc<Stmt>>, LoxResult> {
@@ -347,57 +634,122 @@ impl<'a> Parser<'a> {
     }
 
     // This method handles comparison operators (>, >=, <, <=). It works similarly to equality() but for comparison operators.
-    //Example: a > b <= c would be parsed as ((a > b) <= c).
+    // At most one comparison operator is allowed: `a > b <= c` would compare the bool `a > b`
+    // with c, so a second operator is a parse error rather than parsed left-associatively.
     fn comparison(&mut self) -> Result<Expr, LoxResult> {
-        let mut expr = self.term()?;
-
-        while self.is_match(&[
+        let comparisons = [
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
-        ]) {
+        ];
+        let start = self.current;
+        let mut expr = self.bit_or()?;
+
+        if self.is_match(&comparisons) {
             let operator = self.previous().clone();
-            let right = self.term()?;
-            expr = Binary(Rc::new(BinaryExpr {
-                left: Rc::new(expr),
-                operator,
-                right: Rc::new(right),
-            }))
+            let right = self.bit_or()?;
+            expr = self.mark(
+                start,
+                Binary(Rc::new(BinaryExpr {
+                    left: Rc::new(expr),
+                    operator,
+                    right: Rc::new(right),
+                })),
+            );
+            if self.is_match(&comparisons) {
+                let chained = self.previous().clone();
+                return Err(self.error(
+                    chained,
+                    "Chained comparison is not allowed; use explicit parentheses or logical operators.",
+                ));
+            }
         }
 
         Ok(expr)
     }
 
+    // Bitwise operators bind tighter than comparison and looser than arithmetic, from loosest
+    // to tightest: |, ^, &, then the shifts. Example: 1 | 2 & 3 << 1 parses as (1 | (2 & (3 << 1))).
+    fn bit_or(&mut self) -> Result<Expr, LoxResult> {
+        self.binary_level(&[TokenType::Pipe], Parser::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, LoxResult> {
+        self.binary_level(&[TokenType::Caret], Parser::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, LoxResult> {
+        self.binary_level(&[TokenType::Ampersand], Parser::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expr, LoxResult> {
+        self.binary_level(
+            &[TokenType::LessLess, TokenType::GreaterGreater],
+            Parser::term,
+        )
+    }
+
+    // A left-associative level of binary operators whose operands are parsed by `operand`.
+    fn binary_level(
+        &mut self,
+        operators: &[TokenType],
+        operand: fn(&mut Self) -> Result<Expr, LoxResult>,
+    ) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let mut expr = operand(self)?;
+        while self.is_match(operators) {
+            let operator = self.previous().clone();
+            let right = operand(self)?;
+            expr = self.mark(
+                start,
+                Binary(Rc::new(BinaryExpr {
+                    left: Rc::new(expr),
+                    operator,
+                    right: Rc::new(right),
+                })),
+            )
+        }
+        Ok(expr)
+    }
+
     // These methods handle addition/subtraction and multiplication/division respectively. They work similarly to comparison() but for their specific operators.
     //Example for term(): a + b - c would be parsed as ((a + b) - c).
     fn term(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.factor()?;
 
         while self.is_match(&[TokenType::Minus, TokenType::Plus]) {
             let operator = self.previous().clone();
             let right = self.factor()?;
-            expr = Binary(Rc::new(BinaryExpr {
-                left: Rc::new(expr),
-                operator,
-                right: Rc::new(right),
-            }))
+            expr = self.mark(
+                start,
+                Binary(Rc::new(BinaryExpr {
+                    left: Rc::new(expr),
+                    operator,
+                    right: Rc::new(right),
+                })),
+            )
         }
 
         Ok(expr)
     }
     // Example for factor(): a * b / c would be parsed as ((a * b) / c).
     fn factor(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.unary()?;
 
-        while self.is_match(&[TokenType::Slash, TokenType::Star]) {
+        while self.is_match(&[TokenType::Slash, TokenType::Star, TokenType::Div]) {
             let operator = self.previous().clone();
             let right = self.unary()?;
-            expr = Binary(Rc::new(BinaryExpr {
-                left: Rc::new(expr),
-                operator,
-                right: Rc::new(right),
-            }))
+            expr = self.mark(
+                start,
+                Binary(Rc::new(BinaryExpr {
+                    left: Rc::new(expr),
+                    operator,
+                    right: Rc::new(right),
+                })),
+            )
         }
 
         Ok(expr)
@@ -405,22 +757,88 @@ impl<'a> Parser<'a> {
     // This method handles unary operators (! and -). If it finds a unary operator, it creates a unary expression. Otherwise, it falls through to primary().
     //Example: !-a would be parsed as (!(-a)).
     fn unary(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         if self.is_match(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous().clone();
             let right = self.unary()?;
-            return Ok(Unary(Rc::new(UnaryExpr {
+            let unary = Unary(Rc::new(UnaryExpr {
                 operator,
                 right: Rc::new(right),
-            })));
+            }));
+            return Ok(self.mark(start, unary));
+        }
+        self.postfix()
+    }
+
+    // `i++` is sugar for `(i = i + 1) - 1`: it assigns the new value but evaluates to the old
+    // one. The generated operators keep the `++`/`--` lexeme so errors point at what was written.
+    fn postfix(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
+        let expr = self.call()?;
+        if !self.is_match(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            return Ok(expr);
         }
-        self.call()
+        let token = self.previous().clone();
+        let Expr::Variable(variable) = expr else {
+            return Err(self.error(token, "Invalid increment target."));
+        };
+        let (step, undo) = if token.ttype == TokenType::PlusPlus {
+            (TokenType::Plus, TokenType::Minus)
+        } else {
+            (TokenType::Minus, TokenType::Plus)
+        };
+        let operator =
+            |ttype| Token::new(ttype, token.lexeme.clone(), None, token.line, token.column);
+        let one = || {
+            Rc::new(Literal(Rc::new(LiteralExpr {
+                value: Some(Object::Number(1.0)),
+            })))
+        };
+        let assign = Expr::Assign(Rc::new(AssignExpr {
+            name: variable.name.clone(),
+            value: Rc::new(Binary(Rc::new(BinaryExpr {
+                left: Rc::new(Expr::Variable(variable)),
+                operator: operator(step),
+                right: one(),
+            }))),
+        }));
+        let increment = Binary(Rc::new(BinaryExpr {
+            left: Rc::new(assign),
+            operator: operator(undo),
+            right: one(),
+        }));
+        Ok(self.mark(start, increment))
     }
 
+    // Marks the span of every node from here down to primary(), since each one starts at the
+    // first token of the primary expression.
     fn call(&mut self) -> Result<Expr, LoxResult> {
-        let mut expr = self.primary()?;
+        let start = self.current;
+        let primary = self.primary()?;
+        let mut expr = self.mark(start, primary);
         loop {
             if self.is_match(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+                expr = self.mark(start, expr);
+            } else if self.is_match(&[TokenType::Dot]) {
+                let name = self
+                    .consume(TokenType::Identifier, "Expect property name after '.'.")?
+                    .clone();
+                expr = Expr::Get(Rc::new(GetExpr {
+                    object: Rc::new(expr),
+                    name,
+                }));
+                expr = self.mark(start, expr);
+            } else if self.is_match(&[TokenType::LeftBracket]) {
+                let bracket = self.previous().clone();
+                let index = Rc::new(self.expression()?);
+                self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index(Rc::new(IndexExpr {
+                    object: Rc::new(expr),
+                    bracket,
+                    index,
+                }));
+                expr = self.mark(start, expr);
             } else {
                 break;
             }
@@ -431,9 +849,10 @@ impl<'a> Parser<'a> {
     fn finish_call(&mut self, callee: Expr) -> Result<Expr, LoxResult> {
         let mut arguments = Vec::new();
         if !self.check(TokenType::RightParen) {
-            arguments.push(Rc::new(self.expression()?));
-            while self.is_match(&[TokenType::Comma]) {
-                arguments.push(Rc::new(self.expression()?));
+            arguments.push(Rc::new(self.assignment()?));
+            // a comma may also end the list: f(1, 2,)
+            while self.is_match(&[TokenType::Comma]) && !self.check(TokenType::RightParen) {
+                arguments.push(Rc::new(self.assignment()?));
             }
         }
         let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
@@ -471,8 +890,76 @@ impl<'a> Parser<'a> {
         if self.is_match(&[TokenType::LeftParen]) {
             let expr = Rc::new(self.expression()?);
             self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
-            return Ok(Expr::Grouping(Rc::new(GroupingExpr {
-                expression: expr,
+            return Ok(Expr::Grouping(Rc::new(GroupingExpr { expression: expr })));
+        }
+        if self.is_match(&[TokenType::LeftBracket]) {
+            let bracket = self.previous().clone();
+            let mut elements = Vec::new();
+            if !self.check(TokenType::RightBracket) {
+                elements.push(Rc::new(self.assignment()?));
+                while self.is_match(&[TokenType::Comma]) {
+                    elements.push(Rc::new(self.assignment()?));
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+            return Ok(Expr::Array(Rc::new(ArrayExpr { bracket, elements })));
+        }
+        // A statement starting with '{' is a block. In expression position '{' starts a map
+        // literal, or a block expression when what follows is not `key:`.
+        if self.is_match(&[TokenType::LeftBrace]) {
+            if !self.is_map_literal() {
+                return self.block_expression();
+            }
+            let brace = self.previous().clone();
+            let (mut keys, mut values) = (Vec::new(), Vec::new());
+            if !self.check(TokenType::RightBrace) {
+                loop {
+                    keys.push(Rc::new(self.assignment()?));
+                    self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                    values.push(Rc::new(self.assignment()?));
+                    if !self.is_match(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+            return Ok(Expr::Map(Rc::new(MapExpr {
+                brace,
+                keys,
+                values,
+            })));
+        }
+        // A lambda: `fun (a, b) { ... }` used as a value. It is named "anonymous" for printing, and
+        // is_lambda tells it apart from a function really called that.
+        if self.is_match(&[TokenType::Fun]) {
+            let keyword = self.previous();
+            let name = Token::new(
+                TokenType::Identifier,
+                "anonymous".to_string(),
+                None,
+                keyword.line,
+                keyword.column,
+            );
+            self.consume(TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+            let declaration = FunctionStmt {
+                is_lambda: true,
+                ..self.function_body(name, "function")?
+            };
+            return Ok(Expr::Function(Rc::new(FunctionExpr {
+                declaration: Rc::new(declaration),
+            })));
+        }
+        if self.is_match(&[TokenType::Super]) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self
+                .consume(TokenType::Identifier, "Expect superclass method name.")?
+                .clone();
+            return Ok(Expr::Super(Rc::new(SuperExpr { keyword, method })));
+        }
+        if self.is_match(&[TokenType::This]) {
+            return Ok(Expr::This(Rc::new(ThisExpr {
+                keyword: self.previous().clone(),
             })));
         }
         if self.is_match(&[TokenType::Identifier]) {
@@ -480,11 +967,78 @@ impl<'a> Parser<'a> {
                 name: self.previous().clone(),
             })));
         }
-        Err(LoxResult::pares_error(
-            self.peek().clone(),
-            "Expect expression.",
-        ))
+        let peek = self.peek().clone();
+        Err(self.error(peek, "Expect expression."))
     }
+    // Just after a '{' in expression position: `{}` and `{key: ...` are maps. Finding out
+    // means parsing the first key and coming back, forgetting any errors it ran into.
+    fn is_map_literal(&mut self) -> bool {
+        if self.check(TokenType::RightBrace) {
+            return true;
+        }
+        let (current, errors, incomplete) = (self.current, self.errors.len(), self.incomplete);
+        let is_map = self.assignment().is_ok() && self.check(TokenType::Colon);
+        self.current = current;
+        self.errors.truncate(errors);
+        self.incomplete = incomplete;
+        is_map
+    }
+
+    // `{ var x = compute(); x * 2 }`: the statements run in a scope of their own and the block
+    // is worth the expression at its end, written without a ';', or nil if there is none.
+    fn block_expression(&mut self) -> Result<Expr, LoxResult> {
+        let mut statements = Vec::new();
+        let mut value = None;
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            if !self.starts_expression() {
+                statements.push(self.declaration()?);
+                continue;
+            }
+            let start = self.current;
+            let expr = self.expression()?;
+            if self.check(TokenType::RightBrace) {
+                value = Some(Rc::new(expr));
+                break;
+            }
+            // a nested block needs no ';' after it, just as a block statement doesn't
+            if !matches!(expr, Expr::Block(_)) {
+                self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+            }
+            let statement = Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
+                expression: Rc::new(expr),
+            })));
+            statements.push(self.mark_stmt(start, statement));
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(Expr::Block(Rc::new(BlockExpr {
+            statements: Rc::new(statements),
+            value,
+        })))
+    }
+
+    // Whether the next statement is an expression, rather than a declaration or a statement
+    // that starts with a keyword or a label.
+    fn starts_expression(&self) -> bool {
+        match self.peek().ttype {
+            TokenType::Class
+            | TokenType::Var
+            | TokenType::Const
+            | TokenType::Break
+            | TokenType::Continue
+            | TokenType::For
+            | TokenType::If
+            | TokenType::Import
+            | TokenType::Print
+            | TokenType::Return
+            | TokenType::Try
+            | TokenType::While
+            | TokenType::Do => false,
+            TokenType::Fun => !self.check_next(TokenType::Identifier),
+            TokenType::Identifier => !self.check_next(TokenType::Colon),
+            _ => true,
+        }
+    }
+
     // consume checks if the current token matches the given token type
     fn consume(&mut self, ttype: TokenType, message: &str) -> Result<&Token, LoxResult> {
         if self.check(ttype) {
@@ -496,15 +1050,19 @@ impl<'a> Parser<'a> {
     //It first parses a comparison expression, then checks for equality operators. If found, it creates a binary expression.
     //Example: a == b != c would be parsed as ((a == b) != c).
     fn equality(&mut self) -> Result<Expr, LoxResult> {
+        let start = self.current;
         let mut expr = self.comparison()?;
         while self.is_match(&[TokenType::BangEqual, TokenType::EqualEqual]) {
             let operator = self.previous().clone();
             let right = self.comparison()?;
-            expr = Binary(Rc::new(BinaryExpr {
-                left: Rc::new(expr),
-                operator,
-                right: Rc::new(right),
-            }));
+            expr = self.mark(
+                start,
+                Binary(Rc::new(BinaryExpr {
+                    left: Rc::new(expr),
+                    operator,
+                    right: Rc::new(right),
+                })),
+            );
         }
 
         Ok(expr)
@@ -527,6 +1085,14 @@ impl<'a> Parser<'a> {
         self.peek().ttype == ttype
     }
 
+    // Looks one token past the current one.
+    fn check_next(&self, ttype: TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.ttype == ttype,
+            None => false,
+        }
+    }
+
     /// Advances the parser to the next token and returns the previous token.
     /// This method is used to consume the current token and move the parser forward.
     /// If the parser is already at the end of the token stream, this method will not advance further.
@@ -550,11 +1116,48 @@ impl<'a> Parser<'a> {
     }
 
     fn error(&mut self, token: Token, message: &str) -> LoxResult {
-        self.had_error = true;
-        LoxResult::pares_error(token, message)
+        if self.repl && token.ttype == TokenType::Eof {
+            // The REPL reads another line and parses again rather than reporting this.
+            self.incomplete = true;
+        }
+        let err = LoxResult::pares_error(token, message);
+        self.errors.push(err.clone());
+        err
+    }
+    // Records that expr covers the tokens from start through the one just consumed.
+    fn mark(&mut self, start: usize, expr: Expr) -> Expr {
+        let span = Span::between(&self.tokens[start], self.previous());
+        self.spans.insert_expr(expr.clone(), span);
+        expr
+    }
+
+    fn mark_stmt(&mut self, start: usize, stmt: Rc<Stmt>) -> Rc<Stmt> {
+        let span = Span::between(&self.tokens[start], self.previous());
+        self.spans.insert_stmt((*stmt).clone(), span);
+        stmt
+    }
+
+    // Where in the source each node of the last parse() came from.
+    pub fn spans(&self) -> &Spans {
+        &self.spans
+    }
+
+    pub fn take_spans(&mut self) -> Spans {
+        std::mem::take(&mut self.spans)
     }
+
     pub fn success(&self) -> bool {
-        !self.had_error
+        self.errors.is_empty()
+    }
+
+    // Every error found by the last parse(), in source order.
+    pub fn errors(&self) -> &[LoxResult] {
+        &self.errors
+    }
+
+    // True when a REPL parse failed only because the input ended mid-statement.
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
     }
 
     /// Synchronizes the parser by advancing to the next token and skipping tokens until a valid statement is found.
@@ -572,11 +1175,15 @@ impl<'a> Parser<'a> {
                 TokenType::Class
                     | TokenType::Fun
                     | TokenType::Var
+                    | TokenType::Const
                     | TokenType::For
+                    | TokenType::Do
                     | TokenType::If
                     | TokenType::While
                     | TokenType::Print
                     | TokenType::Return
+                    | TokenType::Try
+                    | TokenType::Import
             ) {
                 return;
             }
@@ -584,3 +1191,803 @@ impl<'a> Parser<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a token stream by hand so each test controls exactly which mistake the parser sees.
+    fn tokens(spec: &[(TokenType, &str)]) -> Vec<Token> {
+        let mut tokens: Vec<Token> = spec
+            .iter()
+            .map(|(ttype, lexeme)| {
+                let literal = match ttype {
+                    TokenType::Number => Some(Object::Number(lexeme.parse().unwrap())),
+                    TokenType::String => Some(Object::String(lexeme.to_string())),
+                    _ => None,
+                };
+                Token::new(*ttype, lexeme.to_string(), literal, 1, 1)
+            })
+            .collect();
+        tokens.push(Token::eof(1, 1));
+        tokens
+    }
+
+    fn is_print(stmt: &Stmt) -> bool {
+        matches!(stmt, Stmt::Print(_))
+    }
+
+    #[test]
+    fn recovers_from_missing_semicolon() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (Var, "var"),
+            (Identifier, "a"),
+            (Equal, "="),
+            (Number, "1"),
+            (Var, "var"),
+            (Identifier, "b"),
+            (Equal, "="),
+            (Number, "2"),
+            (Semicolon, ";"),
+            (Print, "print"),
+            (Number, "3"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert_eq!(parser.errors.len(), 1);
+        assert!(!parser.success());
+        assert_eq!(statements.len(), 1);
+        assert!(is_print(&statements[0]));
+    }
+
+    #[test]
+    fn recovers_from_missing_paren() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (If, "if"),
+            (LeftParen, "("),
+            (True, "true"),
+            (Print, "print"),
+            (Number, "1"),
+            (Semicolon, ";"),
+            (Print, "print"),
+            (Number, "2"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert_eq!(parser.errors.len(), 1);
+        assert_eq!(statements.len(), 1);
+        assert!(is_print(&statements[0]));
+    }
+
+    #[test]
+    fn recovers_from_extra_closing_brace() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (Print, "print"),
+            (Number, "1"),
+            (Semicolon, ";"),
+            (RightBrace, "}"),
+            (Print, "print"),
+            (Number, "2"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert_eq!(parser.errors.len(), 1);
+        assert_eq!(statements.len(), 2);
+        assert!(statements.iter().all(|stmt| is_print(stmt)));
+    }
+
+    #[test]
+    fn recovers_from_keyword_used_as_identifier() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (Var, "var"),
+            (Class, "class"),
+            (Equal, "="),
+            (Number, "1"),
+            (Semicolon, ";"),
+            (Print, "print"),
+            (Number, "2"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert_eq!(parser.errors.len(), 1);
+        assert_eq!(statements.len(), 1);
+        assert!(is_print(&statements[0]));
+    }
+
+    #[test]
+    fn reports_every_error_in_one_pass() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (Var, "var"),
+            (Equal, "="),
+            (Number, "1"),
+            (Semicolon, ";"),
+            (Print, "print"),
+            (Semicolon, ";"),
+            (Var, "var"),
+            (Identifier, "c"),
+            (Equal, "="),
+            (Number, "3"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert_eq!(parser.errors.len(), 2);
+        assert_eq!(statements.len(), 1);
+        assert!(matches!(&*statements[0], Stmt::Var(_)));
+    }
+
+    #[test]
+    fn recovery_resumes_at_every_statement_keyword() {
+        let statements = [
+            "const x = 2;",
+            "do { } while (false);",
+            "try { } catch (e) { }",
+            "import \"lib.lox\";",
+        ];
+        for statement in statements {
+            let source = format!("var = 1 {}", statement);
+            let mut scanner = crate::scanner::Scanner::new(source);
+            let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+            let parsed = parser.parse().unwrap();
+            assert_eq!(parser.errors.len(), 1, "after the error in {:?}", statement);
+            assert_eq!(parsed.len(), 1, "{:?} was skipped", statement);
+        }
+    }
+
+    #[test]
+    fn valid_program_has_no_errors() {
+        use TokenType::*;
+        let tokens = tokens(&[(Print, "print"), (String, "ok"), (Semicolon, ";")]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        assert_eq!(statements.len(), 1);
+    }
+
+    #[test]
+    fn repl_parser_flags_input_ending_mid_statement() {
+        use TokenType::*;
+        let tokens = tokens(&[(Fun, "fun"), (Identifier, "f"), (LeftParen, "(")]);
+        let mut parser = Parser::for_repl(&tokens);
+        parser.parse().unwrap();
+        assert!(parser.is_incomplete());
+
+        let mut parser = Parser::new(&tokens);
+        parser.parse().unwrap();
+        assert!(!parser.is_incomplete());
+        assert!(!parser.success());
+    }
+
+    #[test]
+    fn repl_parser_allows_final_expression_without_semicolon() {
+        use TokenType::*;
+        let tokens = tokens(&[(Number, "1"), (Plus, "+"), (Number, "2")]);
+        let mut parser = Parser::for_repl(&tokens);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        assert_eq!(statements.len(), 1);
+    }
+
+    #[test]
+    fn parses_map_literals() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (Var, "var"),
+            (Identifier, "m"),
+            (Equal, "="),
+            (LeftBrace, "{"),
+            (String, "a"),
+            (Colon, ":"),
+            (Number, "1"),
+            (Comma, ","),
+            (String, "b"),
+            (Colon, ":"),
+            (Number, "2"),
+            (RightBrace, "}"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        match statements[0].as_ref() {
+            Stmt::Var(var) => match var.initializer.as_deref() {
+                Some(Expr::Map(map)) => {
+                    assert_eq!(map.keys.len(), 2);
+                    assert_eq!(map.values.len(), 2);
+                }
+                _ => panic!("expected a map literal"),
+            },
+            _ => panic!("expected a var statement"),
+        }
+    }
+
+    #[test]
+    fn map_entries_need_a_colon() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (Print, "print"),
+            (LeftBrace, "{"),
+            (String, "a"),
+            (Number, "1"),
+            (RightBrace, "}"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        parser.parse().unwrap();
+        assert_eq!(parser.errors.len(), 1);
+    }
+
+    #[test]
+    fn rejects_chained_comparisons() {
+        use TokenType::*;
+        let chained = tokens(&[
+            (Print, "print"),
+            (Identifier, "a"),
+            (Less, "<"),
+            (Identifier, "b"),
+            (Less, "<"),
+            (Identifier, "c"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&chained);
+        parser.parse().unwrap();
+        match parser.errors() {
+            [LoxResult::ParseError { token, message }] => {
+                assert_eq!(token.lexeme, "<");
+                assert_eq!(
+                    message,
+                    "Chained comparison is not allowed; use explicit parentheses or logical operators."
+                );
+            }
+            errors => panic!("expected one parse error, got {:?}", errors),
+        }
+
+        let explicit = tokens(&[
+            (Print, "print"),
+            (LeftParen, "("),
+            (Identifier, "a"),
+            (Less, "<"),
+            (Identifier, "b"),
+            (RightParen, ")"),
+            (And, "and"),
+            (LeftParen, "("),
+            (Identifier, "b"),
+            (GreaterEqual, ">="),
+            (Identifier, "c"),
+            (RightParen, ")"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&explicit);
+        parser.parse().unwrap();
+        assert!(parser.success());
+    }
+
+    #[test]
+    fn increments_need_a_variable() {
+        use TokenType::*;
+        let tokens = tokens(&[(Number, "5"), (PlusPlus, "++"), (Semicolon, ";")]);
+        let mut parser = Parser::new(&tokens);
+        parser.parse().unwrap();
+        match parser.errors() {
+            [LoxResult::ParseError { message, .. }] => {
+                assert_eq!(message, "Invalid increment target.")
+            }
+            errors => panic!("expected one parse error, got {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn lists_of_arguments_and_parameters_may_end_with_a_comma() {
+        use TokenType::*;
+        let call = tokens(&[
+            (Identifier, "f"),
+            (LeftParen, "("),
+            (Number, "1"),
+            (Comma, ","),
+            (Number, "2"),
+            (Comma, ","),
+            (RightParen, ")"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&call);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        match statements[0].as_ref() {
+            Stmt::Expression(stmt) => match stmt.expression.as_ref() {
+                Expr::Call(call) => assert_eq!(call.arguments.len(), 2),
+                _ => panic!("expected a call"),
+            },
+            _ => panic!("expected an expression statement"),
+        }
+
+        let function = tokens(&[
+            (Fun, "fun"),
+            (Identifier, "g"),
+            (LeftParen, "("),
+            (Identifier, "a"),
+            (Comma, ","),
+            (Identifier, "b"),
+            (Comma, ","),
+            (RightParen, ")"),
+            (LeftBrace, "{"),
+            (RightBrace, "}"),
+        ]);
+        let mut parser = Parser::new(&function);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        match statements[0].as_ref() {
+            Stmt::Function(function) => assert_eq!(function.params.len(), 2),
+            _ => panic!("expected a function declaration"),
+        }
+
+        let leading = tokens(&[
+            (Identifier, "f"),
+            (LeftParen, "("),
+            (Comma, ","),
+            (RightParen, ")"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&leading);
+        parser.parse().unwrap();
+        assert!(!parser.success());
+    }
+
+    #[test]
+    fn commas_separate_arguments_but_sequence_grouped_expressions() {
+        use TokenType::*;
+        let call = tokens(&[
+            (Identifier, "f"),
+            (LeftParen, "("),
+            (Number, "1"),
+            (Comma, ","),
+            (Number, "2"),
+            (RightParen, ")"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&call);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        let Stmt::Expression(stmt) = statements[0].as_ref() else {
+            panic!("expected an expression statement");
+        };
+        match stmt.expression.as_ref() {
+            Expr::Call(call) => assert_eq!(call.arguments.len(), 2),
+            _ => panic!("expected a call"),
+        }
+
+        let grouping = tokens(&[
+            (LeftParen, "("),
+            (Number, "1"),
+            (Comma, ","),
+            (Number, "2"),
+            (RightParen, ")"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&grouping);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        let Stmt::Expression(stmt) = statements[0].as_ref() else {
+            panic!("expected an expression statement");
+        };
+        match stmt.expression.as_ref() {
+            Expr::Grouping(group) => assert!(matches!(group.expression.as_ref(), Expr::Comma(_))),
+            _ => panic!("expected a grouping"),
+        }
+    }
+
+    #[test]
+    fn negation_binds_tighter_than_equality() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (Print, "print"),
+            (Bang, "!"),
+            (Identifier, "a"),
+            (EqualEqual, "=="),
+            (Bang, "not"),
+            (Bang, "not"),
+            (Identifier, "b"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        let Stmt::Print(print) = statements[0].as_ref() else {
+            panic!("expected a print statement");
+        };
+        let Expr::Binary(equality) = print.expression.as_ref() else {
+            panic!("expected == at the top");
+        };
+        assert_eq!(equality.operator.ttype, EqualEqual);
+        assert!(
+            matches!(equality.left.as_ref(), Expr::Unary(not) if matches!(not.right.as_ref(), Expr::Variable(_)))
+        );
+        let Expr::Unary(outer) = equality.right.as_ref() else {
+            panic!("expected the right side to be negated");
+        };
+        assert!(matches!(outer.right.as_ref(), Expr::Unary(_)));
+    }
+
+    #[test]
+    fn only_the_last_parameter_may_be_a_rest_parameter() {
+        use TokenType::*;
+        let rest_last = tokens(&[
+            (Fun, "fun"),
+            (Identifier, "f"),
+            (LeftParen, "("),
+            (Identifier, "a"),
+            (Comma, ","),
+            (DotDotDot, "..."),
+            (Identifier, "b"),
+            (RightParen, ")"),
+            (LeftBrace, "{"),
+            (RightBrace, "}"),
+        ]);
+        let mut parser = Parser::new(&rest_last);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        match statements[0].as_ref() {
+            Stmt::Function(function) => {
+                assert_eq!(function.params.len(), 2);
+                assert!(function.variadic);
+            }
+            _ => panic!("expected a function declaration"),
+        }
+
+        let rest_first = tokens(&[
+            (Fun, "fun"),
+            (Identifier, "f"),
+            (LeftParen, "("),
+            (DotDotDot, "..."),
+            (Identifier, "a"),
+            (Comma, ","),
+            (Identifier, "b"),
+            (RightParen, ")"),
+            (LeftBrace, "{"),
+            (RightBrace, "}"),
+        ]);
+        let mut parser = Parser::new(&rest_first);
+        parser.parse().unwrap();
+        assert!(!parser.success());
+        match &parser.errors()[0] {
+            LoxResult::ParseError { message, .. } => {
+                assert_eq!(message, "Rest parameter must be last.")
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn do_while_parses_to_a_post_condition_loop() {
+        use TokenType::*;
+        let loop_tokens = tokens(&[
+            (Do, "do"),
+            (Print, "print"),
+            (Identifier, "i"),
+            (Semicolon, ";"),
+            (While, "while"),
+            (LeftParen, "("),
+            (False, "false"),
+            (RightParen, ")"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&loop_tokens);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        assert_eq!(statements.len(), 1);
+        let Stmt::While(do_while) = statements[0].as_ref() else {
+            panic!("expected a loop");
+        };
+        assert!(do_while.do_while);
+        assert!(do_while.increment.is_none());
+        assert!(matches!(do_while.body.as_ref(), Stmt::Print(_)));
+        assert!(matches!(do_while.condition.as_ref(), Expr::Literal(_)));
+
+        let missing_semicolon = tokens(&[
+            (Do, "do"),
+            (LeftBrace, "{"),
+            (RightBrace, "}"),
+            (While, "while"),
+            (LeftParen, "("),
+            (True, "true"),
+            (RightParen, ")"),
+        ]);
+        let mut parser = Parser::new(&missing_semicolon);
+        parser.parse().unwrap();
+        assert!(!parser.success());
+    }
+
+    #[test]
+    fn parse_expression_reads_a_lone_expression() {
+        use TokenType::*;
+        let arithmetic = tokens(&[
+            (Number, "1"),
+            (Plus, "+"),
+            (Number, "2"),
+            (Star, "*"),
+            (Number, "3"),
+        ]);
+        let mut parser = Parser::new(&arithmetic);
+        let expr = parser.parse_expression().unwrap();
+        assert!(parser.success());
+        assert_eq!(
+            crate::ast_printer::AstPrinter.print(&expr).unwrap(),
+            "(+ 1 (* 2 3))"
+        );
+
+        let incomplete = tokens(&[(Number, "1"), (Plus, "+")]);
+        let mut parser = Parser::new(&incomplete);
+        assert!(parser.parse_expression().is_err());
+        assert!(!parser.success());
+
+        let trailing = tokens(&[(Number, "1"), (Number, "2")]);
+        let mut parser = Parser::new(&trailing);
+        match parser.parse_expression() {
+            Err(LoxResult::ParseError { token, message }) => {
+                assert_eq!(token.lexeme, "2");
+                assert_eq!(message, "Expect end of expression.");
+            }
+            _ => panic!("expected the trailing token to be rejected"),
+        }
+    }
+
+    #[test]
+    fn const_requires_an_initializer() {
+        use TokenType::*;
+        let declaration = tokens(&[
+            (Const, "const"),
+            (Identifier, "PI"),
+            (Equal, "="),
+            (Number, "3"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&declaration);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        match statements[0].as_ref() {
+            Stmt::Var(var) => assert!(var.is_const),
+            _ => panic!("expected a declaration"),
+        }
+
+        let uninitialized = tokens(&[(Const, "const"), (Identifier, "PI"), (Semicolon, ";")]);
+        let mut parser = Parser::new(&uninitialized);
+        parser.parse().unwrap();
+        match parser.errors() {
+            [LoxResult::ParseError { message, .. }] => {
+                assert_eq!(message, "Expect '=' after constant name.")
+            }
+            errors => panic!("expected one parse error, got {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn for_without_clauses_loops_on_true() {
+        use TokenType::*;
+        let forever = tokens(&[
+            (For, "for"),
+            (LeftParen, "("),
+            (Semicolon, ";"),
+            (Semicolon, ";"),
+            (RightParen, ")"),
+            (Break, "break"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&forever);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        let Stmt::While(forever) = statements[0].as_ref() else {
+            panic!("expected a bare loop with no initializer block");
+        };
+        assert!(matches!(
+            forever.condition.as_ref(),
+            Expr::Literal(literal) if literal.value == Some(Object::Bool(true))
+        ));
+        assert!(forever.increment.is_none());
+        assert!(matches!(forever.body.as_ref(), Stmt::Break(_)));
+
+        let stepping = tokens(&[
+            (For, "for"),
+            (LeftParen, "("),
+            (Semicolon, ";"),
+            (Semicolon, ";"),
+            (Identifier, "i"),
+            (Equal, "="),
+            (Number, "1"),
+            (RightParen, ")"),
+            (Break, "break"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&stepping);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        let Stmt::While(stepping) = statements[0].as_ref() else {
+            panic!("expected a loop");
+        };
+        assert!(matches!(stepping.condition.as_ref(), Expr::Literal(_)));
+        assert!(matches!(
+            stepping.increment.as_deref(),
+            Some(Expr::Assign(_))
+        ));
+    }
+
+    #[test]
+    fn var_may_declare_several_names() {
+        use TokenType::*;
+        let declaration = tokens(&[
+            (Var, "var"),
+            (Identifier, "a"),
+            (Equal, "="),
+            (Number, "1"),
+            (Comma, ","),
+            (Identifier, "b"),
+            (Equal, "="),
+            (Number, "2"),
+            (Comma, ","),
+            (Identifier, "c"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&declaration);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        assert_eq!(statements.len(), 1);
+        let Stmt::MultiVar(multi) = statements[0].as_ref() else {
+            panic!("expected one multiple declaration");
+        };
+        let declared: Vec<(&str, bool)> = multi
+            .declarations
+            .iter()
+            .map(|declaration| match declaration.as_ref() {
+                Stmt::Var(var) => (var.name.lexeme.as_str(), var.initializer.is_some()),
+                _ => panic!("expected a variable declaration"),
+            })
+            .collect();
+        assert_eq!(declared, vec![("a", true), ("b", true), ("c", false)]);
+
+        let single = tokens(&[(Var, "var"), (Identifier, "a"), (Semicolon, ";")]);
+        let mut parser = Parser::new(&single);
+        let statements = parser.parse().unwrap();
+        assert!(matches!(statements[0].as_ref(), Stmt::Var(_)));
+
+        let trailing = tokens(&[
+            (Var, "var"),
+            (Identifier, "a"),
+            (Comma, ","),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&trailing);
+        parser.parse().unwrap();
+        match parser.errors() {
+            [LoxResult::ParseError { token, message }] => {
+                assert_eq!(token.lexeme, ";");
+                assert_eq!(message, "Expect variable name.");
+            }
+            errors => panic!("expected one parse error, got {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn braces_in_expression_position_are_maps_or_block_expressions() {
+        let initializer = |source: &str| {
+            let mut scanner = crate::scanner::Scanner::new(format!("var v = {};", source));
+            let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+            let statements = parser.parse().unwrap();
+            assert!(parser.success(), "{:?}", parser.errors());
+            match statements[0].as_ref() {
+                Stmt::Var(var) => Rc::clone(var.initializer.as_ref().unwrap()),
+                _ => panic!("expected a declaration"),
+            }
+        };
+        assert!(matches!(initializer("{}").as_ref(), Expr::Map(_)));
+        assert!(matches!(initializer("{\"a\": 1}").as_ref(), Expr::Map(_)));
+
+        let Expr::Block(block) = initializer("{ var x = 1; print x; x * 2 }")
+            .as_ref()
+            .clone()
+        else {
+            panic!("expected a block expression");
+        };
+        assert_eq!(block.statements.len(), 2);
+        assert!(matches!(block.value.as_deref(), Some(Expr::Binary(_))));
+
+        let Expr::Block(block) = initializer("{ var x = 1; }").as_ref().clone() else {
+            panic!("expected a block expression");
+        };
+        assert!(block.value.is_none());
+
+        // a nested block is a statement without a ';' unless it ends the block
+        let Expr::Block(outer) = initializer("{ { print 1; } { 2 } }").as_ref().clone() else {
+            panic!("expected a block expression");
+        };
+        assert_eq!(outer.statements.len(), 1);
+        assert!(matches!(outer.value.as_deref(), Some(Expr::Block(_))));
+    }
+
+    #[test]
+    fn braces_in_statement_position_are_blocks() {
+        let mut scanner = crate::scanner::Scanner::new("{ } { print 1; }".to_string());
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = parser.parse().unwrap();
+        assert!(parser.success(), "{:?}", parser.errors());
+        assert_eq!(statements.len(), 2);
+        match (statements[0].as_ref(), statements[1].as_ref()) {
+            (Stmt::Block(empty), Stmt::Block(block)) => {
+                assert!(empty.statements.is_empty());
+                assert_eq!(block.statements.len(), 1);
+            }
+            _ => panic!("expected two block statements"),
+        }
+    }
+
+    #[test]
+    fn spans_cover_a_node_from_its_first_token_to_its_last() {
+        let mut scanner = crate::scanner::Scanner::new("print (1 + 2) * 3;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+        let spans = parser.spans();
+        let span = |start_col, end_col| {
+            Some(Span {
+                start_line: 1,
+                start_col,
+                end_line: 1,
+                end_col,
+            })
+        };
+        assert_eq!(spans.stmt(&statements[0]), span(1, 18));
+        let Stmt::Print(print) = statements[0].as_ref() else {
+            panic!("expected a print statement");
+        };
+        let Expr::Binary(product) = print.expression.as_ref() else {
+            panic!("expected a product");
+        };
+        assert_eq!(spans.expr(&print.expression), span(7, 17));
+        assert_eq!(spans.expr(&product.left), span(7, 13));
+        let Expr::Grouping(group) = product.left.as_ref() else {
+            panic!("expected a grouping");
+        };
+        assert_eq!(spans.expr(&group.expression), span(8, 12));
+        assert_eq!(spans.expr(&product.right), span(17, 17));
+    }
+
+    #[test]
+    fn dangling_else_binds_to_the_innermost_if() {
+        use TokenType::*;
+        let tokens = tokens(&[
+            (If, "if"),
+            (LeftParen, "("),
+            (Identifier, "a"),
+            (RightParen, ")"),
+            (If, "if"),
+            (LeftParen, "("),
+            (Identifier, "b"),
+            (RightParen, ")"),
+            (Print, "print"),
+            (Number, "1"),
+            (Semicolon, ";"),
+            (Else, "else"),
+            (Print, "print"),
+            (Number, "2"),
+            (Semicolon, ";"),
+        ]);
+        let mut parser = Parser::new(&tokens);
+        let statements = parser.parse().unwrap();
+        assert!(parser.success());
+        let Stmt::If(outer) = statements[0].as_ref() else {
+            panic!("expected an if statement");
+        };
+        assert!(outer.else_branch.is_none());
+        let Stmt::If(inner) = outer.then_branch.as_ref() else {
+            panic!("expected the then branch to be the inner if");
+        };
+        assert!(is_print(&inner.then_branch));
+        assert!(inner.else_branch.as_deref().is_some_and(is_print));
+    }
+}
@@ -11,30 +11,127 @@ use crate::token::*;
 
 pub struct Resolver<'a> {
     interpreter: &'a Interpreter,
-    scopes: RefCell<Vec<RefCell<HashMap<String, bool>>>>,
+    scopes: RefCell<Vec<RefCell<HashMap<String, Local>>>>,
+    current_function: RefCell<FunctionType>,
+    current_class: RefCell<ClassType>,
+    // Labels of the loops enclosing the current statement, within the current function.
+    labels: RefCell<Vec<String>>,
+    // Problems that don't stop the program outside strict mode, for the caller to report.
+    warnings: RefCell<Vec<LoxResult>>,
+}
+
+// A name declared in a local scope: whether its initializer has finished, and the slot it takes
+// in that scope's runtime environment. Slots are numbered in declaration order, the same order
+// the interpreter defines them in.
+struct Local {
+    defined: bool,
+    slot: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
 }
 
 impl<'a> StmtVisitor<()> for Resolver<'a> {
     fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<(), LoxResult> {
+        if *self.current_function.borrow() == FunctionType::None {
+            return Err(LoxResult::runtime_error(
+                &stmt.token,
+                "Can't return from top-level code.",
+            ));
+        }
         if let Some(value) = stmt.value.clone() {
+            if *self.current_function.borrow() == FunctionType::Initializer {
+                return Err(LoxResult::runtime_error(
+                    &stmt.token,
+                    "Can't return a value from an initializer.",
+                ));
+            }
             self.resolve_expr(value)?;
         }
         Ok(())
     }
-    fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), LoxResult> {
-        self.declare(&stmt.name);
+
+    fn visit_class_stmt(&self, _: Rc<Stmt>, stmt: &ClassStmt) -> Result<(), LoxResult> {
+        let enclosing_class = self.current_class.replace(ClassType::Class);
+        self.declare(&stmt.name)?;
         self.define(&stmt.name);
 
-        self.resolve_function(stmt)?;
+        if let Some(superclass) = &stmt.superclass {
+            if let Expr::Variable(variable) = superclass.deref() {
+                if variable.name.lexeme == stmt.name.lexeme {
+                    return Err(LoxResult::runtime_error(
+                        &variable.name,
+                        "A class can't inherit from itself.",
+                    ));
+                }
+            }
+            self.current_class.replace(ClassType::Subclass);
+            self.resolve_expr(superclass.clone())?;
+
+            self.begin_scope();
+            self.declare_implicit("super");
+        }
+
+        self.begin_scope();
+        self.declare_implicit("this");
 
+        for method in stmt.methods.iter() {
+            if let Stmt::Function(method) = method.deref() {
+                let declaration = if method.name.lexeme == "init" {
+                    FunctionType::Initializer
+                } else {
+                    FunctionType::Method
+                };
+                self.resolve_function(method, declaration)?;
+            }
+        }
+
+        self.end_scope();
+        if stmt.superclass.is_some() {
+            self.end_scope();
+        }
+        self.current_class.replace(enclosing_class);
         Ok(())
     }
-    fn visit_break_stmt(&self, _: Rc<Stmt>, _stmt: &BreakStmt) -> Result<(), LoxResult> {
+    fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), LoxResult> {
+        self.declare(&stmt.name)?;
+        self.define(&stmt.name);
+
+        self.resolve_function(stmt, FunctionType::Function)?;
+
         Ok(())
     }
+    fn visit_break_stmt(&self, _: Rc<Stmt>, stmt: &BreakStmt) -> Result<(), LoxResult> {
+        self.check_label(&stmt.label)
+    }
+    fn visit_continue_stmt(&self, _: Rc<Stmt>, stmt: &ContinueStmt) -> Result<(), LoxResult> {
+        self.check_label(&stmt.label)
+    }
     fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<(), LoxResult> {
         self.resolve_expr(stmt.condition.clone())?;
-        self.resolve_stmt(stmt.body.clone())?;
+        if let Some(label) = &stmt.label {
+            self.labels.borrow_mut().push(label.lexeme.clone());
+        }
+        let body = self.resolve_stmt(stmt.body.clone());
+        if stmt.label.is_some() {
+            self.labels.borrow_mut().pop();
+        }
+        body?;
+        if let Some(increment) = stmt.increment.clone() {
+            self.resolve_expr(increment)?;
+        }
         Ok(())
     }
     fn visit_if_stmt(&self, _: Rc<Stmt>, stmt: &IfStmt) -> Result<(), LoxResult> {
@@ -57,22 +154,72 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
         self.resolve_expr(stmt.expression.clone())?;
         Ok(())
     }
+    // the imported file is resolved on its own when it runs
+    fn visit_import_stmt(&self, _: Rc<Stmt>, _stmt: &ImportStmt) -> Result<(), LoxResult> {
+        Ok(())
+    }
+
     fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<(), LoxResult> {
         self.resolve_expr(stmt.expression.clone())?;
         Ok(())
     }
 
     fn visit_var_stmt(&self, _: Rc<Stmt>, stmt: &VarStmt) -> Result<(), LoxResult> {
-        self.declare(&stmt.name);
+        self.declare(&stmt.name)?;
         if let Some(init) = stmt.initializer.clone() {
             self.resolve_expr(init)?;
         }
         self.define(&stmt.name);
         Ok(())
     }
+
+    // The error variable is the first local of the catch block's scope.
+    fn visit_try_stmt(&self, _: Rc<Stmt>, stmt: &TryStmt) -> Result<(), LoxResult> {
+        self.begin_scope();
+        self.resolve(&stmt.try_body)?;
+        self.end_scope();
+
+        self.begin_scope();
+        self.declare(&stmt.error)?;
+        self.define(&stmt.error);
+        self.resolve(&stmt.catch_body)?;
+        self.end_scope();
+
+        if let Some(finally_body) = &stmt.finally_body {
+            self.begin_scope();
+            self.resolve(finally_body)?;
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    // each declaration is resolved in the current scope, so a later initializer sees the
+    // earlier names
+    fn visit_multivar_stmt(&self, _: Rc<Stmt>, stmt: &MultiVarStmt) -> Result<(), LoxResult> {
+        self.resolve(&stmt.declarations)
+    }
 }
 
 impl<'a> ExprVisitor<()> for Resolver<'a> {
+    fn visit_map_expr(&self, _: Rc<Expr>, expr: &MapExpr) -> Result<(), LoxResult> {
+        for (key, value) in expr.keys.iter().zip(expr.values.iter()) {
+            self.resolve_expr(key.clone())?;
+            self.resolve_expr(value.clone())?;
+        }
+        Ok(())
+    }
+
+    fn visit_function_expr(&self, _: Rc<Expr>, expr: &FunctionExpr) -> Result<(), LoxResult> {
+        self.resolve_function(&expr.declaration, FunctionType::Function)
+    }
+
+    fn visit_array_expr(&self, _: Rc<Expr>, expr: &ArrayExpr) -> Result<(), LoxResult> {
+        for element in expr.elements.iter() {
+            self.resolve_expr(element.clone())?;
+        }
+        Ok(())
+    }
+
     fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<(), LoxResult> {
         self.resolve_expr(expr.callee.clone())?;
 
@@ -82,6 +229,10 @@ impl<'a> ExprVisitor<()> for Resolver<'a> {
 
         Ok(())
     }
+    fn visit_comma_expr(&self, _: Rc<Expr>, expr: &CommaExpr) -> Result<(), LoxResult> {
+        self.resolve_expr(expr.left.clone())?;
+        self.resolve_expr(expr.right.clone())
+    }
     fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<(), LoxResult> {
         self.resolve_expr(expr.left.clone())?;
         self.resolve_expr(expr.right.clone())?;
@@ -97,6 +248,16 @@ impl<'a> ExprVisitor<()> for Resolver<'a> {
         Ok(())
     }
 
+    fn visit_block_expr(&self, _: Rc<Expr>, expr: &BlockExpr) -> Result<(), LoxResult> {
+        self.begin_scope();
+        self.resolve(&expr.statements)?;
+        if let Some(value) = expr.value.clone() {
+            self.resolve_expr(value)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
     fn visit_grouping_expr(&self, _: Rc<Expr>, expr: &GroupingExpr) -> Result<(), LoxResult> {
         self.resolve_expr(expr.expression.clone())?;
         Ok(())
@@ -111,16 +272,71 @@ impl<'a> ExprVisitor<()> for Resolver<'a> {
         Ok(())
     }
 
+    fn visit_index_expr(&self, _: Rc<Expr>, expr: &IndexExpr) -> Result<(), LoxResult> {
+        self.resolve_expr(expr.object.clone())?;
+        self.resolve_expr(expr.index.clone())?;
+        Ok(())
+    }
+
+    fn visit_indexset_expr(&self, _: Rc<Expr>, expr: &IndexSetExpr) -> Result<(), LoxResult> {
+        self.resolve_expr(expr.value.clone())?;
+        self.resolve_expr(expr.object.clone())?;
+        self.resolve_expr(expr.index.clone())?;
+        Ok(())
+    }
+
+    fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<(), LoxResult> {
+        self.resolve_expr(expr.object.clone())?;
+        Ok(())
+    }
+
+    fn visit_set_expr(&self, _: Rc<Expr>, expr: &SetExpr) -> Result<(), LoxResult> {
+        self.resolve_expr(expr.value.clone())?;
+        self.resolve_expr(expr.object.clone())?;
+        Ok(())
+    }
+
+    fn visit_super_expr(&self, wrapper: Rc<Expr>, expr: &SuperExpr) -> Result<(), LoxResult> {
+        match *self.current_class.borrow() {
+            ClassType::None => {
+                return Err(LoxResult::runtime_error(
+                    &expr.keyword,
+                    "Can't use 'super' outside of a class.",
+                ))
+            }
+            ClassType::Class => {
+                return Err(LoxResult::runtime_error(
+                    &expr.keyword,
+                    "Can't use 'super' in a class with no superclass.",
+                ))
+            }
+            ClassType::Subclass => {}
+        }
+        self.resolve_local(wrapper, &expr.keyword);
+        Ok(())
+    }
+
+    fn visit_this_expr(&self, wrapper: Rc<Expr>, expr: &ThisExpr) -> Result<(), LoxResult> {
+        if *self.current_class.borrow() == ClassType::None {
+            return Err(LoxResult::runtime_error(
+                &expr.keyword,
+                "Can't use 'this' outside of a class.",
+            ));
+        }
+        self.resolve_local(wrapper, &expr.keyword);
+        Ok(())
+    }
+
     fn visit_variable_expr(&self, wrapper: Rc<Expr>, expr: &VariableExpr) -> Result<(), LoxResult> {
         if !self.scopes.borrow().is_empty()
             && self
-            .scopes
-            .borrow()
-            .last()
-            .unwrap()
-            .borrow()
-            .get(&expr.name.lexeme)
-            == Some(&false)
+                .scopes
+                .borrow()
+                .last()
+                .unwrap()
+                .borrow()
+                .get(&expr.name.lexeme)
+                .is_some_and(|local| !local.defined)
         {
             Err(LoxResult::runtime_error(
                 &expr.name,
@@ -138,16 +354,47 @@ impl<'a> Resolver<'a> {
         Self {
             interpreter,
             scopes: RefCell::new(Vec::new()),
+            current_function: RefCell::new(FunctionType::None),
+            current_class: RefCell::new(ClassType::None),
+            labels: RefCell::new(Vec::new()),
+            warnings: RefCell::new(Vec::new()),
         }
     }
 
+    // Anything after a `return` in the same list of statements can never run. Only a return
+    // directly in the list counts, not one inside a branch, so nothing is flagged that some
+    // path might still reach.
     pub fn resolve(&self, statements: &Rc<Vec<Rc<Stmt>>>) -> Result<(), LoxResult> {
+        let mut returned: Option<&Token> = None;
         for statement in statements.deref() {
+            if let Some(token) = returned.take() {
+                self.warn(LoxResult::runtime_error(
+                    token,
+                    "Unreachable code after return.",
+                ))?;
+            }
             self.resolve_stmt(statement.clone())?;
+            if let Stmt::Return(stmt) = statement.deref() {
+                returned = Some(&stmt.token);
+            }
         }
         Ok(())
     }
 
+    // The warnings found so far, oldest first, leaving none behind.
+    pub fn take_warnings(&self) -> Vec<LoxResult> {
+        self.warnings.take()
+    }
+
+    // Strict mode treats a warning as an error.
+    fn warn(&self, warning: LoxResult) -> Result<(), LoxResult> {
+        if self.interpreter.is_strict() {
+            return Err(warning);
+        }
+        self.warnings.borrow_mut().push(warning);
+        Ok(())
+    }
+
     fn resolve_stmt(&self, stmt: Rc<Stmt>) -> Result<(), LoxResult> {
         stmt.accept(stmt.clone(), self)
     }
@@ -164,48 +411,274 @@ impl<'a> Resolver<'a> {
         self.scopes.borrow_mut().pop();
     }
 
-    fn declare(&self, name: &Token) {
-        if !self.scopes.borrow().is_empty() {
-            self.scopes
-                .borrow()
-                .last()
-                .unwrap()
-                .borrow_mut()
-                .insert(name.lexeme.clone(), false);
+    fn declare(&self, name: &Token) -> Result<(), LoxResult> {
+        if let Some(scope) = self.scopes.borrow().last() {
+            if scope.borrow().contains_key(&name.lexeme) {
+                return Err(LoxResult::runtime_error(
+                    name,
+                    "Already a variable with this name in this scope.",
+                ));
+            }
+            let slot = scope.borrow().len();
+            scope.borrow_mut().insert(
+                name.lexeme.clone(),
+                Local {
+                    defined: false,
+                    slot,
+                },
+            );
         }
+        Ok(())
     }
 
     fn define(&self, name: &Token) {
-        if !self.scopes.borrow().is_empty() {
-            self.scopes
-                .borrow()
-                .last()
-                .unwrap()
-                .borrow_mut()
-                .insert(name.lexeme.clone(), true);
+        if let Some(scope) = self.scopes.borrow().last() {
+            if let Some(local) = scope.borrow_mut().get_mut(&name.lexeme) {
+                local.defined = true;
+            }
+        }
+    }
+
+    // Binds a name the interpreter defines itself, like `this`, in the innermost scope.
+    fn declare_implicit(&self, name: &str) {
+        let scopes = self.scopes.borrow();
+        let mut scope = scopes.last().unwrap().borrow_mut();
+        let slot = scope.len();
+        scope.insert(
+            name.to_string(),
+            Local {
+                defined: true,
+                slot,
+            },
+        );
+    }
+
+    fn check_label(&self, label: &Option<Token>) -> Result<(), LoxResult> {
+        match label {
+            Some(label) if !self.labels.borrow().contains(&label.lexeme) => Err(
+                LoxResult::runtime_error(label, &format!("Undefined label '{}'.", label.lexeme)),
+            ),
+            _ => Ok(()),
         }
     }
 
     fn resolve_local(&self, expr: Rc<Expr>, name: &Token) {
         for (scope, map) in self.scopes.borrow().iter().rev().enumerate() {
-            if map.borrow().contains_key(&name.lexeme.clone()) {
-                self.interpreter.resolve(expr, scope);
+            if let Some(local) = map.borrow().get(&name.lexeme) {
+                self.interpreter.resolve(expr, scope, local.slot);
                 return;
             }
         }
     }
 
-    fn resolve_function(&self, function: &FunctionStmt) -> Result<(), LoxResult> {
+    fn resolve_function(
+        &self,
+        function: &FunctionStmt,
+        declaration: FunctionType,
+    ) -> Result<(), LoxResult> {
+        let enclosing_function = self.current_function.replace(declaration);
+        // a function body can't jump to a loop outside it
+        let enclosing_labels = self.labels.replace(Vec::new());
         self.begin_scope();
 
         for param in function.params.iter() {
-            self.declare(param);
+            self.declare(param)?;
             self.define(param);
         }
 
         self.resolve(&function.body)?;
 
         self.end_scope();
+        self.labels.replace(enclosing_labels);
+        self.current_function.replace(enclosing_function);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn resolve_source(source: &str) -> Result<(), LoxResult> {
+        let interpreter = Interpreter::new();
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens()?;
+        let mut parser = Parser::new(tokens);
+        let statements = Rc::new(parser.parse()?);
+        Resolver::new(&interpreter).resolve(&statements)
+    }
+
+    fn error_message(result: Result<(), LoxResult>) -> String {
+        match result {
+            Err(LoxResult::RuntimeError { message, .. }) => message,
+            _ => panic!("expected a resolution error"),
+        }
+    }
+
+    #[test]
+    fn rejects_reading_local_in_its_own_initializer() {
+        let result = resolve_source("{ var a = a; }");
+        assert_eq!(
+            error_message(result),
+            "Can't read local variable in its own initializer."
+        );
+    }
+
+    #[test]
+    fn rejects_redeclaring_local_in_same_scope() {
+        let result = resolve_source("{ var a = 1; var a = 2; }");
+        assert_eq!(
+            error_message(result),
+            "Already a variable with this name in this scope."
+        );
+    }
+
+    #[test]
+    fn rejects_redeclaring_local_in_function_body() {
+        let result = resolve_source("fun f() { var a = 1; var a = 2; }");
+        assert_eq!(
+            error_message(result),
+            "Already a variable with this name in this scope."
+        );
+    }
+
+    #[test]
+    fn allows_redeclaring_globals() {
+        assert!(resolve_source("var a = 1; var a = 2;").is_ok());
+        assert!(resolve_source("var a = 1; { var a = 2; }").is_ok());
+    }
+
+    #[test]
+    fn rejects_return_at_top_level() {
+        assert_eq!(
+            error_message(resolve_source("return;")),
+            "Can't return from top-level code."
+        );
+        assert_eq!(
+            error_message(resolve_source("{ if (true) return 1; }")),
+            "Can't return from top-level code."
+        );
+    }
+
+    #[test]
+    fn allows_return_inside_functions() {
+        assert!(resolve_source("fun f() { return 1; }").is_ok());
+        assert!(resolve_source("var g = fun () { return; };").is_ok());
+        assert!(resolve_source("class A { m() { return this; } }").is_ok());
+    }
+
+    #[test]
+    fn rejects_labels_that_do_not_enclose_the_jump() {
+        assert_eq!(
+            error_message(resolve_source("while (true) { break outer; }")),
+            "Undefined label 'outer'."
+        );
+        assert_eq!(
+            error_message(resolve_source(
+                "outer: while (true) { fun f() { while (true) continue outer; } }"
+            )),
+            "Undefined label 'outer'."
+        );
+        assert!(resolve_source("outer: while (true) { while (true) break outer; }").is_ok());
+    }
+
+    #[test]
+    fn rejects_duplicate_parameter_names() {
+        let result = resolve_source("fun f(a, a) {}");
+        assert_eq!(
+            error_message(result),
+            "Already a variable with this name in this scope."
+        );
+    }
+
+    #[test]
+    fn allows_shadowing_in_nested_scope_and_global_redeclaration() {
+        assert!(resolve_source("{ var a = 1; { var a = 2; } }").is_ok());
+        assert!(resolve_source("var a = 1; var a = 2;").is_ok());
+    }
+
+    #[test]
+    fn rejects_this_outside_of_class() {
+        let result = resolve_source("fun f() { print this; }");
+        assert_eq!(
+            error_message(result),
+            "Can't use 'this' outside of a class."
+        );
+    }
+
+    #[test]
+    fn rejects_returning_value_from_initializer() {
+        let result = resolve_source("class A { init() { return 1; } }");
+        assert_eq!(
+            error_message(result),
+            "Can't return a value from an initializer."
+        );
+        assert!(resolve_source("class A { init() { return; } }").is_ok());
+    }
+
+    #[test]
+    fn rejects_misplaced_super() {
+        let result = resolve_source("fun f() { super.method(); }");
+        assert_eq!(
+            error_message(result),
+            "Can't use 'super' outside of a class."
+        );
+        let result = resolve_source("class A { method() { super.method(); } }");
+        assert_eq!(
+            error_message(result),
+            "Can't use 'super' in a class with no superclass."
+        );
+    }
+
+    #[test]
+    fn rejects_class_inheriting_from_itself() {
+        let result = resolve_source("class A < A {}");
+        assert_eq!(error_message(result), "A class can't inherit from itself.");
+    }
+
+    // The warnings from resolving the source, which must otherwise resolve cleanly.
+    fn warnings(source: &str) -> Vec<String> {
+        let interpreter = Interpreter::new();
+        let mut scanner = Scanner::new(source.to_string());
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = Rc::new(parser.parse().unwrap());
+        let resolver = Resolver::new(&interpreter);
+        resolver.resolve(&statements).unwrap();
+        resolver
+            .take_warnings()
+            .into_iter()
+            .map(|warning| error_message(Err(warning)))
+            .collect()
+    }
+
+    #[test]
+    fn flags_statements_after_a_return() {
+        assert_eq!(
+            warnings("fun f() { return 1; print 2; print 3; }"),
+            vec!["Unreachable code after return."]
+        );
+        assert_eq!(
+            warnings("fun f() { { return; } print 1; while (true) { return; var x; } }"),
+            vec!["Unreachable code after return."]
+        );
+    }
+
+    #[test]
+    fn does_not_flag_code_after_a_return_in_a_branch() {
+        assert!(warnings("fun f(x) { if (x) return 1; else print 2; print 3; }").is_empty());
+        assert!(warnings("fun f(x) { if (x) { return 1; } return 2; }").is_empty());
+    }
+
+    #[test]
+    fn unreachable_code_is_an_error_in_strict_mode() {
+        let interpreter = Interpreter::new();
+        interpreter.set_strict(true);
+        let mut scanner = Scanner::new("fun f() { return; print 1; }".to_string());
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = Rc::new(parser.parse().unwrap());
+        let result = Resolver::new(&interpreter).resolve(&statements);
+        assert_eq!(error_message(result), "Unreachable code after return.");
+    }
+}
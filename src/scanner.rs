@@ -1,44 +1,56 @@
 use crate::error::LoxResult;
 use crate::object::Object;
+use crate::symbol::Symbol;
 use crate::token::Token;
 use crate::token_type::TokenType;
 pub struct Scanner {
-    source: String,
+    source: Vec<char>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
+    // Index of the first character on the current line, for computing columns.
+    line_start: usize,
+    start_column: usize,
+    errors: Vec<LoxResult>,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
         Scanner {
-            source,
+            source: source.chars().collect(),
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_column: 1,
+            errors: Vec::new(),
         }
     }
 
+    // Scanning carries on past a bad character so every error is found in one pass; the first
+    // is returned and errors() has them all.
     pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, LoxResult> {
-        let mut had_err: Option<LoxResult> = None;
         while !self.is_at_end() {
             self.start = self.current;
-            match self.scan_token() {
-                Ok(_) => (),
-                Err(err) => {
-                    had_err = Some(err);
-                }
+            self.start_column = self.start - self.line_start + 1;
+            if let Err(err) = self.scan_token() {
+                self.errors.push(err);
             }
         }
-        if let Some(err) = had_err {
-            return Err(err);
+        if let Some(err) = self.errors.first() {
+            return Err(err.clone());
         }
-        self.tokens.push(Token::eof(self.line));
+        self.tokens
+            .push(Token::eof(self.line, self.current - self.line_start + 1));
         Ok(&self.tokens)
     }
 
+    pub fn errors(&self) -> &[LoxResult] {
+        &self.errors
+    }
+
     pub fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -49,12 +61,40 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
+            ':' => self.add_token(TokenType::Colon),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    self.add_token(TokenType::DotDotDot);
+                } else {
+                    self.add_token(TokenType::Dot);
+                }
+            }
+            '-' => {
+                if self.is_match('-') {
+                    self.add_token(TokenType::MinusMinus);
+                } else {
+                    self.add_token(TokenType::Minus);
+                }
+            }
+            '+' => {
+                if self.is_match('+') {
+                    self.add_token(TokenType::PlusPlus);
+                } else {
+                    self.add_token(TokenType::Plus);
+                }
+            }
             ';' => self.add_token(TokenType::Semicolon),
             '*' => self.add_token(TokenType::Star),
+            '&' => self.add_token(TokenType::Ampersand),
+            '|' => self.add_token(TokenType::Pipe),
+            '^' => self.add_token(TokenType::Caret),
+            // only `??` uses a question mark
+            '?' if self.is_match('?') => self.add_token(TokenType::QuestionQuestion),
             '!' => {
                 if self.is_match('=') {
                     self.add_token(TokenType::BangEqual);
@@ -70,14 +110,18 @@ impl Scanner {
                 }
             }
             '>' => {
-                if self.is_match('=') {
+                if self.is_match('>') {
+                    self.add_token(TokenType::GreaterGreater);
+                } else if self.is_match('=') {
                     self.add_token(TokenType::GreaterEqual);
                 } else {
                     self.add_token(TokenType::Greater);
                 }
             }
             '<' => {
-                if self.is_match('=') {
+                if self.is_match('<') {
+                    self.add_token(TokenType::LessLess);
+                } else if self.is_match('=') {
                     self.add_token(TokenType::LessEqual);
                 } else {
                     self.add_token(TokenType::Less);
@@ -89,15 +133,13 @@ impl Scanner {
                         self.advance();
                     }
                 } else if self.is_match('*') {
-                    self.scan_comment()?;
+                    self.scan_comment(self.line)?;
                 } else {
                     self.add_token(TokenType::Slash);
                 }
             }
             ' ' | '\r' | '\t' => (),
-            '\n' => {
-                self.line += 1;
-            }
+            '\n' => self.new_line(),
             '"' => self.string()?,
             '0'..='9' => self.number()?,
             _ => {
@@ -112,23 +154,65 @@ impl Scanner {
     }
 
     fn string(&mut self) -> Result<(), LoxResult> {
+        let mut value = String::new();
+        let mut invalid_escape = None;
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            let c = self.advance();
+            match c {
+                '\n' => {
+                    self.new_line();
+                    value.push(c);
+                }
+                '\\' => {
+                    if self.is_at_end() {
+                        break;
+                    }
+                    let escaped = match self.advance() {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '\\' => '\\',
+                        '"' => '"',
+                        '0' => '\0',
+                        other => {
+                            if other == '\n' {
+                                self.new_line();
+                            }
+                            // Keep going to the closing quote so the rest of the string isn't
+                            // scanned as code.
+                            if invalid_escape.is_none() {
+                                invalid_escape = Some(LoxResult::new(
+                                    self.line,
+                                    &format!("Invalid escape sequence '\\{}' in string.", other),
+                                ));
+                            }
+                            other
+                        }
+                    };
+                    value.push(escaped);
+                }
+                _ => value.push(c),
             }
-            self.advance();
         }
         if self.is_at_end() {
             return Err(LoxResult::new(self.line, "Unterminated string."));
         }
         self.advance();
-        let value = &self.source[self.start + 1..self.current - 1]; // +1 and -1 to remove the quotes
-        self.add_token_with_literal(TokenType::String, Some(Object::String(value.to_string())));
+        if let Some(err) = invalid_escape {
+            return Err(err);
+        }
+        self.add_token_with_literal(TokenType::String, Some(Object::String(value)));
         Ok(())
     }
 
+    // Called after consuming a '\n'.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
     fn advance(&mut self) -> char {
-        let result = self.source.chars().nth(self.current).unwrap();
+        let result = self.source[self.current];
         self.current += 1;
         result
     }
@@ -141,12 +225,13 @@ impl Scanner {
     fn add_token_with_literal(&mut self, token_type: TokenType, literal: Option<Object>) {
         // This line is creating a substring (called a lexeme) from the source code.
         // It starts at the index self.start and ends at self.current.
-        let lexeme = &self.source[self.start..self.current].to_owned();
+        let lexeme: String = self.source[self.start..self.current].iter().collect();
         self.tokens.push(Token::new(
             token_type,
-            lexeme.to_string(),
+            lexeme,
             literal,
             self.line,
+            self.start_column,
         ));
     }
 
@@ -154,7 +239,7 @@ impl Scanner {
         if self.is_at_end() {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected {
+        if self.source[self.current] != expected {
             return false;
         }
         self.current += 1;
@@ -165,81 +250,121 @@ impl Scanner {
         if self.is_at_end() {
             return '\0';
         }
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current]
     }
 
+    // Takes in everything that could belong to the literal, so a malformed one like `1e` or
+    // `0xG` is reported as a whole rather than splitting into a number and an identifier.
     fn number(&mut self) -> Result<(), LoxResult> {
-        while self.is_digit(self.peek()) {
-            self.advance();
-        }
-        if self.peek() == '.' && self.is_digit(self.peek_next()) {
+        if self.source[self.start] == '0' && matches!(self.peek(), 'x' | 'X') {
             self.advance();
+            while self.is_alpha_numeric(self.peek()) {
+                self.advance();
+            }
+        } else {
             while self.is_digit(self.peek()) {
                 self.advance();
             }
+            if self.peek() == '.' && self.is_digit(self.peek_next()) {
+                self.advance();
+                while self.is_digit(self.peek()) {
+                    self.advance();
+                }
+            }
+            if matches!(self.peek(), 'e' | 'E') {
+                self.advance();
+                if matches!(self.peek(), '+' | '-') {
+                    self.advance();
+                }
+                while self.is_alpha_numeric(self.peek()) {
+                    self.advance();
+                }
+            }
         }
-        self.add_token_with_literal(
-            TokenType::Number,
-            Some(Object::Number(
-                self.source[self.start..self.current].parse().unwrap(),
+        let text: String = self.source[self.start..self.current].iter().collect();
+        match parse_number(&text) {
+            Some(value) => {
+                self.add_token_with_literal(TokenType::Number, Some(Object::Number(value)));
+                Ok(())
+            }
+            None => Err(LoxResult::new(
+                self.line,
+                &format!("Invalid number literal '{}'.", text),
             )),
-        );
-        Ok(())
+        }
     }
 
     fn identifier(&mut self) -> Result<(), LoxResult> {
         while self.is_alpha_numeric(self.peek()) {
             self.advance();
         }
-        let text = &self.source[self.start..self.current];
-        let token_type = Scanner::keywords(text);
-        if let Some(token_type) = token_type {
-            self.add_token(token_type);
-        } else {
-            self.add_token(TokenType::Identifier);
+        let text: String = self.source[self.start..self.current].iter().collect();
+        let token_type = Scanner::keywords(&text).unwrap_or(TokenType::Identifier);
+        self.add_token(token_type);
+        // `this` and `super` are looked up in environments just like identifiers
+        if matches!(
+            token_type,
+            TokenType::Identifier | TokenType::This | TokenType::Super
+        ) {
+            let token = self.tokens.last_mut().unwrap();
+            token.symbol = Some(Symbol::intern(&token.lexeme));
         }
         Ok(())
     }
 
     fn is_digit(&self, c: char) -> bool {
-        ('0'..='9').contains(&c)
+        c.is_ascii_digit()
     }
 
     fn peek_next(&self) -> char {
         if self.current + 1 >= self.source.len() {
             return '\0';
         }
-        self.source.chars().nth(self.current + 1).unwrap()
+        self.source[self.current + 1]
     }
 
     fn is_alpha_numeric(&self, c: char) -> bool {
-        ('a'..='z').contains(&c) || ('A'..='Z').contains(&c) || self.is_digit(c)
+        c.is_alphabetic() || c == '_' || self.is_digit(c)
     }
 
     fn keywords(identifier: &str) -> Option<TokenType> {
         match identifier {
             "and" => Some(TokenType::And),
+            "catch" => Some(TokenType::Catch),
             "class" => Some(TokenType::Class),
+            "const" => Some(TokenType::Const),
+            // Floor division is a keyword rather than Python's `//`, which already starts a
+            // comment here.
+            "div" => Some(TokenType::Div),
+            "do" => Some(TokenType::Do),
             "else" => Some(TokenType::Else),
             "false" => Some(TokenType::False),
+            "finally" => Some(TokenType::Finally),
             "for" => Some(TokenType::For),
             "fun" => Some(TokenType::Fun),
             "if" => Some(TokenType::If),
             "nil" => Some(TokenType::Nil),
+            // a readable spelling of `!`
+            "not" => Some(TokenType::Bang),
             "or" => Some(TokenType::Or),
             "print" => Some(TokenType::Print),
             "return" => Some(TokenType::Return),
             "super" => Some(TokenType::Super),
             "this" => Some(TokenType::This),
             "true" => Some(TokenType::True),
+            "try" => Some(TokenType::Try),
             "var" => Some(TokenType::Var),
             "while" => Some(TokenType::While),
             "break" => Some(TokenType::Break),
+            "continue" => Some(TokenType::Continue),
+            "import" => Some(TokenType::Import),
             _ => None,
         }
     }
 
-    fn scan_comment(&mut self) -> Result<(), LoxResult> {
+    // `opened_on` is the line of the outermost `/*`, which is what an unterminated comment
+    // is reported against however deeply it nests.
+    fn scan_comment(&mut self, opened_on: usize) -> Result<(), LoxResult> {
         loop {
             match self.peek() {
                 '*' => {
@@ -249,17 +374,20 @@ impl Scanner {
                     }
                 }
                 '\n' => {
-                    self.line += 1;
                     self.advance();
+                    self.new_line();
                 }
                 '/' => {
                     self.advance();
                     if self.is_match('*') {
-                        self.scan_comment()?; // we are calling the function recursively for the nested comment /* /*   */  */
+                        self.scan_comment(opened_on)?; // we are calling the function recursively for the nested comment /* /*   */  */
                     }
                 }
                 '\0' => {
-                    return Err(LoxResult::new(self.line, "Unterminated comment."));
+                    return Err(LoxResult::new(
+                        self.line,
+                        &format!("Unterminated block comment started on line {}.", opened_on),
+                    ));
                 }
                 _ => {
                     self.advance();
@@ -268,3 +396,241 @@ impl Scanner {
         }
     }
 }
+
+/// Parses a numeric literal: an optional leading '-', integer digits, an optional fraction
+/// (`.` followed by digits) and an optional exponent (`e`/`E`, optional sign, digits), or
+/// `0x` followed by hex digits.
+/// This is the single definition of number syntax; anything else (`1.`, `.5`, `1e`, `+1`,
+/// `0x`) is rejected with `None`.
+pub fn parse_number(text: &str) -> Option<f64> {
+    let unsigned = text.strip_prefix('-').unwrap_or(text);
+    if let Some(hex) = unsigned
+        .strip_prefix("0x")
+        .or_else(|| unsigned.strip_prefix("0X"))
+    {
+        // from_str_radix would accept a sign of its own
+        if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let value = i64::from_str_radix(hex, 16).ok()? as f64;
+        return Some(if unsigned.len() < text.len() {
+            -value
+        } else {
+            value
+        });
+    }
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while *i < bytes.len() && bytes[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        *i > start
+    };
+
+    if bytes.first() == Some(&b'-') {
+        i += 1;
+    }
+    if !digits(&mut i) {
+        return None;
+    }
+    if i < bytes.len() && bytes[i] == b'.' {
+        i += 1;
+        if !digits(&mut i) {
+            return None;
+        }
+    }
+    if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+        i += 1;
+        if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+            i += 1;
+        }
+        if !digits(&mut i) {
+            return None;
+        }
+    }
+    if i != bytes.len() {
+        return None;
+    }
+    text.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_number_accepts_integers_and_fractions() {
+        assert_eq!(parse_number("0"), Some(0.0));
+        assert_eq!(parse_number("42"), Some(42.0));
+        assert_eq!(parse_number("-7"), Some(-7.0));
+        assert_eq!(parse_number("3.25"), Some(3.25));
+    }
+
+    #[test]
+    fn parse_number_accepts_exponents() {
+        assert_eq!(parse_number("1e3"), Some(1000.0));
+        assert_eq!(parse_number("2.5E-2"), Some(0.025));
+        assert_eq!(parse_number("-1e+2"), Some(-100.0));
+    }
+
+    #[test]
+    fn parse_number_accepts_hex() {
+        assert_eq!(parse_number("0x1F"), Some(31.0));
+        assert_eq!(parse_number("0XfF"), Some(255.0));
+        assert_eq!(parse_number("-0x10"), Some(-16.0));
+    }
+
+    #[test]
+    fn parse_number_rejects_malformed_input() {
+        for text in [
+            "", "-", "1.", ".5", "1e", "1e+", "+1", "1x", "abc", "1.2.3", "0x", "0xG", "0x+1",
+        ] {
+            assert_eq!(parse_number(text), None, "{:?} should be rejected", text);
+        }
+    }
+
+    #[test]
+    fn scanner_uses_shared_number_syntax() {
+        let mut scanner = Scanner::new("12.5".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].ttype, TokenType::Number);
+        assert_eq!(tokens[0].literal, Some(Object::Number(12.5)));
+    }
+
+    #[test]
+    fn scans_exponent_and_hex_literals() {
+        let mut scanner = Scanner::new("1e3 2.5e-2 0x1F".to_string());
+        let values: Vec<Option<Object>> = scanner
+            .scan_tokens()
+            .unwrap()
+            .iter()
+            .map(|token| token.literal.clone())
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                Some(Object::Number(1000.0)),
+                Some(Object::Number(0.025)),
+                Some(Object::Number(31.0)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_numbers_are_errors_with_their_line() {
+        for source in ["\n1e", "\n0xG", "\n2e+"] {
+            let mut scanner = Scanner::new(source.to_string());
+            match scanner.scan_tokens() {
+                Err(LoxResult::GenericError { line, message }) => {
+                    assert_eq!(line, 2);
+                    assert!(message.starts_with("Invalid number literal"), "{}", message);
+                }
+                _ => panic!("{:?} should not scan", source),
+            }
+        }
+    }
+
+    #[test]
+    fn unterminated_comments_name_the_outermost_opener() {
+        let source = "print 1;\n/* outer\n  /* inner */\n  /* unclosed\n*/\nprint 2;";
+        let mut scanner = Scanner::new(source.to_string());
+        match scanner.scan_tokens() {
+            Err(LoxResult::GenericError { message, .. }) => {
+                assert_eq!(message, "Unterminated block comment started on line 2.")
+            }
+            _ => panic!("expected an unterminated comment"),
+        }
+    }
+
+    #[test]
+    fn scanner_handles_multibyte_characters() {
+        let mut scanner = Scanner::new("var café = \"🦀 naïve\";\nprint café;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[1].ttype, TokenType::Identifier);
+        assert_eq!(tokens[1].lexeme, "café");
+        assert_eq!(tokens[3].ttype, TokenType::String);
+        assert_eq!(
+            tokens[3].literal,
+            Some(Object::String("🦀 naïve".to_string()))
+        );
+        assert_eq!(tokens[6].lexeme, "café");
+        assert_eq!(tokens[6].line, 2);
+    }
+
+    #[test]
+    fn scans_bitwise_operators() {
+        let mut scanner = Scanner::new("& | ^ << >> <= >".to_string());
+        let types: Vec<TokenType> = scanner
+            .scan_tokens()
+            .unwrap()
+            .iter()
+            .map(|t| t.ttype)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Ampersand,
+                TokenType::Pipe,
+                TokenType::Caret,
+                TokenType::LessLess,
+                TokenType::GreaterGreater,
+                TokenType::LessEqual,
+                TokenType::Greater,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    fn string_literal(source: &str) -> Result<Option<Object>, LoxResult> {
+        let mut scanner = Scanner::new(source.to_string());
+        Ok(scanner.scan_tokens()?[0].literal.clone())
+    }
+
+    #[test]
+    fn strings_process_escape_sequences() {
+        assert_eq!(
+            string_literal(r#""a\nb""#).unwrap(),
+            Some(Object::String("a\nb".to_string()))
+        );
+        assert_eq!(
+            string_literal(r#""quote: \"""#).unwrap(),
+            Some(Object::String("quote: \"".to_string()))
+        );
+        assert_eq!(
+            string_literal(r#""\t\r\\\0""#).unwrap(),
+            Some(Object::String("\t\r\\\0".to_string()))
+        );
+    }
+
+    #[test]
+    fn strings_reject_unknown_escapes() {
+        match string_literal("\"line one\nbad \\q\"") {
+            Err(LoxResult::GenericError { line, message }) => {
+                assert_eq!(line, 2);
+                assert_eq!(message, "Invalid escape sequence '\\q' in string.");
+            }
+            _ => panic!("expected a scan error"),
+        }
+    }
+
+    #[test]
+    fn multiline_strings_keep_line_count() {
+        let mut scanner = Scanner::new("\"a\nb\" x".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].literal, Some(Object::String("a\nb".to_string())));
+        assert_eq!(tokens[1].line, 2);
+    }
+
+    #[test]
+    fn tokens_record_their_column() {
+        let mut scanner = Scanner::new("var x = 1;\n  print \"é\" + x;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!((tokens[1].lexeme.as_str(), tokens[1].column), ("x", 5));
+        assert_eq!((tokens[5].lexeme.as_str(), tokens[5].column), ("print", 3));
+        assert_eq!((tokens[7].lexeme.as_str(), tokens[7].column), ("+", 13));
+        assert_eq!((tokens[10].line, tokens[10].column), (2, 17));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::collections::HashMap;
+
+// The stretch of source a node was parsed from, from the first character of its first token
+// to the last character of its last token. Lines and columns are 1-based, like Token's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl Span {
+    pub fn between(first: &Token, last: &Token) -> Self {
+        Span {
+            start_line: first.line,
+            start_col: first.column,
+            end_line: last.line,
+            end_col: last.column + last.lexeme.chars().count().max(1) - 1,
+        }
+    }
+}
+
+// The span of every node the parser built, keyed by node identity: Expr and Stmt hash by the
+// address of the node they wrap, so a clone of the Rc finds the same entry.
+#[derive(Default)]
+pub struct Spans {
+    exprs: HashMap<Expr, Span>,
+    stmts: HashMap<Stmt, Span>,
+}
+
+impl Spans {
+    pub fn expr(&self, expr: &Expr) -> Option<Span> {
+        self.exprs.get(expr).copied()
+    }
+
+    pub fn stmt(&self, stmt: &Stmt) -> Option<Span> {
+        self.stmts.get(stmt).copied()
+    }
+
+    pub(crate) fn insert_expr(&mut self, expr: Expr, span: Span) {
+        self.exprs.insert(expr, span);
+    }
+
+    pub(crate) fn insert_stmt(&mut self, stmt: Stmt, span: Span) {
+        self.stmts.insert(stmt, span);
+    }
+
+    pub fn extend(&mut self, other: Spans) {
+        self.exprs.extend(other.exprs);
+        self.stmts.extend(other.stmts);
+    }
+
+    pub fn clear(&mut self) {
+        self.exprs.clear();
+        self.stmts.clear();
+    }
+}
@@ -2,14 +2,20 @@ use crate::error::*;
 use crate::expr::*;
 use crate::token::*;
 use std::rc::Rc;
+#[derive(Clone)]
 pub enum Stmt {
     Block(Rc<BlockStmt>),
+    Class(Rc<ClassStmt>),
     If(Rc<IfStmt>),
     Expression(Rc<ExpressionStmt>),
     Function(Rc<FunctionStmt>),
     Break(Rc<BreakStmt>),
+    Continue(Rc<ContinueStmt>),
+    Import(Rc<ImportStmt>),
+    MultiVar(Rc<MultiVarStmt>),
     Print(Rc<PrintStmt>),
     Return(Rc<ReturnStmt>),
+    Try(Rc<TryStmt>),
     Var(Rc<VarStmt>),
     While(Rc<WhileStmt>),
 }
@@ -17,12 +23,17 @@ impl PartialEq for Stmt {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Stmt::Block(expr1), Stmt::Block(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Stmt::Class(expr1), Stmt::Class(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Stmt::If(expr1), Stmt::If(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Stmt::Expression(expr1), Stmt::Expression(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Stmt::Function(expr1), Stmt::Function(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Stmt::Break(expr1), Stmt::Break(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Stmt::Continue(expr1), Stmt::Continue(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Stmt::Import(expr1), Stmt::Import(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Stmt::MultiVar(expr1), Stmt::MultiVar(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Stmt::Print(expr1), Stmt::Print(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Stmt::Return(expr1), Stmt::Return(expr2)) => Rc::ptr_eq(expr1, expr2),
+            (Stmt::Try(expr1), Stmt::Try(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Stmt::Var(expr1), Stmt::Var(expr2)) => Rc::ptr_eq(expr1, expr2),
             (Stmt::While(expr1), Stmt::While(expr2)) => Rc::ptr_eq(expr1, expr2),
           _=> false,
@@ -34,16 +45,20 @@ impl Eq for Stmt{}
 
 use std::hash::*;
 impl Hash for Stmt {
-    fn hash<H: Hasher>(&self, hasher: &mut H)
-    where H: Hasher, 
-       { match self {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+       match self {
             Stmt::Block(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Stmt::Class(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Stmt::If(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Stmt::Expression(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Stmt::Function(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Stmt::Break(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Stmt::Continue(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Stmt::Import(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Stmt::MultiVar(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Stmt::Print(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Stmt::Return(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
+            Stmt::Try(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Stmt::Var(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
             Stmt::While(expr) => {hasher.write_usize(Rc::as_ptr(expr) as usize);}
         }
@@ -52,21 +67,31 @@ impl Hash for Stmt {
 impl Stmt {
     pub fn accept<T>(&self, wrapper: Rc<Stmt>, stmt_visitor: &dyn StmtVisitor<T>) -> Result<T, LoxResult> {
         match self {
-            Stmt::Block(expr) => stmt_visitor.visit_block_stmt(wrapper,&expr),
-            Stmt::If(expr) => stmt_visitor.visit_if_stmt(wrapper,&expr),
-            Stmt::Expression(expr) => stmt_visitor.visit_expression_stmt(wrapper,&expr),
-            Stmt::Function(expr) => stmt_visitor.visit_function_stmt(wrapper,&expr),
-            Stmt::Break(expr) => stmt_visitor.visit_break_stmt(wrapper,&expr),
-            Stmt::Print(expr) => stmt_visitor.visit_print_stmt(wrapper,&expr),
-            Stmt::Return(expr) => stmt_visitor.visit_return_stmt(wrapper,&expr),
-            Stmt::Var(expr) => stmt_visitor.visit_var_stmt(wrapper,&expr),
-            Stmt::While(expr) => stmt_visitor.visit_while_stmt(wrapper,&expr),
+            Stmt::Block(expr) => stmt_visitor.visit_block_stmt(wrapper, expr),
+            Stmt::Class(expr) => stmt_visitor.visit_class_stmt(wrapper, expr),
+            Stmt::If(expr) => stmt_visitor.visit_if_stmt(wrapper, expr),
+            Stmt::Expression(expr) => stmt_visitor.visit_expression_stmt(wrapper, expr),
+            Stmt::Function(expr) => stmt_visitor.visit_function_stmt(wrapper, expr),
+            Stmt::Break(expr) => stmt_visitor.visit_break_stmt(wrapper, expr),
+            Stmt::Continue(expr) => stmt_visitor.visit_continue_stmt(wrapper, expr),
+            Stmt::Import(expr) => stmt_visitor.visit_import_stmt(wrapper, expr),
+            Stmt::MultiVar(expr) => stmt_visitor.visit_multivar_stmt(wrapper, expr),
+            Stmt::Print(expr) => stmt_visitor.visit_print_stmt(wrapper, expr),
+            Stmt::Return(expr) => stmt_visitor.visit_return_stmt(wrapper, expr),
+            Stmt::Try(expr) => stmt_visitor.visit_try_stmt(wrapper, expr),
+            Stmt::Var(expr) => stmt_visitor.visit_var_stmt(wrapper, expr),
+            Stmt::While(expr) => stmt_visitor.visit_while_stmt(wrapper, expr),
         }
     }
 }
 pub struct BlockStmt {
     pub statements: Rc<Vec<Rc<Stmt>>>,
 }
+pub struct ClassStmt {
+    pub name: Token,
+    pub superclass: Option<Rc<Expr>>,
+    pub methods: Rc<Vec<Rc<Stmt>>>,
+}
 pub struct IfStmt {
     pub condition: Rc<Expr>,
     pub then_branch: Rc<Stmt>,
@@ -78,10 +103,23 @@ pub struct ExpressionStmt {
 pub struct FunctionStmt {
     pub name: Token,
     pub params: Rc<Vec<Token>>,
+    pub variadic: bool,
     pub body: Rc<Vec<Rc<Stmt>>>,
+    pub is_lambda: bool,
 }
 pub struct BreakStmt {
     pub token: Token,
+    pub label: Option<Token>,
+}
+pub struct ContinueStmt {
+    pub token: Token,
+    pub label: Option<Token>,
+}
+pub struct ImportStmt {
+    pub path: Token,
+}
+pub struct MultiVarStmt {
+    pub declarations: Rc<Vec<Rc<Stmt>>>,
 }
 pub struct PrintStmt {
     pub expression: Rc<Expr>,
@@ -90,22 +128,37 @@ pub struct ReturnStmt {
     pub token: Token,
     pub value: Option<Rc<Expr>>,
 }
+pub struct TryStmt {
+    pub try_body: Rc<Vec<Rc<Stmt>>>,
+    pub error: Token,
+    pub catch_body: Rc<Vec<Rc<Stmt>>>,
+    pub finally_body: Option<Rc<Vec<Rc<Stmt>>>>,
+}
 pub struct VarStmt {
     pub name: Token,
     pub initializer: Option<Rc<Expr>>,
+    pub is_const: bool,
 }
 pub struct WhileStmt {
     pub condition: Rc<Expr>,
     pub body: Rc<Stmt>,
+    pub increment: Option<Rc<Expr>>,
+    pub label: Option<Token>,
+    pub do_while: bool,
 }
 pub trait StmtVisitor<T> {
     fn visit_block_stmt(&self, wrapper: Rc<Stmt>, stmt: &BlockStmt) -> Result<T,LoxResult>;
+    fn visit_class_stmt(&self, wrapper: Rc<Stmt>, stmt: &ClassStmt) -> Result<T,LoxResult>;
     fn visit_if_stmt(&self, wrapper: Rc<Stmt>, stmt: &IfStmt) -> Result<T,LoxResult>;
     fn visit_expression_stmt(&self, wrapper: Rc<Stmt>, stmt: &ExpressionStmt) -> Result<T,LoxResult>;
     fn visit_function_stmt(&self, wrapper: Rc<Stmt>, stmt: &FunctionStmt) -> Result<T,LoxResult>;
     fn visit_break_stmt(&self, wrapper: Rc<Stmt>, stmt: &BreakStmt) -> Result<T,LoxResult>;
+    fn visit_continue_stmt(&self, wrapper: Rc<Stmt>, stmt: &ContinueStmt) -> Result<T,LoxResult>;
+    fn visit_import_stmt(&self, wrapper: Rc<Stmt>, stmt: &ImportStmt) -> Result<T,LoxResult>;
+    fn visit_multivar_stmt(&self, wrapper: Rc<Stmt>, stmt: &MultiVarStmt) -> Result<T,LoxResult>;
     fn visit_print_stmt(&self, wrapper: Rc<Stmt>, stmt: &PrintStmt) -> Result<T,LoxResult>;
     fn visit_return_stmt(&self, wrapper: Rc<Stmt>, stmt: &ReturnStmt) -> Result<T,LoxResult>;
+    fn visit_try_stmt(&self, wrapper: Rc<Stmt>, stmt: &TryStmt) -> Result<T,LoxResult>;
     fn visit_var_stmt(&self, wrapper: Rc<Stmt>, stmt: &VarStmt) -> Result<T,LoxResult>;
     fn visit_while_stmt(&self, wrapper: Rc<Stmt>, stmt: &WhileStmt) -> Result<T,LoxResult>;
 }
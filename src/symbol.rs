@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// An interned identifier name. Environments key on symbols, so a variable lookup hashes a u32
+// rather than the whole name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+// Hands out one symbol per distinct name, in the order names are first seen.
+#[derive(Default)]
+pub struct Interner {
+    symbols: HashMap<String, Symbol>,
+    names: Vec<String>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.symbols.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
+
+// One interner per thread: the globals outlive any single scan, so a name must map to the same
+// symbol in every script and REPL line the thread runs.
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+
+    pub fn name(self) -> String {
+        INTERNER.with(|interner| interner.borrow().name(self).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_name_gives_same_symbol() {
+        let mut interner = Interner::default();
+        let a = interner.intern("a");
+        let b = interner.intern("b");
+        assert_eq!(interner.intern("a"), a);
+        assert_ne!(a, b);
+        assert_eq!(interner.name(b), "b");
+    }
+
+    #[test]
+    fn thread_interner_round_trips_names() {
+        let symbol = Symbol::intern("counter");
+        assert_eq!(Symbol::intern("counter"), symbol);
+        assert_eq!(symbol.name(), "counter");
+    }
+}
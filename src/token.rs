@@ -1,4 +1,5 @@
 use crate::object::Object;
+use crate::symbol::Symbol;
 use crate::token_type::TokenType;
 use std::fmt::Display;
 
@@ -8,20 +9,37 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Option<Object>,
     pub line: usize,
+    // 1-based, counted in characters from the start of the line.
+    pub column: usize,
+    // Set by the scanner for names, which environments look up by symbol.
+    pub symbol: Option<Symbol>,
 }
 
 impl Token {
-    pub fn new(ttype: TokenType, lexeme: String, literal: Option<Object>, line: usize) -> Self {
+    pub fn new(
+        ttype: TokenType,
+        lexeme: String,
+        literal: Option<Object>,
+        line: usize,
+        column: usize,
+    ) -> Self {
         Token {
             ttype,
             lexeme,
             literal,
             line,
+            column,
+            symbol: None,
         }
     }
 
-    pub fn eof(line: usize) -> Self {
-        Token::new(TokenType::Eof, "".to_string(), None, line)
+    // Tokens built outside the scanner have no symbol yet, so fall back to interning the lexeme.
+    pub fn symbol(&self) -> Symbol {
+        self.symbol.unwrap_or_else(|| Symbol::intern(&self.lexeme))
+    }
+
+    pub fn eof(line: usize, column: usize) -> Self {
+        Token::new(TokenType::Eof, "".to_string(), None, line, column)
     }
 }
 
@@ -1,14 +1,22 @@
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TokenType {
     Break,
+    Continue,
+    Import,
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
+    DotDotDot,
     Minus,
+    MinusMinus,
     Plus,
+    PlusPlus,
     Semicolon,
     Slash,
     Star,
@@ -20,13 +28,24 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    Ampersand,
+    Pipe,
+    Caret,
+    QuestionQuestion,
+    LessLess,
+    GreaterGreater,
     Identifier,
     String,
     Number,
     And,
+    Catch,
     Class,
+    Const,
+    Div,
+    Do,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
@@ -37,6 +56,7 @@ pub enum TokenType {
     Super,
     This,
     True,
+    Try,
     Var,
     While,
     Eof,
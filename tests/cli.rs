@@ -0,0 +1,117 @@
+use std::process::Command;
+
+// Runs the interpreter binary, with any flags, on a script written to a temporary file.
+fn run_script(flags: &[&str], name: &str, source: &str) -> std::process::Output {
+    let path = std::env::temp_dir().join(format!("lox-cli-{}-{}.lox", std::process::id(), name));
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_ast-interpreter"))
+        .args(flags)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn tokens_flag_prints_the_token_stream() {
+    let output = run_script(&["--tokens"], "tokens", "var x = \"hi\";\nprint 1.5;");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1:1 Var var nil\n\
+         1:5 Identifier x nil\n\
+         1:7 Equal = nil\n\
+         1:9 String \"hi\" hi\n\
+         1:13 Semicolon ; nil\n\
+         2:1 Print print nil\n\
+         2:7 Number 1.5 1.5\n\
+         2:10 Semicolon ; nil\n\
+         2:11 Eof  nil\n"
+    );
+}
+
+#[test]
+fn tokens_flag_reports_scanner_errors() {
+    let output = run_script(&["--tokens"], "bad-tokens", "var x = @;");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn json_flag_prints_the_syntax_tree() {
+    let output = run_script(&["--json"], "json", "print nil;");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "[{\"type\":\"PrintStmt\",\"expression\":{\"type\":\"LiteralExpr\",\"value\":null}}]\n"
+    );
+}
+
+#[test]
+fn runtime_errors_quote_the_source_line() {
+    let source = "var a = 1;\nprint a +\n    missing;\n";
+    let output = run_script(&[], "snippet", source);
+    assert_eq!(output.status.code(), Some(70));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("[line 3, col 5] Error at 'missing'"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.ends_with("\n        missing;\n        ^\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn error_format_json_writes_one_object_per_error() {
+    let output = run_script(
+        &["--error-format", "json"],
+        "error-json",
+        "print 1;\n\"x\"();\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "{\"line\":2,\"col\":5,\"kind\":\"runtime\",\"message\":\"can only call functions and classes\"}\n"
+    );
+}
+
+#[test]
+fn error_format_color_is_plain_when_stderr_is_not_a_terminal() {
+    let source = "print missing;\n";
+    let plain = run_script(&[], "error-plain", source);
+    let color = run_script(&["--error-format", "color"], "error-color", source);
+    assert_eq!(color.stderr, plain.stderr);
+    assert!(!plain.stderr.contains(&0x1b));
+}
+
+#[test]
+fn exit_sets_the_process_exit_code() {
+    let output = run_script(&[], "exit", "print 1;\nexit(3);\nprint 2;\n");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn a_missing_script_is_reported_not_a_panic() {
+    for flags in [&[][..], &["--ast"], &["--json"], &["--tokens"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_ast-interpreter"))
+            .args(flags)
+            .arg("/no/such/script.lox")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(70), "{:?}", flags);
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "Error: Could not read file '/no/such/script.lox'.\n",
+            "{:?}",
+            flags
+        );
+    }
+}
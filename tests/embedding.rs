@@ -0,0 +1,203 @@
+use ast_interpreter::interpreter::Capabilities;
+use ast_interpreter::{Lox, LoxResult};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// A writer whose contents the test can still read after handing it to Lox.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn print_goes_to_the_supplied_writer() {
+    let output = SharedBuffer::default();
+    let lox = Lox::with_output(output.clone());
+    assert!(lox.run_source("print 1; print \"hi\";").is_ok());
+    assert_eq!(output.contents(), "1\nhi\n");
+}
+
+#[test]
+fn globals_persist_across_runs() {
+    let lox = Lox::new();
+    assert!(lox
+        .run_source("var count = 1; fun bump() { count = count + 1; }")
+        .is_ok());
+    assert!(lox
+        .run_source("bump(); bump(); assert_eq(count, 3);")
+        .is_ok());
+}
+
+#[test]
+fn errors_are_returned_instead_of_exiting() {
+    let lox = Lox::new();
+    match lox.run_source("var = 1; print;").unwrap_err().as_slice() {
+        [LoxResult::ParseError { .. }, LoxResult::ParseError { .. }] => (),
+        errors => panic!("expected two parse errors, got {:?}", errors),
+    }
+    match lox
+        .run_source("print undefined_name;")
+        .unwrap_err()
+        .as_slice()
+    {
+        [LoxResult::RuntimeError { message, .. }] => {
+            assert!(message.starts_with("Undefined variable"))
+        }
+        errors => panic!("expected a runtime error, got {:?}", errors),
+    }
+    // the interpreter is still usable after a failed run
+    assert!(lox.run_source("var ok = true;").is_ok());
+}
+
+#[test]
+fn diagnostics_go_to_the_supplied_writer_once() {
+    let diagnostics = SharedBuffer::default();
+    let lox = Lox::with_diagnostics(diagnostics.clone());
+    assert!(lox
+        .run_source("fun fail() { return 1 + nil; }\nfail();")
+        .is_err());
+    let contents = diagnostics.contents();
+    assert_eq!(contents.matches("Error").count(), 1, "got {:?}", contents);
+    assert!(contents.contains("operands must be two numbers or two strings"));
+}
+
+#[test]
+fn runtime_errors_in_calls_print_a_traceback() {
+    let diagnostics = SharedBuffer::default();
+    let lox = Lox::with_diagnostics(diagnostics.clone());
+    let source = "fun b() {\n  return missing;\n}\nfun a() {\n  b();\n}\na();\n";
+    assert!(lox.run_source(source).is_err());
+    let contents = diagnostics.contents();
+    assert!(
+        contents.ends_with(
+            "Traceback (outermost call first):\n  [line 7] in <script>\n  [line 5] in a\n  [line 2] in b\n"
+        ),
+        "got {:?}",
+        contents
+    );
+}
+
+#[test]
+fn unreachable_code_is_a_warning_and_still_runs() {
+    let diagnostics = SharedBuffer::default();
+    let lox = Lox::with_diagnostics(diagnostics.clone());
+    let source = "fun f() {\n  return 1;\n  print 2;\n}\nvar result = f();\n";
+    assert!(lox.run_source(source).is_ok());
+    assert!(lox.run_source("assert_eq(result, 1);").is_ok());
+    assert!(
+        diagnostics
+            .contents()
+            .starts_with("[line 2, col 3] Warning at 'return': Unreachable code after return.\n"),
+        "got {:?}",
+        diagnostics.contents()
+    );
+}
+
+#[test]
+fn exit_hands_its_code_back_to_the_host() {
+    let lox = Lox::new();
+    match lox
+        .run_source("var before = 1; exit(3); var after = 2;")
+        .unwrap_err()
+        .as_slice()
+    {
+        [LoxResult::Exit { code }] => assert_eq!(*code, 3),
+        errors => panic!("expected an exit, got {:?}", errors),
+    }
+    // nothing after exit() ran
+    assert!(lox.run_source("print before;").is_ok());
+    assert!(lox.run_source("print after;").is_err());
+}
+
+#[test]
+fn imports_run_other_files_once() {
+    let dir = std::env::temp_dir().join(format!("lox-import-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    // the two files import each other; each still runs only once
+    std::fs::write(
+        dir.join("main.lox"),
+        "import \"lib/math.lox\";\nvar answer = double(21);\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib/math.lox"),
+        "import \"../main.lox\";\nvar loads = 0;\nfun double(n) { return n * 2; }\n",
+    )
+    .unwrap();
+    let lox = Lox::new();
+    let result = lox.run_file(dir.join("main.lox").to_str().unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(result.is_ok(), "{:?}", result);
+    assert!(lox.run_source("assert_eq(answer, 42);").is_ok());
+
+    match lox
+        .run_source("import \"no/such/file.lox\";")
+        .unwrap_err()
+        .as_slice()
+    {
+        // relative to main.lox, the last file run
+        [LoxResult::RuntimeError { message, .. }] => {
+            assert!(message.starts_with("Could not read imported file '"));
+            assert!(message.ends_with("/no/such/file.lox'."), "{}", message);
+        }
+        errors => panic!("expected a runtime error, got {:?}", errors),
+    }
+}
+
+#[test]
+fn scripts_read_and_write_files() {
+    let dir = std::env::temp_dir().join(format!("lox-files-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("notes.txt");
+    let lox = Lox::new();
+    let source = format!(
+        "var path = \"{}\"; assert_eq(writeFile(path, \"line one\\nline two\"), nil); \
+         assert_eq(readFile(path), \"line one\\nline two\");",
+        path.display()
+    );
+    let result = lox.run_source(&source);
+    let written = std::fs::read_to_string(&path);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(written.unwrap(), "line one\nline two");
+
+    match lox.run_source("readFile(path);").unwrap_err().as_slice() {
+        [LoxResult::SystemError { message }] => {
+            assert!(message.starts_with("Could not read file '"), "{}", message);
+        }
+        errors => panic!("expected a system error, got {:?}", errors),
+    }
+}
+
+#[test]
+fn file_natives_fail_when_io_is_disabled() {
+    let path = std::env::temp_dir().join(format!("lox-sandbox-{}.txt", std::process::id()));
+    let lox = Lox::new();
+    lox.interpreter().set_capabilities(Capabilities {
+        allow_io: false,
+        ..Capabilities::default()
+    });
+    for call in ["readFile(path);", "writeFile(path, \"x\");"] {
+        let source = format!("var path = \"{}\"; {}", path.display(), call);
+        match lox.run_source(&source).unwrap_err().as_slice() {
+            [LoxResult::SystemError { message }] => assert_eq!(message, "file I/O is disabled."),
+            errors => panic!("expected a system error, got {:?}", errors),
+        }
+    }
+    assert!(!path.exists());
+}